@@ -6,6 +6,9 @@ candid-extractor target/wasm32-unknown-unknown/release/icrc7.wasm > src/icrc7/ic
 mv target/wasm32-unknown-unknown/release/icrc7.wasm wasm_files
 gzip wasm_files/icrc7.wasm
 
+cargo build --target wasm32-unknown-unknown --release --package icrc7_archive
+candid-extractor target/wasm32-unknown-unknown/release/icrc7_archive.wasm > src/icrc7_archive/icrc7_archive.did || true
+
 cargo build --target wasm32-unknown-unknown --release --package icrc7_backend
 candid-extractor target/wasm32-unknown-unknown/release/icrc7_backend.wasm > src/icrc7_backend/icrc7_backend.did || true
 
@@ -1,10 +1,34 @@
-use candid::CandidType;
+use candid::{CandidType, Principal};
 use icrc_ledger_types::icrc1::account::Account;
 use serde::Deserialize;
 
+// roles and operator scopes of the collections, named as in the icrc7 canister
+#[derive(CandidType, Debug)]
+pub enum Role {
+    Admin,
+    Minter,
+}
+
+#[derive(CandidType, Debug)]
+pub enum OperatorScope {
+    Transfer,
+}
+
+#[derive(CandidType, Debug)]
+pub struct RoleAssignment {
+    pub principal: Principal,
+    pub roles: Vec<Role>,
+}
+
+#[derive(CandidType, Debug)]
+pub struct OperatorAssignment {
+    pub operator: Principal,
+    pub scopes: Vec<OperatorScope>,
+}
+
 #[derive(CandidType, Debug)]
 pub struct InitArg {
-    pub minting_authority: Option<Account>,
+    pub minting_account: Option<Account>,
     pub icrc7_symbol: String,
     pub icrc7_name: String,
     pub icrc7_description: Option<String>,
@@ -18,6 +42,16 @@ pub struct InitArg {
     pub icrc7_atomic_batch_transfers: Option<bool>,
     pub tx_window: Option<u64>,
     pub permitted_drift: Option<u64>,
+    pub trusted_callers: Option<Vec<Principal>>,
+    pub roles: Option<Vec<RoleAssignment>>,
+    pub operators: Option<Vec<OperatorAssignment>>,
+}
+
+// given at install and, optionally, at upgrade
+#[derive(CandidType, Deserialize, Debug)]
+pub struct FactoryArg {
+    // canisters allowed to call the factory, replacing the current ones when set
+    pub trusted_callers: Option<Vec<Principal>>,
 }
 
 #[derive(CandidType, Deserialize, Debug)]
@@ -37,10 +71,12 @@ pub struct Arg {
     pub permitted_drift: Option<u64>,
 }
 
-impl From<(Account, Arg)> for InitArg {
-    fn from((account, arg): (Account, Arg)) -> Self {
+// the collection of `account`, called by the backend and the factory. The backend mints the
+// tokens and forwards the transfers of the users, the factory updates the minting authority
+impl From<(Account, Arg, Principal, Principal)> for InitArg {
+    fn from((account, arg, backend, factory): (Account, Arg, Principal, Principal)) -> Self {
         Self {
-            minting_authority: Some(account),
+            minting_account: Some(account),
             icrc7_symbol: arg.icrc7_symbol,
             icrc7_name: arg.icrc7_name,
             icrc7_description: arg.icrc7_description,
@@ -54,6 +90,21 @@ impl From<(Account, Arg)> for InitArg {
             icrc7_atomic_batch_transfers: arg.icrc7_atomic_batch_transfers,
             tx_window: arg.tx_window,
             permitted_drift: arg.permitted_drift,
+            trusted_callers: Some(vec![backend, factory]),
+            roles: Some(vec![
+                RoleAssignment {
+                    principal: backend,
+                    roles: vec![Role::Minter],
+                },
+                RoleAssignment {
+                    principal: factory,
+                    roles: vec![Role::Admin],
+                },
+            ]),
+            operators: Some(vec![OperatorAssignment {
+                operator: backend,
+                scopes: vec![OperatorScope::Transfer],
+            }]),
         }
     }
 }
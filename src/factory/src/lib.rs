@@ -1,8 +1,8 @@
 use std::collections::HashMap;
 
 use candid::{Encode, Principal};
-use common::guards::not_anonymous_caller;
-use common::types::{Arg, InitArg};
+use common::guards::{controller_guard, not_anonymous_caller};
+use common::types::{Arg, FactoryArg, InitArg};
 use ic_cdk::api::management_canister::{
     main::{create_canister, install_code, CreateCanisterArgument, InstallCodeArgument},
     provisional::CanisterSettings,
@@ -10,12 +10,44 @@ use ic_cdk::api::management_canister::{
 use ic_cdk::call;
 use ic_cdk_macros::export_candid;
 use icrc_ledger_types::icrc1::account::Account;
-use memory::{get_collections, insert_collection};
+use memory::{
+    get_collections, get_trusted_callers, insert_collection, insert_trusted_callers,
+    set_trusted_callers,
+};
 
 pub const ICRC7_WASM: &[u8] = std::include_bytes!("../../../wasm_files/icrc7.wasm.gz");
 pub mod common;
 pub mod memory;
 
+#[ic_cdk::init]
+fn init(arg: Option<FactoryArg>) {
+    if let Some(trusted_callers) = arg.and_then(|arg| arg.trusted_callers) {
+        set_trusted_callers(trusted_callers);
+    }
+}
+
+#[ic_cdk::post_upgrade]
+fn post_upgrade(arg: Option<FactoryArg>) {
+    init(arg)
+}
+
+#[ic_cdk::update(guard = "controller_guard")]
+fn add_trusted_callers(principals: Vec<Principal>) -> bool {
+    insert_trusted_callers(principals);
+    true
+}
+
+#[ic_cdk::update(guard = "controller_guard")]
+fn remove_trusted_callers(principals: Vec<Principal>) -> bool {
+    memory::remove_trusted_callers(principals);
+    true
+}
+
+#[ic_cdk::query(guard = "controller_guard")]
+fn trusted_callers() -> Vec<Principal> {
+    get_trusted_callers()
+}
+
 #[ic_cdk::update(guard = "not_anonymous_caller")]
 async fn mint_collection_canister(arg: Arg, minting_account: Account) -> Result<Principal, String> {
     // let caller = ic_cdk::caller();
@@ -37,7 +69,8 @@ async fn mint_collection_canister(arg: Arg, minting_account: Account) -> Result<
         Err((code, msg)) => return Err(format!("Rejection Code: {:?}, Message: {:?}", code, msg)),
         Ok((principal,)) => principal.canister_id,
     };
-    let init_arg = InitArg::from((account, arg));
+    // the collection trusts the backend calling the factory and the factory itself
+    let init_arg = InitArg::from((account, arg, ic_cdk::caller(), ic_cdk::id()));
     let init_arg = Encode!(&init_arg).unwrap();
     match install_code(InstallCodeArgument {
         mode: ic_cdk::api::management_canister::main::CanisterInstallMode::Install,
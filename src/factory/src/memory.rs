@@ -14,6 +14,11 @@ thread_local! {
     static COLLECTIONS: RefCell<StableBTree<String, String, Memory>> = RefCell::new({
         StableBTree::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0))))
     });
+
+    // canisters of the deployment allowed to call the factory
+    static TRUSTED_CALLERS: RefCell<StableBTree<Principal, (), Memory>> = RefCell::new({
+        StableBTree::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))))
+    });
 }
 
 pub fn get_collections() -> HashMap<Principal, Principal> {
@@ -38,3 +43,34 @@ pub fn insert_collection(canister_id: Principal, owner: Principal) {
             .insert(owner.to_string(), canister_id.to_string())
     });
 }
+
+pub fn is_trusted_caller(principal: &Principal) -> bool {
+    TRUSTED_CALLERS.with(|callers| callers.borrow().contains_key(principal))
+}
+
+pub fn get_trusted_callers() -> Vec<Principal> {
+    TRUSTED_CALLERS.with(|callers| callers.borrow().iter().map(|(k, _)| k).collect())
+}
+
+pub fn insert_trusted_callers(principals: Vec<Principal>) {
+    TRUSTED_CALLERS.with(|callers| {
+        let mut callers = callers.borrow_mut();
+        for principal in principals {
+            callers.insert(principal, ());
+        }
+    });
+}
+
+pub fn remove_trusted_callers(principals: Vec<Principal>) {
+    TRUSTED_CALLERS.with(|callers| {
+        let mut callers = callers.borrow_mut();
+        for principal in principals {
+            callers.remove(&principal);
+        }
+    });
+}
+
+pub fn set_trusted_callers(principals: Vec<Principal>) {
+    TRUSTED_CALLERS.with(|callers| callers.borrow_mut().clear_new());
+    insert_trusted_callers(principals);
+}
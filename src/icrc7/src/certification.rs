@@ -0,0 +1,173 @@
+use ic_certified_map::{fork, labeled, AsHashTree, HashTree, RbTree};
+use icrc_ledger_types::icrc1::account::Account;
+use serde_bytes::ByteBuf;
+
+use crate::{
+    icrc3_types::{Hash, Value},
+    icrc7_types::Icrc7TokenMetadata,
+};
+
+// labels of the root of the certified tree, in the order required by the hash tree encoding
+const BALANCE_LABEL: &[u8] = b"balance";
+const METADATA_LABEL: &[u8] = b"metadata";
+const OWNER_LABEL: &[u8] = b"owner";
+
+// tokens are keyed by the big-endian bytes of their id, so the tree follows the numeric order
+pub fn token_key(token_id: u128) -> Vec<u8> {
+    token_id.to_be_bytes().to_vec()
+}
+
+// accounts are keyed by the owner's bytes followed by the effective 32 bytes subaccount
+pub fn account_key(account: &Account) -> Vec<u8> {
+    let mut key = account.owner.as_slice().to_vec();
+    key.extend_from_slice(account.effective_subaccount());
+    key
+}
+
+pub fn metadata_value(metadata: &Icrc7TokenMetadata) -> Value {
+    Value::Map(
+        metadata
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+    )
+}
+
+// merges two witnesses of the same tree into a single one revealing the union of both
+fn merge_witnesses<'a>(lhs: HashTree<'a>, rhs: HashTree<'a>) -> HashTree<'a> {
+    match (lhs, rhs) {
+        (HashTree::Pruned(_), tree) | (tree, HashTree::Pruned(_)) => tree,
+        (HashTree::Fork(lhs), HashTree::Fork(rhs)) => {
+            let (lhs_left, lhs_right) = *lhs;
+            let (rhs_left, rhs_right) = *rhs;
+            fork(
+                merge_witnesses(lhs_left, rhs_left),
+                merge_witnesses(lhs_right, rhs_right),
+            )
+        }
+        (HashTree::Labeled(label, lhs), HashTree::Labeled(_, rhs)) => {
+            labeled(label, merge_witnesses(*lhs, *rhs))
+        }
+        (tree, _) => tree,
+    }
+}
+
+fn witness<'a>(tree: &'a RbTree<Vec<u8>, Hash>, keys: &[Vec<u8>]) -> HashTree<'a> {
+    keys.iter()
+        .map(|key| tree.witness(key))
+        .reduce(merge_witnesses)
+        .unwrap_or_else(|| HashTree::Pruned(tree.root_hash()))
+}
+
+// CBOR encoding of a hash tree, prefixed with the self-describe tag expected by the agents
+pub fn encode_hash_tree(tree: &HashTree) -> ByteBuf {
+    let mut bytes = vec![0xd9, 0xd9, 0xf7];
+    ciborium::ser::into_writer(tree, &mut bytes).expect("failed to encode hash tree");
+    ByteBuf::from(bytes)
+}
+
+// Certified view of the collection. Every leaf holds the ICRC-3 hash of the value served by
+// the matching query, so that clients can check a response against the certificate:
+// - `owner/<token_id>`: hash of the owner's account
+// - `metadata/<token_id>`: hash of the token metadata map
+// - `balance/<account>`: hash of the account's balance, accounts without tokens are left out
+#[derive(Default)]
+pub struct CertifiedTrees {
+    balance: RbTree<Vec<u8>, Hash>,
+    metadata: RbTree<Vec<u8>, Hash>,
+    owner: RbTree<Vec<u8>, Hash>,
+}
+
+impl CertifiedTrees {
+    pub fn set_owner(&mut self, token_id: u128, owner: Option<&Account>) {
+        match owner {
+            Some(owner) => self
+                .owner
+                .insert(token_key(token_id), Value::account(owner).hash()),
+            None => self.owner.delete(&token_key(token_id)),
+        }
+    }
+
+    pub fn set_metadata(&mut self, token_id: u128, metadata: Option<&Icrc7TokenMetadata>) {
+        match metadata {
+            Some(metadata) => self
+                .metadata
+                .insert(token_key(token_id), metadata_value(metadata).hash()),
+            None => self.metadata.delete(&token_key(token_id)),
+        }
+    }
+
+    pub fn set_balance(&mut self, account: &Account, balance: u128) {
+        if balance == 0 {
+            self.balance.delete(&account_key(account));
+        } else {
+            self.balance
+                .insert(account_key(account), Value::nat(balance).hash());
+        }
+    }
+
+    // whole tree with the given subtrees under the collection labels and `tip` at the root
+    fn tree<'a>(
+        tip: HashTree<'a>,
+        balance: HashTree<'a>,
+        metadata: HashTree<'a>,
+        owner: HashTree<'a>,
+    ) -> HashTree<'a> {
+        fork(
+            fork(labeled(BALANCE_LABEL, balance), tip),
+            fork(
+                labeled(METADATA_LABEL, metadata),
+                labeled(OWNER_LABEL, owner),
+            ),
+        )
+    }
+
+    pub fn root_hash(&self, tip: HashTree) -> Hash {
+        Self::tree(
+            tip,
+            HashTree::Pruned(self.balance.root_hash()),
+            HashTree::Pruned(self.metadata.root_hash()),
+            HashTree::Pruned(self.owner.root_hash()),
+        )
+        .reconstruct()
+    }
+
+    pub fn tip_witness<'a>(&'a self, tip: HashTree<'a>) -> HashTree<'a> {
+        Self::tree(
+            tip,
+            HashTree::Pruned(self.balance.root_hash()),
+            HashTree::Pruned(self.metadata.root_hash()),
+            HashTree::Pruned(self.owner.root_hash()),
+        )
+    }
+
+    pub fn owner_witness(&self, tip: HashTree, token_ids: &[u128]) -> HashTree<'_> {
+        let keys: Vec<Vec<u8>> = token_ids.iter().map(|id| token_key(*id)).collect();
+        Self::tree(
+            HashTree::Pruned(tip.reconstruct()),
+            HashTree::Pruned(self.balance.root_hash()),
+            HashTree::Pruned(self.metadata.root_hash()),
+            witness(&self.owner, &keys),
+        )
+    }
+
+    pub fn metadata_witness(&self, tip: HashTree, token_ids: &[u128]) -> HashTree<'_> {
+        let keys: Vec<Vec<u8>> = token_ids.iter().map(|id| token_key(*id)).collect();
+        Self::tree(
+            HashTree::Pruned(tip.reconstruct()),
+            HashTree::Pruned(self.balance.root_hash()),
+            witness(&self.metadata, &keys),
+            HashTree::Pruned(self.owner.root_hash()),
+        )
+    }
+
+    pub fn balance_witness(&self, tip: HashTree, accounts: &[Account]) -> HashTree<'_> {
+        let keys: Vec<Vec<u8>> = accounts.iter().map(account_key).collect();
+        Self::tree(
+            HashTree::Pruned(tip.reconstruct()),
+            witness(&self.balance, &keys),
+            HashTree::Pruned(self.metadata.root_hash()),
+            HashTree::Pruned(self.owner.root_hash()),
+        )
+    }
+}
@@ -3,11 +3,14 @@ use serde::Deserialize;
 
 use crate::ext_types::{AccountIdentifierHex, TokenIdentifier};
 
+pub use icrc7_common::errors::InsertTransactionError;
+
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub enum TransferError {
     NonExistingTokenId,
     InvalidRecipient,
     Unauthorized,
+    NonTransferable,
     TooOld,
     CreatedInFuture { ledger_time: u64 },
     Duplicate { duplicate_of: u128 },
@@ -19,31 +22,156 @@ pub enum TransferError {
 pub enum ApprovalError {
     Unauthorized { tokens_ids: Vec<u128> },
     TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: u128 },
     TemporaryUnavailable,
     NonExistingTokenId,
     InvalidSpender,
+    NonTransferable,
     GenericError { error_code: u128, message: String },
     GenericBatchError { error_code: u128, message: String },
 }
 
-#[derive(CandidType, Clone)]
+#[derive(CandidType, Clone, Debug)]
 pub enum BurnError {
     Unauthorized,
     NonExistingTokenId,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: u128 },
     GenericError { error_code: u128, message: String },
     GenericBatchError { error_code: u128, message: String },
 }
 
-#[derive(CandidType, Clone)]
+#[derive(CandidType, Clone, Debug)]
 pub enum MintError {
     SupplyCapReached,
     Unauthorized,
     TokenIdAlreadyExist,
     TokenIdMinimumLimit,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: u128 },
     GenericError { error_code: u128, message: String },
     GenericBatchError { error_code: u128, message: String },
 }
 
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum UpdateMetadataError {
+    Unauthorized,
+    NonExistingTokenId,
+    GenericError { error_code: u128, message: String },
+    GenericBatchError { error_code: u128, message: String },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum UpdateCollectionError {
+    SupplyCapBelowTotalSupply { total_supply: u128 },
+    GenericError { error_code: u128, message: String },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum ApproveTokenError {
+    InvalidSpender,
+    Unauthorized,
+    NonExistingTokenId,
+    NonTransferable,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: u128 },
+    GenericError { error_code: u128, message: String },
+    GenericBatchError { error_code: u128, message: String },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum ApproveCollectionError {
+    InvalidSpender,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: u128 },
+    GenericError { error_code: u128, message: String },
+    GenericBatchError { error_code: u128, message: String },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum RevokeTokenApprovalError {
+    ApprovalDoesNotExist,
+    Unauthorized,
+    NonExistingTokenId,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    GenericError { error_code: u128, message: String },
+    GenericBatchError { error_code: u128, message: String },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum RevokeCollectionApprovalError {
+    ApprovalDoesNotExist,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    GenericError { error_code: u128, message: String },
+    GenericBatchError { error_code: u128, message: String },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum TransferFromError {
+    InvalidRecipient,
+    Unauthorized,
+    NonExistingTokenId,
+    NonTransferable,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: u128 },
+    GenericError { error_code: u128, message: String },
+    GenericBatchError { error_code: u128, message: String },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum SnapshotError {
+    // imports only go into a collection without tokens nor transactions
+    NotEmpty,
+    ImportNotStarted,
+    InvalidConfig,
+    HashMismatch { restored_hash: Vec<u8> },
+    GenericError { error_code: u128, message: String },
+}
+
+// outcome of the `created_at_time` validation shared by all the update methods
+#[derive(Clone, Debug)]
+pub enum TimeError {
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+}
+
+macro_rules! impl_from_time_error {
+    ($($error:ident),*) => {
+        $(
+            impl From<TimeError> for $error {
+                fn from(e: TimeError) -> Self {
+                    match e {
+                        TimeError::TooOld => $error::TooOld,
+                        TimeError::CreatedInFuture { ledger_time } => {
+                            $error::CreatedInFuture { ledger_time }
+                        }
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_time_error!(
+    MintError,
+    BurnError,
+    ApprovalError,
+    TransferError,
+    TransferFromError,
+    ApproveTokenError,
+    ApproveCollectionError,
+    RevokeTokenApprovalError,
+    RevokeCollectionApprovalError
+);
+
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub enum ExtCommonError {
     InvalidToken(TokenIdentifier),
@@ -57,15 +185,6 @@ pub enum ExtTransferError {
     Rejected,
     InvalidToken(TokenIdentifier),
     CannotNotify(AccountIdentifierHex),
+    NonTransferable,
     Other(String),
 }
-
-#[derive(CandidType, Debug, PartialEq, Deserialize)]
-pub enum InsertTransactionError {
-    SyncPending,
-    NotSetArchiveCanister,
-    RemoteError,
-    Unexpected(String),
-    CantWrite,
-    InvalidId,
-}
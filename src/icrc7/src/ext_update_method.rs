@@ -5,37 +5,37 @@ use crate::ext_types::{
     AccountIdentifier, AccountIdentifierHex, ExtApproveArg, ExtMintArg, ExtTokenIndex,
     ExtTransferArg, ExtTransferResult, TokenIdentifier,
 };
-use crate::guards::owner_guard;
-use crate::state::STATE;
+use crate::guards::{not_paused, not_paused_metadata_editor};
+use crate::state::mutate_state;
 
-#[ic_cdk::update(name = "transfer")]
+#[ic_cdk::update(name = "transfer", guard = "not_paused")]
 pub fn ext_transfer(arg: ExtTransferArg) -> ExtTransferResult {
     let caller = ic_cdk::caller();
-    STATE.with(|s| s.borrow_mut().ext_transfer(&caller, arg))
+    mutate_state(|s| s.ext_transfer(&caller, arg))
 }
 
-#[ic_cdk::update(name = "approve")]
+#[ic_cdk::update(name = "approve", guard = "not_paused")]
 pub fn ext_approve(arg: ExtApproveArg) -> bool {
     let caller = ic_cdk::caller();
-    STATE.with(|s| s.borrow_mut().ext_approve(&caller, arg))
+    mutate_state(|s| s.ext_approve(&caller, arg))
 }
 
-#[ic_cdk::update(name = "mintNFT")]
+#[ic_cdk::update(name = "mintNFT", guard = "not_paused")]
 pub fn ext_mint(arg: ExtMintArg) -> ExtTokenIndex {
     let caller = ic_cdk::caller();
     if caller == Principal::anonymous() {
         return 0;
     }
-    STATE.with(|s| s.borrow_mut().ext_mint(&caller, arg))
+    mutate_state(|s| s.ext_mint(&caller, arg))
 }
 
-#[ic_cdk::update(name = "batchMintNFT")]
+#[ic_cdk::update(name = "batchMintNFT", guard = "not_paused")]
 pub fn ext_batch_mint(args: Vec<ExtMintArg>) -> Vec<ExtTokenIndex> {
     let caller = ic_cdk::caller();
     if caller == Principal::anonymous() {
         return vec![0];
     }
-    STATE.with(|s| s.borrow_mut().ext_batch_mint(&caller, args))
+    mutate_state(|s| s.ext_batch_mint(&caller, args))
 }
 
 #[ic_cdk::update(name = "setAccountMapping")]
@@ -45,13 +45,11 @@ pub fn ext_set_account_mapping() -> Option<AccountIdentifierHex> {
         return None;
     }
     let account_id = AccountIdentifier::from_principal(&caller, &Some(*DEFAULT_SUBACCOUNT));
-    STATE.with(|s| {
-        s.borrow_mut()
-            .ext_set_account_mapping(&caller, account_id.to_hex())
-    })
+    mutate_state(|s| s.ext_set_account_mapping(&caller, account_id.to_hex()))
 }
 
-#[ic_cdk::update(name = "updateMetadata", guard = "owner_guard")]
+#[ic_cdk::update(name = "updateMetadata", guard = "not_paused_metadata_editor")]
 pub fn ext_update_metadata(token: TokenIdentifier, description: String) -> bool {
-    STATE.with(|s| s.borrow_mut().ext_update_metadata(token, description))
+    let caller = ic_cdk::caller();
+    mutate_state(|s| s.ext_update_metadata(&caller, token, description))
 }
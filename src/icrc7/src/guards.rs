@@ -1,45 +1,82 @@
-use std::str::FromStr;
-
-use crate::state::STATE;
+use crate::{icrc7_types::Role, state::STATE, utils::caller};
 use candid::Principal;
-use ic_cdk::caller;
 
-#[inline(always)]
-pub fn owner_guard() -> Result<(), String> {
-    let owner = STATE
-        .with(|s| s.borrow().icrc7_minting_authority())
-        .ok_or_else(|| String::from("The canister not set owner"))?;
+fn caller_has_role(role: Role) -> Result<(), String> {
+    if STATE.with(|s| s.borrow().has_role(&caller(), role)) {
+        Ok(())
+    } else {
+        Err(format!("The caller doesn't have the {:?} role", role))
+    }
+}
+
+pub fn admin_guard() -> Result<(), String> {
+    caller_has_role(Role::Admin)
+}
+
+pub fn metadata_editor_guard() -> Result<(), String> {
+    caller_has_role(Role::MetadataEditor)
+}
+
+pub fn archiver_guard() -> Result<(), String> {
+    caller_has_role(Role::Archiver)
+}
+
+pub fn pauser_guard() -> Result<(), String> {
+    caller_has_role(Role::Pauser)
+}
 
-    if caller() == owner.owner {
+pub fn importer_guard() -> Result<(), String> {
+    if STATE.with(|s| s.borrow().is_importer(&caller())) {
         Ok(())
     } else {
-        Err(String::from("The caller is not the owner of contract"))
+        Err(String::from("The caller didn't begin the import"))
     }
 }
 
-// convert a string slice to a principal
-fn slice_to_principal(principal: &str) -> Principal {
-    match Principal::from_str(principal) {
-        Ok(p) => p,
-        _ => Principal::anonymous(),
+pub fn not_paused() -> Result<(), String> {
+    STATE.with(|s| {
+        let s = s.borrow();
+        if s.scalars.paused {
+            Err(String::from("The collection is paused"))
+        } else if s.scalars.importer.is_some() {
+            Err(String::from("The collection is being imported"))
+        } else {
+            Ok(())
+        }
+    })
+}
+
+pub fn authenticated_caller() -> Result<(), String> {
+    if caller() == Principal::anonymous() {
+        Err(String::from("Anonymous Identity"))
+    } else {
+        Ok(())
     }
 }
 
-pub fn not_anonymous_caller() -> Result<(), String> {
-    let caller = caller();
+pub fn not_paused_authenticated_caller() -> Result<(), String> {
+    not_paused()?;
+    authenticated_caller()
+}
+
+pub fn not_paused_metadata_editor() -> Result<(), String> {
+    not_paused()?;
+    metadata_editor_guard()
+}
 
-    let backend_principal = slice_to_principal(
-        #[allow(clippy::option_env_unwrap)]
-        option_env!("CANISTER_ID_ICRC7_BACKEND")
-            .expect("Env variable CANISTER_ID_FACTORY not found!"),
-    );
+pub fn not_paused_archiver() -> Result<(), String> {
+    not_paused()?;
+    archiver_guard()
+}
 
-    let factory_principal = slice_to_principal(
-        #[allow(clippy::option_env_unwrap)]
-        option_env!("CANISTER_ID_FACTORY").expect("Env variable CANISTER_ID_FACTORY not found!"),
-    );
+pub fn not_paused_backend_caller() -> Result<(), String> {
+    not_paused()?;
+    not_anonymous_caller()
+}
 
-    if caller != backend_principal && caller != factory_principal {
+// only the canisters of the deployment, set at install time or by an admin, may call
+pub fn not_anonymous_caller() -> Result<(), String> {
+    if !STATE.with(|s| s.borrow().is_trusted_caller(&caller())) {
         return Err("Caller is not the backend of StakeShare dapp!.".to_string());
     }
     Ok(())
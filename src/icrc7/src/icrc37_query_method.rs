@@ -0,0 +1,42 @@
+use icrc_ledger_types::icrc1::account::Account;
+
+use crate::{
+    icrc37_types::{CollectionApproval, IsApprovedArg, TokenApproval},
+    state::{State, STATE},
+};
+
+#[ic_cdk::query]
+pub fn icrc37_max_approvals_per_token_or_collection() -> Option<u128> {
+    Some(State::DEFAULT_MAX_APPROVALS_PER_TOKEN_OR_COLLECTION)
+}
+
+#[ic_cdk::query]
+pub fn icrc37_max_revoke_approvals() -> Option<u128> {
+    Some(State::DEFAULT_MAX_REVOKE_APPROVALS)
+}
+
+#[ic_cdk::query]
+pub fn icrc37_is_approved(args: Vec<IsApprovedArg>) -> Vec<bool> {
+    STATE.with(|s| s.borrow().icrc37_is_approved(&args))
+}
+
+#[ic_cdk::query]
+pub fn icrc37_get_token_approvals(
+    token_id: u128,
+    prev: Option<TokenApproval>,
+    take: Option<u128>,
+) -> Vec<TokenApproval> {
+    STATE.with(|s| s.borrow().icrc37_get_token_approvals(token_id, prev, take))
+}
+
+#[ic_cdk::query]
+pub fn icrc37_get_collection_approvals(
+    owner: Account,
+    prev: Option<CollectionApproval>,
+    take: Option<u128>,
+) -> Vec<CollectionApproval> {
+    STATE.with(|s| {
+        s.borrow()
+            .icrc37_get_collection_approvals(owner, prev, take)
+    })
+}
@@ -0,0 +1,118 @@
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::{storable::Bound, Storable};
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{
+        ApproveCollectionError, ApproveTokenError, RevokeCollectionApprovalError,
+        RevokeTokenApprovalError, TransferFromError,
+    },
+    icrc7_types::Approval,
+};
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ApprovalInfo {
+    pub spender: Account,
+    pub from_subaccount: Option<Subaccount>,
+    pub expires_at: Option<u64>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: u64,
+}
+
+impl ApprovalInfo {
+    pub fn new(owner: &Account, approval: &Approval) -> Self {
+        Self {
+            spender: approval.account,
+            from_subaccount: owner.subaccount,
+            expires_at: approval.expires_at,
+            memo: approval.memo.clone(),
+            created_at_time: approval.created_at_time.unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ApproveTokenArg {
+    pub token_id: u128,
+    pub approval_info: ApprovalInfo,
+}
+
+pub type ApproveTokenResult = Result<u128, ApproveTokenError>;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ApproveCollectionArg {
+    pub approval_info: ApprovalInfo,
+}
+
+pub type ApproveCollectionResult = Result<u128, ApproveCollectionError>;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct RevokeTokenApprovalArg {
+    pub spender: Option<Account>,
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+pub type RevokeTokenApprovalResult = Result<u128, RevokeTokenApprovalError>;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct RevokeCollectionApprovalArg {
+    pub spender: Option<Account>,
+    pub from_subaccount: Option<Subaccount>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+pub type RevokeCollectionApprovalResult = Result<u128, RevokeCollectionApprovalError>;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct IsApprovedArg {
+    pub spender: Account,
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TokenApproval {
+    pub token_id: u128,
+    pub approval_info: ApprovalInfo,
+}
+
+pub type CollectionApproval = ApprovalInfo;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct TransferFromArg {
+    pub spender_subaccount: Option<Subaccount>,
+    pub from: Account,
+    pub to: Account,
+    pub token_id: u128,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+pub type TransferFromResult = Result<u128, TransferFromError>;
+
+// key of the collection level approvals, ordered by owner first so that all the
+// approvals granted by an account can be read with a single range scan
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub struct CollectionApprovalKey {
+    pub owner: Account,
+    pub spender: Account,
+}
+
+impl Storable for CollectionApprovalKey {
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
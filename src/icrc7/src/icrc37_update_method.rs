@@ -0,0 +1,45 @@
+use crate::{
+    guards::not_paused,
+    icrc37_types::{
+        ApproveCollectionArg, ApproveCollectionResult, ApproveTokenArg, ApproveTokenResult,
+        RevokeCollectionApprovalArg, RevokeCollectionApprovalResult, RevokeTokenApprovalArg,
+        RevokeTokenApprovalResult, TransferFromArg, TransferFromResult,
+    },
+    state::mutate_state,
+};
+
+#[ic_cdk::update(guard = "not_paused")]
+pub fn icrc37_approve_tokens(args: Vec<ApproveTokenArg>) -> Vec<Option<ApproveTokenResult>> {
+    let caller = ic_cdk::caller();
+    mutate_state(|s| s.icrc37_approve_tokens(&caller, args))
+}
+
+#[ic_cdk::update(guard = "not_paused")]
+pub fn icrc37_approve_collection(
+    args: Vec<ApproveCollectionArg>,
+) -> Vec<Option<ApproveCollectionResult>> {
+    let caller = ic_cdk::caller();
+    mutate_state(|s| s.icrc37_approve_collection(&caller, args))
+}
+
+#[ic_cdk::update(guard = "not_paused")]
+pub fn icrc37_revoke_token_approvals(
+    args: Vec<RevokeTokenApprovalArg>,
+) -> Vec<Option<RevokeTokenApprovalResult>> {
+    let caller = ic_cdk::caller();
+    mutate_state(|s| s.icrc37_revoke_token_approvals(&caller, args))
+}
+
+#[ic_cdk::update(guard = "not_paused")]
+pub fn icrc37_revoke_collection_approvals(
+    args: Vec<RevokeCollectionApprovalArg>,
+) -> Vec<Option<RevokeCollectionApprovalResult>> {
+    let caller = ic_cdk::caller();
+    mutate_state(|s| s.icrc37_revoke_collection_approvals(&caller, args))
+}
+
+#[ic_cdk::update(guard = "not_paused")]
+pub fn icrc37_transfer_from(args: Vec<TransferFromArg>) -> Vec<Option<TransferFromResult>> {
+    let caller = ic_cdk::caller();
+    mutate_state(|s| s.icrc37_transfer_from(&caller, args))
+}
@@ -0,0 +1,27 @@
+use crate::{
+    icrc3_types::{
+        DataCertificate, GetArchivesArgs, GetArchivesResult, GetBlocksArgs, GetBlocksResult,
+        SupportedBlockType,
+    },
+    state::STATE,
+};
+
+#[ic_cdk::query]
+pub fn icrc3_get_blocks(args: GetBlocksArgs) -> GetBlocksResult {
+    STATE.with(|s| s.borrow().icrc3_get_blocks(args))
+}
+
+#[ic_cdk::query]
+pub fn icrc3_get_archives(args: GetArchivesArgs) -> GetArchivesResult {
+    STATE.with(|s| s.borrow().icrc3_get_archives(args))
+}
+
+#[ic_cdk::query]
+pub fn icrc3_get_tip_certificate() -> Option<DataCertificate> {
+    STATE.with(|s| s.borrow().icrc3_get_tip_certificate())
+}
+
+#[ic_cdk::query]
+pub fn icrc3_supported_block_types() -> Vec<SupportedBlockType> {
+    STATE.with(|s| s.borrow().icrc3_supported_block_types())
+}
@@ -0,0 +1,34 @@
+use candid::{CandidType, Nat, Principal};
+use serde::Deserialize;
+use serde_bytes::ByteBuf;
+
+pub use icrc7_common::icrc3_types::{
+    ArchivedBlocks, BlockWithId, GetBlocksArgs, GetBlocksFn, GetBlocksRequest, GetBlocksResult,
+    Hash, Value,
+};
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct DataCertificate {
+    pub certificate: ByteBuf,
+    pub hash_tree: ByteBuf,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetArchivesArgs {
+    pub from: Option<Principal>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Icrc3ArchiveInfo {
+    pub canister_id: Principal,
+    pub start: Nat,
+    pub end: Nat,
+}
+
+pub type GetArchivesResult = Vec<Icrc3ArchiveInfo>;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SupportedBlockType {
+    pub block_type: String,
+    pub url: String,
+}
@@ -1,94 +1,234 @@
 use std::collections::HashMap;
 
-use candid::{CandidType, Decode, Encode};
+use candid::{define_function, CandidType, Decode, Encode, Principal};
 use ic_stable_structures::{storable::Bound, Storable};
-use icrc_ledger_types::{
-    icrc::generic_metadata_value::MetadataValue,
-    icrc1::account::{Account, Subaccount},
-};
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
 use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
 
-use crate::errors::{ApprovalError, BurnError, InsertTransactionError, MintError, TransferError};
-
-#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
-pub enum TransactionType {
-    Mint {
-        tid: u128,
-        from: Account,
-        to: Account,
-    },
-    Burn {
-        tid: u128,
-        from: Account,
-        to: Account,
-    },
-    Transfer {
-        tid: u128,
-        from: Account,
-        to: Account,
-    },
-    Approval {
-        tid: u128,
-        from: Account,
-        to: Account,
+use crate::{
+    errors::{
+        ApprovalError, BurnError, InsertTransactionError, MintError, SnapshotError, TransferError,
+        UpdateCollectionError, UpdateMetadataError,
     },
-}
+    icrc37_types::CollectionApprovalKey,
+    icrc3_types::{DataCertificate, Hash, Value},
+    schema::{decode_versioned, encode_versioned, Versioned},
+    state::Icrc7Token,
+};
 
-#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
-pub struct Transaction {
-    pub at: u64,
+pub use icrc7_common::{
+    errors::SyncReceipt,
+    transaction::{Transaction, TransactionType},
+};
+
+// tombstone of a burned token, its id is never reused
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BurnedToken {
+    pub token_id: u128,
+    // owner of the token when it was burned
+    pub owner: Account,
+    pub metadata: Icrc7TokenMetadata,
+    pub burned_at: u64,
     pub txn_id: u128,
-    pub op: String,
-    pub txn_type: TransactionType,
-    pub memo: Option<Vec<u8>>,
 }
 
-impl Storable for Transaction {
+impl BurnedToken {
+    // hash of the tombstone that doesn't depend on the order of its metadata entries
+    pub fn snapshot_hash(&self) -> Hash {
+        let burned = BurnedToken {
+            metadata: Icrc7TokenMetadata::new(),
+            ..self.clone()
+        };
+        let metadata = Value::Map(self.metadata.clone().into_iter().collect());
+        let mut hasher = Sha256::new();
+        hasher.update(Encode!(&burned).expect("failed to encode burned token"));
+        hasher.update(metadata.hash());
+        hasher.finalize().into()
+    }
+}
+
+impl Versioned for BurnedToken {
+    const VERSION: u8 = 1;
+}
+
+impl Storable for BurnedToken {
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        decode_versioned(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Owned(encode_versioned(self))
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// a contiguous range of transactions, by txn id, moved to an archive canister
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ArchivedTxnRange {
+    pub canister_id: Principal,
+    pub start: u128,
+    pub end: u128,
+}
+
+impl Versioned for ArchivedTxnRange {
+    const VERSION: u8 = 1;
+}
+
+impl Storable for ArchivedTxnRange {
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        decode_versioned(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Owned(encode_versioned(self))
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// key of the owner index, ordered by owner first so that the tokens of an account
+// can be read with a single range scan
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub struct OwnerTokenKey {
+    pub owner: Account,
+    pub token_id: u128,
+}
+
+impl Storable for OwnerTokenKey {
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
         Decode!(bytes.as_ref(), Self).unwrap()
     }
 
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
         std::borrow::Cow::Owned(Encode!(self).unwrap())
     }
 
     const BOUND: Bound = Bound::Unbounded;
 }
 
-impl Transaction {
-    pub fn new(txn_id: u128, txn_type: TransactionType, at: u64, memo: Option<Vec<u8>>) -> Self {
-        let op = match &txn_type {
-            TransactionType::Transfer {
-                tid: _,
-                from: _,
-                to: _,
-            } => "transfer".into(),
-            TransactionType::Mint {
-                tid: _,
-                from: _,
-                to: _,
-            } => "mint".into(),
-            TransactionType::Burn {
-                tid: _,
-                from: _,
-                to: _,
-            } => "burn".into(),
-            TransactionType::Approval {
-                tid: _,
-                from: _,
-                to: _,
-            } => "approve".into(),
-        };
+// permissions that can be granted to a principal, independently of each other
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum Role {
+    // grants and revokes roles and updates the collection settings
+    Admin,
+    Minter,
+    MetadataEditor,
+    Archiver,
+    Pauser,
+}
+
+impl Role {
+    pub const ALL: [Role; 5] = [
+        Role::Admin,
+        Role::Minter,
+        Role::MetadataEditor,
+        Role::Archiver,
+        Role::Pauser,
+    ];
+}
+
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub struct RoleKey {
+    pub principal: Principal,
+    pub role: Role,
+}
+
+impl Storable for RoleKey {
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// what an operator canister may do on behalf of the users
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum OperatorScope {
+    Mint,
+    Transfer,
+    UpdateMetadata,
+}
+
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub struct OperatorKey {
+    pub operator: Principal,
+    pub scope: OperatorScope,
+}
+
+impl Storable for OperatorKey {
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct OperatorAssignment {
+    pub operator: Principal,
+    pub scopes: Vec<OperatorScope>,
+}
+
+// principal an update acts for, and the operator that called on its behalf if any
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Actor {
+    pub principal: Principal,
+    pub operator: Option<Principal>,
+}
+
+impl From<Principal> for Actor {
+    fn from(principal: Principal) -> Self {
         Self {
-            op,
-            txn_id,
-            at,
-            txn_type,
-            memo,
+            principal,
+            operator: None,
         }
     }
 }
 
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RoleAssignment {
+    pub principal: Principal,
+    pub roles: Vec<Role>,
+}
+
+// `get_txn_logs` of the archive canister, taking the first txn id and the number of txns
+define_function!(pub GetTxnLogsFn : (u128, u128) -> (Vec<Transaction>) query);
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ArchivedTxnLogs {
+    pub start: u128,
+    pub length: u128,
+    pub callback: GetTxnLogsFn,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct TxnHistory {
+    pub txn_count: u128,
+    pub txn_logs: Vec<Transaction>,
+    pub archived_txn_logs: Vec<ArchivedTxnLogs>,
+}
+
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub struct TransferArg {
     pub from_subaccount: Option<Subaccount>,
@@ -100,32 +240,137 @@ pub struct TransferArg {
 
 pub type TransferResult = Result<u128, TransferError>;
 
-pub type Icrc7TokenMetadata = HashMap<String, MetadataValue>;
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CertifiedOwnerOfResponse {
+    pub owners: Vec<Option<Account>>,
+    pub certificate: DataCertificate,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CertifiedTokenMetadataResponse {
+    pub metadata: Vec<Option<Icrc7TokenMetadata>>,
+    pub certificate: DataCertificate,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CertifiedBalanceOfResponse {
+    pub balances: Vec<u128>,
+    pub certificate: DataCertificate,
+}
+
+pub type Icrc7TokenMetadata = HashMap<String, Value>;
 
 #[derive(CandidType, Deserialize, Clone)]
 pub struct MintArg {
     pub from_subaccount: Option<Subaccount>,
     pub to: Account,
-    pub token_id: u128,
+    // assigned by the collection when None, according to its token id strategy
+    pub token_id: Option<u128>,
     pub memo: Option<Vec<u8>>,
-    // if None, then the combination of Collection's symbol and token's id will be provided
-    // for e.g.: "ICRC7 100"
-    pub token_name: Option<String>,
-    pub token_description: Option<String>,
-    pub token_logo: Option<String>,
+    // stored as is and returned unchanged by icrc7_token_metadata
+    pub metadata: Icrc7TokenMetadata,
+    // defaults to the collection's flag
+    pub transferable: Option<bool>,
+    // overrides the collection's royalties for this token
+    pub royalties: Option<Royalty>,
+    // time, in nanoseconds, from which the token is expired, None never expires
+    pub valid_until: Option<u64>,
+    // enables the deduplication of the request, as for transfers
+    pub created_at_time: Option<u64>,
 }
 
+// id of the minted token
 pub type MintResult = Result<u128, MintError>;
 
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TokenIdStrategy {
+    // ids are taken from an increasing counter unless the caller picks a higher one
+    #[default]
+    Sequential,
+    // every mint must carry its own token id
+    CallerProvided,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Royalty {
+    pub recipient: Account,
+    // share of the sale price, in hundredths of a percent
+    pub basis_points: u16,
+}
+
+impl Royalty {
+    pub const MAX_BASIS_POINTS: u16 = 10_000;
+    pub const BASIS_POINTS_KEY: &'static str = "icrc7:royalties";
+    pub const RECIPIENT_KEY: &'static str = "icrc7:royalty_recipient";
+
+    pub fn is_valid(&self) -> bool {
+        self.basis_points <= Royalty::MAX_BASIS_POINTS
+    }
+
+    // rounded down, computed without overflowing on large prices
+    pub fn amount(&self, sale_price: u128) -> u128 {
+        let basis_points = self.basis_points as u128;
+        let max = Royalty::MAX_BASIS_POINTS as u128;
+        sale_price / max * basis_points + sale_price % max * basis_points / max
+    }
+
+    // how the royalty is reported in collection and token metadata
+    pub fn metadata(&self) -> [(String, Value); 2] {
+        [
+            (
+                Royalty::BASIS_POINTS_KEY.into(),
+                Value::nat(self.basis_points),
+            ),
+            (
+                Royalty::RECIPIENT_KEY.into(),
+                Value::account(&self.recipient),
+            ),
+        ]
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RoyaltyInfo {
+    pub recipient: Account,
+    pub amount: u128,
+}
+
 #[derive(CandidType, Deserialize, Clone)]
 pub struct BurnArg {
     pub from_subaccount: Option<Subaccount>,
     pub token_id: u128,
     pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
 }
 
 pub type BurnResult = Result<u128, BurnError>;
 
+// token id, metadata key and new value of the key, None removes it
+pub type UpdateTokenMetadataArg = (u128, String, Option<Value>);
+
+pub type UpdateMetadataResult = Result<u128, UpdateMetadataError>;
+
+// collection settings to change, the ones left to None are kept as they are
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct UpdateCollectionArg {
+    pub icrc7_name: Option<String>,
+    pub icrc7_description: Option<String>,
+    pub icrc7_logo: Option<String>,
+    pub icrc7_supply_cap: Option<u128>,
+    pub icrc7_max_query_batch_size: Option<u128>,
+    pub icrc7_max_update_batch_size: Option<u128>,
+    pub icrc7_max_take_value: Option<u128>,
+    pub icrc7_default_take_value: Option<u128>,
+    pub icrc7_max_memo_size: Option<u128>,
+    pub icrc7_atomic_batch_transfers: Option<bool>,
+    pub icrc7_royalties: Option<Royalty>,
+    pub tx_window: Option<u64>,
+    pub permitted_drift: Option<u64>,
+}
+
+// id of the transaction recording the changes
+pub type UpdateCollectionResult = Result<u128, UpdateCollectionError>;
+
 #[derive(CandidType, Deserialize)]
 pub struct InitArg {
     pub minting_account: Option<Account>,
@@ -140,8 +385,40 @@ pub struct InitArg {
     pub icrc7_default_take_value: Option<u128>,
     pub icrc7_max_memo_size: Option<u128>,
     pub icrc7_atomic_batch_transfers: Option<bool>,
+    // false for soulbound collections, whose tokens cannot be transferred or approved
+    pub icrc7_transferable: Option<bool>,
+    // paid to the recipient on secondary sales, unless the token sets its own
+    pub icrc7_royalties: Option<Royalty>,
     pub tx_window: Option<u64>,
     pub permitted_drift: Option<u64>,
+    // archiving kicks in once more than this many transactions are kept locally
+    pub archive_max_local_txns: Option<u128>,
+    // number of transactions moved to the archive canister per sync
+    pub archive_batch_size: Option<u128>,
+    // interval, in nanoseconds, between two checks of the archiving job
+    pub archive_check_interval: Option<u64>,
+    // how the ids of minted tokens are chosen, sequential by default
+    pub token_id_strategy: Option<TokenIdStrategy>,
+    // expired tokens are burned automatically when set to true
+    pub burn_expired_tokens: Option<bool>,
+    // interval, in nanoseconds, between two sweeps of the expired tokens
+    pub expiry_check_interval: Option<u64>,
+    // number of tokens whose expired approvals are pruned per sweep
+    pub approval_sweep_batch_size: Option<u128>,
+    // interval, in nanoseconds, between two sweeps of the expired approvals
+    pub approval_sweep_interval: Option<u64>,
+    // canisters allowed to call the collection, the installer when not set
+    pub trusted_callers: Option<Vec<Principal>>,
+    // roles granted on top of the ones of the minting account
+    pub roles: Option<Vec<RoleAssignment>>,
+    // operators allowed to act on behalf of the users from the start
+    pub operators: Option<Vec<OperatorAssignment>>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct UpgradeArg {
+    // replaces the trusted callers when set
+    pub trusted_callers: Option<Vec<Principal>>,
 }
 
 #[derive(CandidType)]
@@ -154,15 +431,21 @@ pub struct Standard {
 pub struct Approval {
     pub expires_at: Option<u64>,
     pub account: Account,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+impl Versioned for Approval {
+    const VERSION: u8 = 1;
 }
 
 impl Storable for Approval {
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
+        decode_versioned(bytes.as_ref())
     }
 
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Owned(encode_versioned(self))
     }
 
     const BOUND: Bound = Bound::Unbounded;
@@ -173,8 +456,14 @@ impl Approval {
         Self {
             expires_at,
             account,
+            memo: None,
+            created_at_time: None,
         }
     }
+
+    pub fn is_active(&self, current_time: u64) -> bool {
+        self.expires_at.is_none() || self.expires_at >= Some(current_time)
+    }
 }
 
 #[derive(CandidType, Deserialize, Clone)]
@@ -184,8 +473,92 @@ pub struct ApprovalArg {
     pub token_id: u128,
     pub expires_at: Option<u64>,
     pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
 }
 
 pub type ApproveResult = Result<u128, ApprovalError>;
 
-pub type SyncReceipt = Result<u32, InsertTransactionError>;
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ApprovalSweepStatus {
+    // first token of the next sweep, None when the next sweep starts a new pass
+    pub next_token_id: Option<u128>,
+    // first collection approval of the next sweep, None when they start over
+    pub next_collection_approval: Option<CollectionApprovalKey>,
+    pub batch_size: u128,
+    pub pruned_approvals: u128,
+    pub completed_passes: u128,
+    pub last_swept_at: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ArchiveStatus {
+    pub archive_log_canister: Option<Principal>,
+    pub local_txn_count: u128,
+    pub archived_txn_count: u128,
+    pub max_local_txns: u128,
+    pub batch_size: u128,
+    pub sync_pending: bool,
+    pub consecutive_failures: u32,
+    pub last_error: Option<InsertTransactionError>,
+    pub last_archived_at: Option<u64>,
+    // set while the job is backing off after a failed sync
+    pub next_attempt_at: Option<u64>,
+}
+
+// position of a snapshot export, each section is exported in ascending key order
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct SnapshotCursor {
+    pub section: SnapshotSection,
+    // hash of the chunks exported so far, the next chunk is chained to it
+    pub snapshot_hash: ByteBuf,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum SnapshotSection {
+    Tokens {
+        after: Option<u128>,
+    },
+    BurnedTokens {
+        after: Option<u128>,
+    },
+    TxnLog {
+        after: Option<u128>,
+    },
+    ArchivedRanges {
+        after: Option<u128>,
+    },
+    CollectionApprovals {
+        after: Option<CollectionApprovalKey>,
+    },
+    ExtAccountMapping {
+        after: Option<String>,
+    },
+    Roles {
+        after: Option<RoleKey>,
+    },
+    Operators {
+        after: Option<OperatorKey>,
+    },
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct SnapshotChunk {
+    // CBOR encoded collection config and counters, only part of the first chunk
+    pub config: Option<ByteBuf>,
+    pub tokens: Vec<Icrc7Token>,
+    pub burned_tokens: Vec<BurnedToken>,
+    pub txn_log: Vec<Transaction>,
+    pub archived_ranges: Vec<ArchivedTxnRange>,
+    pub collection_approvals: Vec<(CollectionApprovalKey, Approval)>,
+    pub ext_account_mapping: Vec<(String, String)>,
+    // they replace the roles and operator scopes of the restored collection
+    pub roles: Vec<RoleKey>,
+    pub operators: Vec<OperatorKey>,
+    // hash of the snapshot up to this chunk, the one of the last chunk is passed to
+    // finish_import
+    pub snapshot_hash: ByteBuf,
+    // where the export resumes, None once the whole collection was exported
+    pub next: Option<SnapshotCursor>,
+}
+
+pub type SnapshotResult = Result<(), SnapshotError>;
@@ -2,23 +2,37 @@ use crate::cycles::WalletReceiveResult;
 use candid::Principal;
 use ic_cdk_macros::export_candid;
 use icrc_ledger_types::icrc1::account::Account;
+use serde_bytes::ByteBuf;
 
 pub mod candid_file_generator;
+pub mod certification;
 pub mod cycles;
 pub mod errors;
 pub mod ext_query_method;
 pub mod ext_types;
 pub mod ext_update_method;
 pub mod guards;
+pub mod icrc37_query_method;
+pub mod icrc37_types;
+pub mod icrc37_update_method;
+pub mod icrc3_query_method;
+pub mod icrc3_types;
 pub mod icrc7_types;
 pub mod init_method;
 pub mod memory;
 pub mod query_method;
+pub mod schema;
 pub mod state;
+pub mod timers;
 pub mod update_method;
 pub mod utils;
 
+#[cfg(test)]
+mod tests;
+
 use crate::ext_types::*;
+use crate::icrc37_types::*;
+use crate::icrc3_types::*;
 use crate::icrc7_types::*;
 
 export_candid!();
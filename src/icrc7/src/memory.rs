@@ -1,13 +1,19 @@
 use crate::{
-    icrc7_types::Transaction,
-    state::{Icrc7Token, MEMORY_MANAGER},
+    icrc37_types::CollectionApprovalKey,
+    icrc3_types::Hash,
+    icrc7_types::{
+        Approval, ArchivedTxnRange, BurnedToken, OperatorKey, OwnerTokenKey, RoleKey, Transaction,
+    },
+    state::{Icrc7Token, Scalars, MEMORY_MANAGER},
 };
+use candid::Principal;
 use ic_stable_structures::{
     memory_manager::{MemoryId, VirtualMemory},
-    DefaultMemoryImpl, StableBTreeMap,
+    DefaultMemoryImpl, StableBTreeMap, StableCell,
 };
 
-// A memory for upgrades, where data from the heap can be serialized/deserialized.
+// Legacy memory where the heap state used to be serialized on upgrade, only read
+// once to migrate older canisters to the scalars cell.
 const UPGRADES: MemoryId = MemoryId::new(0);
 
 pub type Memory = VirtualMemory<DefaultMemoryImpl>;
@@ -27,3 +33,51 @@ pub fn get_log_memory() -> StableBTreeMap<u128, Transaction, Memory> {
 pub fn get_ext_account_memory() -> StableBTreeMap<String, String, Memory> {
     StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3))))
 }
+
+pub fn get_collection_approval_memory() -> StableBTreeMap<CollectionApprovalKey, Approval, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4))))
+}
+
+pub fn get_owner_index_memory() -> StableBTreeMap<OwnerTokenKey, (), Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5))))
+}
+
+pub fn get_burned_token_memory() -> StableBTreeMap<u128, BurnedToken, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6))))
+}
+
+pub fn get_token_expiry_memory() -> StableBTreeMap<(u64, u128), (), Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7))))
+}
+
+pub fn get_dedup_memory() -> StableBTreeMap<Hash, u128, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8))))
+}
+
+pub fn get_dedup_expiry_memory() -> StableBTreeMap<(u64, Hash), (), Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9))))
+}
+
+pub fn get_role_memory() -> StableBTreeMap<RoleKey, (), Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10))))
+}
+
+pub fn get_trusted_caller_memory() -> StableBTreeMap<Principal, (), Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11))))
+}
+
+pub fn get_operator_memory() -> StableBTreeMap<OperatorKey, (), Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12))))
+}
+
+pub fn get_scalars_memory() -> StableCell<Scalars, Memory> {
+    StableCell::init(
+        MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13))),
+        Scalars::default(),
+    )
+    .expect("failed to init scalars cell")
+}
+
+pub fn get_archived_range_memory() -> StableBTreeMap<u128, ArchivedTxnRange, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14))))
+}
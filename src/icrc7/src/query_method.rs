@@ -3,8 +3,15 @@ use ic_cdk::caller;
 use icrc_ledger_types::icrc1::account::Account;
 
 use crate::{
-    guards::not_anonymous_caller, icrc7_types::Transaction, state::STATE, Icrc7TokenMetadata,
-    Standard,
+    guards::{admin_guard, not_anonymous_caller},
+    icrc3_types::Value,
+    icrc7_types::{
+        ApprovalSweepStatus, ArchiveStatus, BurnedToken, CertifiedBalanceOfResponse,
+        CertifiedOwnerOfResponse, CertifiedTokenMetadataResponse, OperatorAssignment,
+        RoleAssignment, RoyaltyInfo, SnapshotChunk, SnapshotCursor, Transaction, TxnHistory,
+    },
+    state::STATE,
+    Icrc7TokenMetadata, Standard,
 };
 
 #[ic_cdk::query]
@@ -12,6 +19,12 @@ pub fn whoami() -> String {
     caller().to_string()
 }
 
+// version of the stored data, see crate::schema
+#[ic_cdk::query(guard = "not_anonymous_caller")]
+pub fn schema_version() -> u32 {
+    STATE.with(|s| s.borrow().schema_version())
+}
+
 #[ic_cdk::query(guard = "not_anonymous_caller")]
 pub fn icrc7_symbol() -> String {
     STATE.with(|s| s.borrow().icrc7_symbol())
@@ -77,6 +90,36 @@ pub fn icrc7_atomic_batch_transfers() -> Option<bool> {
     STATE.with(|s| s.borrow().icrc7_atomic_batch_transfers())
 }
 
+#[ic_cdk::query(guard = "not_anonymous_caller")]
+pub fn icrc7_max_default_take_value() -> u128 {
+    STATE.with(|s| s.borrow().icrc7_max_default_take_value())
+}
+
+#[ic_cdk::query(guard = "not_anonymous_caller")]
+pub fn icrc7_tx_window() -> Option<u128> {
+    STATE.with(|s| s.borrow().icrc7_tx_window())
+}
+
+#[ic_cdk::query(guard = "not_anonymous_caller")]
+pub fn icrc7_permitted_drift() -> Option<u128> {
+    STATE.with(|s| s.borrow().icrc7_permitted_drift())
+}
+
+#[ic_cdk::query(guard = "not_anonymous_caller")]
+pub fn icrc7_collection_metadata() -> Vec<(String, Value)> {
+    STATE.with(|s| s.borrow().icrc7_collection_metadata())
+}
+
+#[ic_cdk::query(guard = "not_anonymous_caller")]
+pub fn royalty_info(token_id: u128, sale_price: u128) -> Option<RoyaltyInfo> {
+    STATE.with(|s| s.borrow().royalty_info(token_id, sale_price))
+}
+
+#[ic_cdk::query(guard = "not_anonymous_caller")]
+pub fn icrc7_is_valid(ids: Vec<u128>) -> Vec<Option<bool>> {
+    STATE.with(|s| s.borrow().icrc7_is_valid(&ids))
+}
+
 #[ic_cdk::query(guard = "not_anonymous_caller")]
 pub fn icrc7_owner_of(ids: Vec<u128>) -> Vec<Option<Account>> {
     STATE.with(|s| s.borrow().icrc7_owner_of(&ids))
@@ -84,10 +127,20 @@ pub fn icrc7_owner_of(ids: Vec<u128>) -> Vec<Option<Account>> {
 
 #[ic_cdk::query(guard = "not_anonymous_caller")]
 pub fn icrc7_supported_standards() -> Vec<Standard> {
-    vec![Standard {
-        name: "ICRC-7".into(),
-        url: "https://github.com/dfinity/ICRC/ICRCs/ICRC-7".into(),
-    }]
+    vec![
+        Standard {
+            name: "ICRC-7".into(),
+            url: "https://github.com/dfinity/ICRC/ICRCs/ICRC-7".into(),
+        },
+        Standard {
+            name: "ICRC-37".into(),
+            url: "https://github.com/dfinity/ICRC/ICRCs/ICRC-37".into(),
+        },
+        Standard {
+            name: "ICRC-3".into(),
+            url: "https://github.com/dfinity/ICRC/ICRCs/ICRC-3".into(),
+        },
+    ]
 }
 
 #[ic_cdk::query(guard = "not_anonymous_caller")]
@@ -95,6 +148,16 @@ pub fn icrc7_archive_log_canister() -> Option<Principal> {
     STATE.with(|s| s.borrow().get_archive_log_canister())
 }
 
+#[ic_cdk::query(guard = "not_anonymous_caller")]
+pub fn icrc7_approval_sweep_status() -> ApprovalSweepStatus {
+    STATE.with(|s| s.borrow().icrc7_approval_sweep_status())
+}
+
+#[ic_cdk::query(guard = "not_anonymous_caller")]
+pub fn icrc7_archive_status() -> ArchiveStatus {
+    STATE.with(|s| s.borrow().icrc7_archive_status())
+}
+
 #[ic_cdk::query(guard = "not_anonymous_caller")]
 pub fn icrc7_tokens(prev: Option<u128>, take: Option<u128>) -> Vec<u128> {
     STATE.with(|s| s.borrow().icrc7_tokens(prev, take))
@@ -110,6 +173,35 @@ pub fn icrc7_balance_of(accounts: Vec<Account>) -> Vec<u128> {
     STATE.with(|s| s.borrow().icrc7_balance_of(&accounts))
 }
 
+#[ic_cdk::query(guard = "not_anonymous_caller")]
+pub fn icrc7_burned_count() -> u128 {
+    STATE.with(|s| s.borrow().icrc7_burned_count())
+}
+
+#[ic_cdk::query(guard = "not_anonymous_caller")]
+pub fn icrc7_burned_tokens(prev: Option<u128>, take: Option<u128>) -> Vec<BurnedToken> {
+    STATE.with(|s| s.borrow().icrc7_burned_tokens(prev, take))
+}
+
+// the certified queries are open to every caller: a certificate is only available to the
+// ingress queries of the end users, never to the calls of other canisters
+#[ic_cdk::query]
+pub fn icrc7_owner_of_certified(ids: Vec<u128>) -> Option<CertifiedOwnerOfResponse> {
+    STATE.with(|s| s.borrow().icrc7_owner_of_certified(&ids))
+}
+
+#[ic_cdk::query]
+pub fn icrc7_token_metadata_certified(
+    token_ids: Vec<u128>,
+) -> Option<CertifiedTokenMetadataResponse> {
+    STATE.with(|s| s.borrow().icrc7_token_metadata_certified(&token_ids))
+}
+
+#[ic_cdk::query]
+pub fn icrc7_balance_of_certified(accounts: Vec<Account>) -> Option<CertifiedBalanceOfResponse> {
+    STATE.with(|s| s.borrow().icrc7_balance_of_certified(&accounts))
+}
+
 #[ic_cdk::query(guard = "not_anonymous_caller")]
 pub fn icrc7_tokens_of(account: Account, prev: Option<u128>, take: Option<u128>) -> Vec<u128> {
     STATE.with(|s| s.borrow().icrc7_tokens_of(account, prev, take))
@@ -119,3 +211,35 @@ pub fn icrc7_tokens_of(account: Account, prev: Option<u128>, take: Option<u128>)
 pub fn icrc7_txn_logs(page_number: u32, page_size: u32) -> Vec<Transaction> {
     STATE.with(|s| s.borrow().icrc7_txn_logs(page_number, page_size))
 }
+
+#[ic_cdk::query(guard = "not_anonymous_caller")]
+pub fn icrc7_txn_history(start: u128, length: u128) -> TxnHistory {
+    STATE.with(|s| s.borrow().icrc7_txn_history(start, length))
+}
+
+#[ic_cdk::query(guard = "not_anonymous_caller")]
+pub fn icrc7_list_roles() -> Vec<RoleAssignment> {
+    STATE.with(|s| s.borrow().icrc7_list_roles())
+}
+
+#[ic_cdk::query(guard = "not_anonymous_caller")]
+pub fn icrc7_paused() -> bool {
+    STATE.with(|s| s.borrow().scalars.paused)
+}
+
+#[ic_cdk::query(guard = "admin_guard")]
+pub fn icrc7_trusted_callers() -> Vec<Principal> {
+    STATE.with(|s| s.borrow().icrc7_trusted_callers())
+}
+
+#[ic_cdk::query(guard = "not_anonymous_caller")]
+pub fn icrc7_list_operators() -> Vec<OperatorAssignment> {
+    STATE.with(|s| s.borrow().icrc7_list_operators())
+}
+
+// pass the `next` cursor of each chunk back until it is None, the `snapshot_hash` of the last
+// chunk covers the whole snapshot
+#[ic_cdk::query(guard = "admin_guard")]
+pub fn icrc7_export_snapshot(cursor: Option<SnapshotCursor>) -> SnapshotChunk {
+    STATE.with(|s| s.borrow().icrc7_export_snapshot(cursor))
+}
@@ -0,0 +1,35 @@
+use crate::state::State;
+
+pub use icrc7_common::schema::{decode_versioned, encode_versioned, Versioned};
+
+// version of the stored data once every migration below has run
+pub const SCHEMA_VERSION: u32 = 5;
+
+type Migration = fn(&mut State);
+
+// one-time migrations, keyed by the schema version they bring the stored data to
+const MIGRATIONS: &[(u32, Migration)] = &[
+    // owner index of the tokens minted before it existed
+    (1, State::index_owners),
+    // metadata editors moved to the roles
+    (2, State::migrate_roles),
+    // 3 used to rewrite every stored value in its versioned envelope on upgrade, the values
+    // are now decoded as they are and get their envelope when they are next written
+    // archived ranges moved out of the scalars to their own map
+    (4, State::migrate_archived_ranges),
+    // total supply of the collections created before it was tracked
+    (5, State::recount_supply),
+];
+
+// runs the migrations the stored data has not been through yet, returns the versions applied
+pub fn run_migrations(state: &mut State) -> Vec<u32> {
+    let mut applied = vec![];
+    for (version, migration) in MIGRATIONS {
+        if *version > state.scalars.schema_version {
+            migration(state);
+            state.scalars.schema_version = *version;
+            applied.push(*version);
+        }
+    }
+    applied
+}
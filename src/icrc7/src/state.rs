@@ -1,9 +1,17 @@
-use std::{cell::RefCell, collections::HashMap};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashSet,
+    ops::Bound::{Excluded, Unbounded},
+};
 
 use crate::{
+    certification::{encode_hash_tree, CertifiedTrees},
     errors::{
-        ApprovalError, BurnError, ExtCommonError, ExtTransferError, InsertTransactionError,
-        MintError, TransferError,
+        ApprovalError, ApproveCollectionError, ApproveTokenError, BurnError, ExtCommonError,
+        ExtTransferError, InsertTransactionError, MintError, RevokeCollectionApprovalError,
+        RevokeTokenApprovalError, SnapshotError, TimeError, TransferError, TransferFromError,
+        UpdateCollectionError, UpdateMetadataError,
     },
     ext_types::{
         AccountIdentifier, AccountIdentifierHex, ExtAllowanceArg, ExtAllowanceResult,
@@ -11,111 +19,389 @@ use crate::{
         ExtMetadataResult, ExtMetadataType, ExtMintArg, ExtSupplyResult, ExtTokenIndex,
         ExtTransferArg, ExtTransferResult, TokenIdentifier, User,
     },
+    icrc37_types::{
+        ApprovalInfo, ApproveCollectionArg, ApproveCollectionResult, ApproveTokenArg,
+        ApproveTokenResult, CollectionApproval, CollectionApprovalKey, IsApprovedArg,
+        RevokeCollectionApprovalArg, RevokeCollectionApprovalResult, RevokeTokenApprovalArg,
+        RevokeTokenApprovalResult, TokenApproval, TransferFromArg, TransferFromResult,
+    },
+    icrc3_types::{
+        ArchivedBlocks, BlockWithId, DataCertificate, GetArchivesArgs, GetArchivesResult,
+        GetBlocksArgs, GetBlocksFn, GetBlocksRequest, GetBlocksResult, Hash, Icrc3ArchiveInfo,
+        SupportedBlockType, Value,
+    },
     icrc7_types::{
-        BurnResult, Icrc7TokenMetadata, MintArg, MintResult, Transaction, TransactionType,
-        TransferArg, TransferResult,
+        Actor, ApprovalSweepStatus, ArchiveStatus, ArchivedTxnLogs, ArchivedTxnRange, BurnResult,
+        BurnedToken, CertifiedBalanceOfResponse, CertifiedOwnerOfResponse,
+        CertifiedTokenMetadataResponse, GetTxnLogsFn, Icrc7TokenMetadata, InitArg, MintArg,
+        MintResult, OperatorAssignment, OperatorKey, OperatorScope, OwnerTokenKey, Role,
+        RoleAssignment, RoleKey, Royalty, RoyaltyInfo, SnapshotChunk, SnapshotCursor,
+        SnapshotResult, SnapshotSection, TokenIdStrategy, Transaction, TransactionType,
+        TransferArg, TransferResult, TxnHistory, UpdateCollectionArg, UpdateCollectionResult,
+        UpdateMetadataResult, UpdateTokenMetadataArg, UpgradeArg,
+    },
+    memory::{
+        get_archived_range_memory, get_burned_token_memory, get_collection_approval_memory,
+        get_dedup_expiry_memory, get_dedup_memory, get_ext_account_memory, get_log_memory,
+        get_operator_memory, get_owner_index_memory, get_role_memory, get_scalars_memory,
+        get_token_expiry_memory, get_token_map_memory, get_trusted_caller_memory,
+        get_upgrades_memory, Memory,
+    },
+    schema::{decode_versioned, encode_versioned, run_migrations, Versioned, SCHEMA_VERSION},
+    utils::{
+        account_transformer, burn_account, canister_id, data_certificate, default_account,
+        insert_many_txn_log, set_certified_data, time, user_transformer,
     },
-    memory::{get_ext_account_memory, get_log_memory, get_token_map_memory, Memory},
-    utils::{account_transformer, burn_account, default_account, user_transformer},
     Approval, ApprovalArg, ApproveResult, BurnArg, SyncReceipt,
 };
-use candid::{CandidType, Decode, Encode, Principal};
+use candid::{CandidType, Decode, Encode, Nat, Principal};
+use ic_certified_map::{fork, labeled, HashTree};
 use ic_stable_structures::{
-    memory_manager::MemoryManager, storable::Bound, DefaultMemoryImpl, StableBTreeMap, Storable,
-};
-use icrc_ledger_types::{
-    icrc::generic_metadata_value::MetadataValue,
-    icrc1::account::{Account, DEFAULT_SUBACCOUNT},
+    memory_manager::MemoryManager, storable::Bound, DefaultMemoryImpl, Memory as _, StableBTreeMap,
+    StableCell, Storable,
 };
+use icrc_ledger_types::icrc1::account::{Account, DEFAULT_SUBACCOUNT};
 use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
 
 #[derive(CandidType, Serialize, Deserialize, Clone)]
 pub struct Icrc7Token {
     pub token_id: u128,
-    pub token_name: String,
-    pub token_description: Option<String>,
-    pub token_logo: Option<String>,
     pub token_owner: Account,
     pub approvals: Vec<Approval>,
+    pub metadata: Icrc7TokenMetadata,
+    // None for the tokens minted before the flag existed, which stay transferable
+    pub transferable: Option<bool>,
+    // None falls back to the collection's royalties
+    pub royalties: Option<Royalty>,
+    pub valid_until: Option<u64>,
+}
+
+// tokens minted before metadata maps were introduced
+#[derive(CandidType, Deserialize)]
+struct LegacyIcrc7Token {
+    token_id: u128,
+    token_name: String,
+    token_description: Option<String>,
+    token_logo: Option<String>,
+    token_owner: Account,
+    approvals: Vec<Approval>,
+}
+
+impl From<LegacyIcrc7Token> for Icrc7Token {
+    fn from(legacy: LegacyIcrc7Token) -> Self {
+        let mut metadata = Icrc7TokenMetadata::new();
+        metadata.insert(Icrc7Token::NAME_KEY.into(), Value::Text(legacy.token_name));
+        if let Some(description) = legacy.token_description {
+            metadata.insert(Icrc7Token::DESCRIPTION_KEY.into(), Value::Text(description));
+        }
+        if let Some(logo) = legacy.token_logo {
+            metadata.insert(Icrc7Token::LOGO_KEY.into(), Value::Text(logo));
+        }
+        Self {
+            token_id: legacy.token_id,
+            token_owner: legacy.token_owner,
+            approvals: legacy.approvals,
+            metadata,
+            transferable: None,
+            royalties: None,
+            valid_until: None,
+        }
+    }
+}
+
+impl Versioned for Icrc7Token {
+    const VERSION: u8 = 1;
+
+    // unversioned tokens were written either with the metadata map or with the legacy fields
+    fn from_version(version: u8, payload: &[u8]) -> Option<Self> {
+        match version {
+            0 => Decode!(payload, Self)
+                .or_else(|_| Decode!(payload, LegacyIcrc7Token).map(Into::into))
+                .ok(),
+            _ => None,
+        }
+    }
 }
 
 impl Storable for Icrc7Token {
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
+        decode_versioned(bytes.as_ref())
     }
 
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Owned(encode_versioned(self))
     }
 
     const BOUND: Bound = Bound::Unbounded;
 }
 
 impl Icrc7Token {
+    // well-known metadata keys, as written by the previous fixed token fields
+    pub const NAME_KEY: &'static str = "Name";
+    pub const DESCRIPTION_KEY: &'static str = "Description";
+    pub const LOGO_KEY: &'static str = "logo";
+    pub const TRANSFERABLE_KEY: &'static str = "icrc7:transferable";
+    pub const VALID_UNTIL_KEY: &'static str = "icrc7:valid_until";
+    pub const EXPIRED_KEY: &'static str = "icrc7:expired";
+
+    // hash of the token that doesn't depend on the order of its metadata entries
+    fn snapshot_hash(&self) -> Hash {
+        let token = Icrc7Token {
+            metadata: Icrc7TokenMetadata::new(),
+            ..self.clone()
+        };
+        let metadata = Value::Map(self.metadata.clone().into_iter().collect());
+        let mut hasher = Sha256::new();
+        hasher.update(Encode!(&token).expect("failed to encode token"));
+        hasher.update(metadata.hash());
+        hasher.finalize().into()
+    }
+
     fn new(
         token_id: u128,
-        token_name: String,
-        token_description: Option<String>,
-        token_logo: Option<String>,
+        metadata: Icrc7TokenMetadata,
         token_owner: Account,
+        transferable: bool,
+        royalties: Option<Royalty>,
+        valid_until: Option<u64>,
     ) -> Self {
         Self {
             token_id,
-            token_name,
-            token_logo,
             token_owner,
-            token_description,
             approvals: vec![],
+            metadata,
+            transferable: Some(transferable),
+            royalties,
+            valid_until,
         }
     }
 
+    fn is_transferable(&self) -> bool {
+        self.transferable.unwrap_or(true)
+    }
+
+    fn is_expired(&self, current_time: u64) -> bool {
+        self.valid_until
+            .is_some_and(|valid_until| valid_until <= current_time)
+    }
+
     fn transfer(&mut self, to: Account) {
         self.token_owner = to;
         self.approvals.clear();
     }
 
     fn approve(&mut self, approval: Approval) {
+        // a new approval for the same spender replaces the previous one
+        self.approvals
+            .retain(|existing| existing.account != approval.account);
         self.approvals.push(approval);
     }
 
+    fn revoke(&mut self, spender: Option<Account>) {
+        match spender {
+            Some(spender) => self
+                .approvals
+                .retain(|approval| approval.account != spender),
+            None => self.approvals.clear(),
+        }
+    }
+
     fn approval_check(&self, current_time: u64, account: &Account) -> bool {
         for approval in self.approvals.iter() {
-            if approval.account == *account
-                && (approval.expires_at.is_none() || approval.expires_at >= Some(current_time))
-            {
+            if approval.account == *account && approval.is_active(current_time) {
                 return true;
             }
         }
         false
     }
 
+    // stored metadata along with the transferable flag and the token's own royalties
     fn token_metadata(&self) -> Icrc7TokenMetadata {
-        let mut metadata = HashMap::<String, MetadataValue>::new();
-        metadata.insert("Name".into(), MetadataValue::Text(self.token_name.clone()));
+        let mut metadata = self.metadata.clone();
         metadata.insert(
-            "Symbol".into(),
-            MetadataValue::Text(self.token_name.clone()),
+            Icrc7Token::TRANSFERABLE_KEY.into(),
+            Value::text(self.is_transferable()),
         );
-        if let Some(ref description) = self.token_description {
-            metadata.insert(
-                "Description".into(),
-                MetadataValue::Text(description.clone()),
-            );
+        if let Some(royalty) = self.royalties {
+            metadata.extend(royalty.metadata());
         }
-        if let Some(ref logo) = self.token_logo {
-            metadata.insert("logo".into(), MetadataValue::Text(logo.clone()));
+        if let Some(valid_until) = self.valid_until {
+            metadata.insert(Icrc7Token::VALID_UNTIL_KEY.into(), Value::nat(valid_until));
         }
         metadata
     }
 
-    fn burn(&mut self, burn_address: Account) {
-        self.token_owner = burn_address;
+    // the EXT interface only knows about a textual description of the token
+    fn description(&self) -> Option<String> {
+        match self.metadata.get(Icrc7Token::DESCRIPTION_KEY) {
+            Some(Value::Text(description)) => Some(description.clone()),
+            _ => None,
+        }
+    }
+
+    fn set_description(&mut self, description: String) {
+        self.set_metadata(
+            Icrc7Token::DESCRIPTION_KEY.into(),
+            Some(Value::Text(description)),
+        );
+    }
+
+    fn set_metadata(&mut self, key: String, value: Option<Value>) {
+        match value {
+            Some(value) => self.metadata.insert(key, value),
+            None => self.metadata.remove(&key),
+        };
+    }
+}
+
+// what makes two requests carrying a created_at_time the same one
+struct DedupRequest<'a> {
+    op: &'static str,
+    caller: &'a Account,
+    // as sent by the caller, mints without an id are identified by the other fields
+    token_id: Option<u128>,
+    to: &'a Account,
+    memo: &'a Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+impl<'a> DedupRequest<'a> {
+    // transfers and transfers from share the same window, the caller being the spender of the
+    // latter
+    fn transfer(
+        caller: &'a Account,
+        token_id: u128,
+        to: &'a Account,
+        memo: &'a Option<Vec<u8>>,
+        created_at_time: u64,
+    ) -> Self {
+        Self {
+            op: "transfer",
+            caller,
+            token_id: Some(token_id),
+            to,
+            memo,
+            created_at_time: Some(created_at_time),
+        }
+    }
+
+    fn mint(caller: &'a Account, arg: &'a MintArg) -> Self {
+        Self {
+            op: "mint",
+            caller,
+            token_id: arg.token_id,
+            to: &arg.to,
+            memo: &arg.memo,
+            created_at_time: arg.created_at_time,
+        }
+    }
+
+    fn burn(caller: &'a Account, arg: &'a BurnArg) -> Self {
+        Self {
+            op: "burn",
+            caller,
+            token_id: Some(arg.token_id),
+            to: caller,
+            memo: &arg.memo,
+            created_at_time: arg.created_at_time,
+        }
+    }
+
+    fn approve(caller: &'a Account, arg: &'a ApprovalArg) -> Self {
+        Self {
+            op: "approve",
+            caller,
+            token_id: Some(arg.token_id),
+            to: &arg.spender,
+            memo: &arg.memo,
+            created_at_time: arg.created_at_time,
+        }
+    }
+
+    // icrc37 approvals always carry a created_at_time, so they are always deduplicated
+    fn approve_token(caller: &'a Account, arg: &'a ApproveTokenArg) -> Self {
+        Self {
+            op: "approve",
+            caller,
+            token_id: Some(arg.token_id),
+            to: &arg.approval_info.spender,
+            memo: &arg.approval_info.memo,
+            created_at_time: Some(arg.approval_info.created_at_time),
+        }
+    }
+
+    fn approve_collection(caller: &'a Account, arg: &'a ApproveCollectionArg) -> Self {
+        Self {
+            op: "approve_collection",
+            caller,
+            token_id: None,
+            to: &arg.approval_info.spender,
+            memo: &arg.approval_info.memo,
+            created_at_time: Some(arg.approval_info.created_at_time),
+        }
+    }
+
+    // hash of the request fields, None for the requests that are not deduplicated
+    fn key(&self) -> Option<Hash> {
+        let created_at_time = self.created_at_time?;
+        let mut fields = vec![
+            ("op".to_string(), Value::text(self.op)),
+            ("caller".into(), Value::account(self.caller)),
+            ("to".into(), Value::account(self.to)),
+            ("ts".into(), Value::nat(created_at_time)),
+        ];
+        if let Some(token_id) = self.token_id {
+            fields.push(("tid".into(), Value::nat(token_id)));
+        }
+        if let Some(memo) = self.memo {
+            fields.push(("memo".into(), Value::blob(memo.clone())));
+        }
+        Some(Value::Map(fields).hash())
     }
 }
 
-#[derive(Serialize, Deserialize)]
 pub struct State {
+    pub roles: StableBTreeMap<RoleKey, (), Memory>,
+    // canisters of the deployment allowed through the not_anonymous_caller guard
+    pub trusted_callers: StableBTreeMap<Principal, (), Memory>,
+    // canisters allowed to act on behalf of the users, within the granted scopes
+    pub operators: StableBTreeMap<OperatorKey, (), Memory>,
+    pub tokens: StableBTreeMap<u128, Icrc7Token, Memory>,
+    pub txn_log: StableBTreeMap<u128, Transaction, Memory>,
+    // ranges of the log moved to an archive canister, by their first txn id
+    pub archived_ranges: StableBTreeMap<u128, ArchivedTxnRange, Memory>,
+    pub ext_account_mapping: StableBTreeMap<String, String, Memory>,
+    pub collection_approvals: StableBTreeMap<CollectionApprovalKey, Approval, Memory>,
+    pub owner_tokens: StableBTreeMap<OwnerTokenKey, (), Memory>,
+    pub burned_tokens: StableBTreeMap<u128, BurnedToken, Memory>,
+    // expiring tokens keyed by their expiry time, so the sweeper only visits the due ones
+    pub token_expiries: StableBTreeMap<(u64, u128), (), Memory>,
+    // txn ids of the requests carrying a created_at_time, keyed by DedupRequest::key
+    pub dedup_txns: StableBTreeMap<Hash, u128, Memory>,
+    // the same keys ordered by created_at_time, to prune the ones out of the window
+    pub dedup_expiries: StableBTreeMap<(u64, Hash), (), Memory>,
+    // rebuilt from the tokens after every upgrade
+    pub certified_trees: CertifiedTrees,
+    // next token to add to the certified trees while they are rebuilt, None once they are complete
+    pub certification_cursor: Option<u128>,
+    // every other field, kept on the heap and written back to stable memory after each update
+    pub scalars: Scalars,
+    scalars_cell: StableCell<Scalars, Memory>,
+}
+
+// the part of the state that is not a stable structure by itself. It is stored as a single
+// record, so that upgrades don't need to serialize anything
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Scalars {
     pub minting_authority: Option<Account>,
+    // editors set before the roles existed, moved to the MetadataEditor role on upgrade
+    #[serde(default)]
+    pub metadata_editors: Vec<Account>,
+    // while set, tokens can't be minted, moved, burned or approved
+    #[serde(default)]
+    pub paused: bool,
     pub icrc7_symbol: String,
     pub icrc7_name: String,
     pub icrc7_description: Option<String>,
@@ -128,25 +414,138 @@ pub struct State {
     pub icrc7_default_take_value: Option<u128>,
     pub icrc7_max_memo_size: Option<u128>,
     pub icrc7_atomic_batch_transfers: Option<bool>,
+    #[serde(default)]
+    pub icrc7_transferable: Option<bool>,
+    #[serde(default)]
+    pub icrc7_royalties: Option<Royalty>,
     pub tx_window: Option<u64>,
     pub permitted_drift: Option<u64>,
-    #[serde(skip, default = "get_token_map_memory")]
-    pub tokens: StableBTreeMap<u128, Icrc7Token, Memory>,
     pub txn_count: u128,
     pub next_token_id: u128,
-    #[serde(skip, default = "get_log_memory")]
-    pub txn_log: StableBTreeMap<u128, Transaction, Memory>,
+    #[serde(default)]
+    pub token_id_strategy: TokenIdStrategy,
     pub archive_log_canister: Option<Principal>,
     pub sync_pending_txn_ids: Option<Vec<u128>>,
     pub archive_txn_count: u128,
-    #[serde(skip, default = "get_ext_account_memory")]
-    pub ext_account_mapping: StableBTreeMap<String, String, Memory>,
+    #[serde(default)]
+    pub archive_max_local_txns: Option<u128>,
+    #[serde(default)]
+    pub archive_batch_size: Option<u128>,
+    #[serde(default)]
+    pub archive_check_interval: Option<u64>,
+    #[serde(default)]
+    pub archive_failures: u32,
+    #[serde(default)]
+    pub archive_last_error: Option<InsertTransactionError>,
+    #[serde(default)]
+    pub archive_last_success_at: Option<u64>,
+    #[serde(default)]
+    pub archive_next_attempt_at: Option<u64>,
+    #[serde(default)]
+    pub burn_expired_tokens: Option<bool>,
+    #[serde(default)]
+    pub expiry_check_interval: Option<u64>,
+    #[serde(default)]
+    pub approval_sweep_batch_size: Option<u128>,
+    #[serde(default)]
+    pub approval_sweep_interval: Option<u64>,
+    #[serde(default)]
+    pub approval_sweep_cursor: Option<u128>,
+    #[serde(default)]
+    pub approval_sweep_pruned: u128,
+    #[serde(default)]
+    pub approval_sweep_passes: u128,
+    #[serde(default)]
+    pub approval_sweep_last_run_at: Option<u64>,
+    // first collection approval of the next sweep, swept alongside the tokens
+    #[serde(default)]
+    pub approval_sweep_collection_cursor: Option<CollectionApprovalKey>,
+    #[serde(default)]
+    pub last_block_hash: Option<Hash>,
+    // ranges archived before they had their own map, moved to it on upgrade
+    #[serde(default)]
+    pub archived_ranges: Vec<ArchivedTxnRange>,
+    // admin who began an import, set until finish_import and the collection is paused meanwhile
+    #[serde(default)]
+    pub importer: Option<Principal>,
+    // hash of the chunks imported so far
+    #[serde(default)]
+    pub import_hash: Option<Hash>,
+    // migrations of crate::schema applied to the stored data, 0 for the data written before
+    // the registry existed
+    #[serde(default)]
+    pub schema_version: u32,
 }
 
 impl Default for State {
+    fn default() -> Self {
+        let scalars_cell = get_scalars_memory();
+        Self {
+            roles: get_role_memory(),
+            trusted_callers: get_trusted_caller_memory(),
+            operators: get_operator_memory(),
+            tokens: get_token_map_memory(),
+            txn_log: get_log_memory(),
+            archived_ranges: get_archived_range_memory(),
+            ext_account_mapping: get_ext_account_memory(),
+            collection_approvals: get_collection_approval_memory(),
+            owner_tokens: get_owner_index_memory(),
+            burned_tokens: get_burned_token_memory(),
+            token_expiries: get_token_expiry_memory(),
+            dedup_txns: get_dedup_memory(),
+            dedup_expiries: get_dedup_expiry_memory(),
+            certified_trees: CertifiedTrees::default(),
+            certification_cursor: None,
+            scalars: scalars_cell.get().clone(),
+            scalars_cell,
+        }
+    }
+}
+
+impl Storable for Scalars {
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        ciborium::de::from_reader(bytes.as_ref()).expect("failed to decode scalars")
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = vec![];
+        ciborium::ser::into_writer(self, &mut bytes).expect("failed to encode scalars");
+        Cow::Owned(bytes)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Scalars {
+    // the scalars with the values the canister keeps changing on its own taken from `other`,
+    // so that they are neither carried by a snapshot nor overwritten by its import
+    fn with_runtime_state_of(self, other: &Scalars) -> Scalars {
+        Scalars {
+            paused: other.paused,
+            importer: other.importer,
+            import_hash: other.import_hash,
+            schema_version: other.schema_version,
+            sync_pending_txn_ids: other.sync_pending_txn_ids.clone(),
+            archive_failures: other.archive_failures,
+            archive_last_error: other.archive_last_error.clone(),
+            archive_last_success_at: other.archive_last_success_at,
+            archive_next_attempt_at: other.archive_next_attempt_at,
+            approval_sweep_cursor: other.approval_sweep_cursor,
+            approval_sweep_pruned: other.approval_sweep_pruned,
+            approval_sweep_passes: other.approval_sweep_passes,
+            approval_sweep_last_run_at: other.approval_sweep_last_run_at,
+            approval_sweep_collection_cursor: other.approval_sweep_collection_cursor,
+            ..self
+        }
+    }
+}
+
+impl Default for Scalars {
     fn default() -> Self {
         Self {
             minting_authority: None,
+            metadata_editors: vec![],
+            paused: false,
             icrc7_symbol: "ICRC7".into(),
             icrc7_name: "ICRC7 Collection".into(),
             icrc7_description: None,
@@ -159,20 +558,106 @@ impl Default for State {
             icrc7_default_take_value: None,
             icrc7_max_memo_size: None,
             icrc7_atomic_batch_transfers: None,
+            icrc7_transferable: None,
+            icrc7_royalties: None,
             tx_window: None,
             permitted_drift: None,
-            tokens: get_token_map_memory(),
             txn_count: 0,
             next_token_id: 0,
-            txn_log: get_log_memory(),
+            token_id_strategy: TokenIdStrategy::default(),
             archive_log_canister: None,
             sync_pending_txn_ids: None,
             archive_txn_count: 0,
-            ext_account_mapping: get_ext_account_memory(),
+            archive_max_local_txns: None,
+            archive_batch_size: None,
+            archive_check_interval: None,
+            archive_failures: 0,
+            archive_last_error: None,
+            archive_last_success_at: None,
+            archive_next_attempt_at: None,
+            burn_expired_tokens: None,
+            expiry_check_interval: None,
+            approval_sweep_batch_size: None,
+            approval_sweep_interval: None,
+            approval_sweep_cursor: None,
+            approval_sweep_pruned: 0,
+            approval_sweep_passes: 0,
+            approval_sweep_last_run_at: None,
+            approval_sweep_collection_cursor: None,
+            last_block_hash: None,
+            archived_ranges: vec![],
+            importer: None,
+            import_hash: None,
+            schema_version: 0,
         }
     }
 }
 
+// next page of a section of a snapshot, with the key to resume after when the page is full
+fn snapshot_page<K, V>(
+    map: &StableBTreeMap<K, V, Memory>,
+    after: Option<K>,
+) -> (Vec<(K, V)>, Option<K>)
+where
+    K: Storable + Ord + Clone,
+    V: Storable,
+{
+    let range = match after {
+        Some(after) => (Excluded(after), Unbounded),
+        None => (Unbounded, Unbounded),
+    };
+    let page: Vec<(K, V)> = map.range(range).take(State::SNAPSHOT_CHUNK_SIZE).collect();
+    let last = match page.last() {
+        Some((key, _)) if page.len() == State::SNAPSHOT_CHUNK_SIZE => Some(key.clone()),
+        _ => None,
+    };
+    (page, last)
+}
+
+// hash of a snapshot chunk chained to the hash of the chunks before it, so that exporting and
+// importing a chunk costs the same whatever the size of the collection
+fn chunk_hash(previous_hash: &[u8], chunk: &SnapshotChunk) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(previous_hash);
+    if let Some(config) = &chunk.config {
+        hasher.update(config);
+    }
+    hasher.update((chunk.tokens.len() as u64).to_be_bytes());
+    for token in chunk.tokens.iter() {
+        hasher.update(token.snapshot_hash());
+    }
+    hasher.update((chunk.burned_tokens.len() as u64).to_be_bytes());
+    for burned in chunk.burned_tokens.iter() {
+        hasher.update(burned.snapshot_hash());
+    }
+    hasher.update((chunk.txn_log.len() as u64).to_be_bytes());
+    for txn in chunk.txn_log.iter() {
+        hasher.update(Encode!(txn).expect("failed to encode transaction"));
+    }
+    hasher.update((chunk.archived_ranges.len() as u64).to_be_bytes());
+    for range in chunk.archived_ranges.iter() {
+        hasher.update(Encode!(range).expect("failed to encode archived range"));
+    }
+    hasher.update((chunk.collection_approvals.len() as u64).to_be_bytes());
+    for (key, approval) in chunk.collection_approvals.iter() {
+        hasher.update(Encode!(key, approval).expect("failed to encode approval"));
+    }
+    hasher.update((chunk.ext_account_mapping.len() as u64).to_be_bytes());
+    for (account_id, principal) in chunk.ext_account_mapping.iter() {
+        hasher
+            .update(Value::Map(vec![(account_id.clone(), Value::Text(principal.clone()))]).hash());
+    }
+    hasher.update((chunk.roles.len() as u64).to_be_bytes());
+    for key in chunk.roles.iter() {
+        hasher.update(Encode!(key).expect("failed to encode role"));
+    }
+    hasher.update((chunk.operators.len() as u64).to_be_bytes());
+    for key in chunk.operators.iter() {
+        hasher.update(Encode!(key).expect("failed to encode operator scope"));
+    }
+    hasher.finalize().into()
+}
+
 impl State {
     pub const DEFAULT_MAX_QUERY_BATCH_SIZE: u128 = 32;
     pub const DEFAULT_MAX_UPDATE_BATCH_SIZE: u128 = 32;
@@ -181,200 +666,1243 @@ impl State {
     pub const DEFAULT_MAX_MEMO_SIZE: u128 = 32;
     pub const DEFAULT_TX_WINDOW: u64 = 24 * 60 * 60 * 1_000_000_000;
     pub const DEFAULT_PERMITTED_DRIFT: u64 = 2 * 60 * 1_000_000_000;
+    pub const DEFAULT_MAX_APPROVALS_PER_TOKEN_OR_COLLECTION: u128 = 32;
+    pub const DEFAULT_MAX_REVOKE_APPROVALS: u128 = 32;
+    pub const MAX_BLOCKS_PER_RESPONSE: u128 = 100;
+    pub const MAX_TXN_HISTORY_LENGTH: u128 = 100;
+    pub const DEFAULT_ARCHIVE_MAX_LOCAL_TXNS: u128 = 2_000;
+    pub const DEFAULT_ARCHIVE_BATCH_SIZE: u128 = 200;
+    pub const DEFAULT_ARCHIVE_CHECK_INTERVAL: u64 = 60 * 1_000_000_000;
+    pub const DEFAULT_EXPIRY_CHECK_INTERVAL: u64 = 60 * 60 * 1_000_000_000;
+    pub const DEFAULT_APPROVAL_SWEEP_BATCH_SIZE: u128 = 100;
+    // deduplication entries dropped at most per recorded request
+    pub const DEDUP_PRUNE_BATCH_SIZE: usize = 100;
+    pub const SNAPSHOT_CHUNK_SIZE: usize = 100;
+    // tokens added to the certified trees per timer tick after an upgrade
+    pub const CERTIFICATION_BATCH_SIZE: usize = 500;
+    pub const DEFAULT_APPROVAL_SWEEP_INTERVAL: u64 = 10 * 60 * 1_000_000_000;
+    pub const ARCHIVE_RETRY_BASE_DELAY: u64 = 60 * 1_000_000_000;
+    pub const ARCHIVE_RETRY_MAX_DELAY: u64 = 60 * 60 * 1_000_000_000;
+
+    pub fn save_scalars(&mut self) {
+        self.scalars_cell
+            .set(self.scalars.clone())
+            .expect("failed to save scalars");
+    }
+
+    // canisters upgraded from a version that serialized the heap state in pre_upgrade get
+    // their scalars decoded once from the upgrades memory, which is then marked as empty
+    pub fn migrate_legacy_state(&mut self) {
+        let memory = get_upgrades_memory();
+        if memory.size() == 0 {
+            return;
+        }
+        let mut state_len_bytes = [0; 4];
+        memory.read(0, &mut state_len_bytes);
+        let state_len = u32::from_le_bytes(state_len_bytes) as usize;
+        if state_len == 0 {
+            return;
+        }
+        let mut state_bytes = vec![0; state_len];
+        memory.read(4, &mut state_bytes);
+        self.scalars =
+            ciborium::de::from_reader(&*state_bytes).expect("failed to decode legacy state");
+        self.save_scalars();
+        memory.write(0, &0u32.to_le_bytes());
+    }
+
+    pub fn schema_version(&self) -> u32 {
+        self.scalars.schema_version
+    }
+
+    fn snapshot_config(&self) -> Vec<u8> {
+        let scalars = self
+            .scalars
+            .clone()
+            .with_runtime_state_of(&Scalars::default());
+        let mut bytes = vec![];
+        ciborium::ser::into_writer(&scalars, &mut bytes).expect("failed to encode scalars");
+        bytes
+    }
+
+    pub fn icrc7_export_snapshot(&self, cursor: Option<SnapshotCursor>) -> SnapshotChunk {
+        let mut chunk = SnapshotChunk {
+            config: None,
+            tokens: vec![],
+            burned_tokens: vec![],
+            txn_log: vec![],
+            archived_ranges: vec![],
+            collection_approvals: vec![],
+            ext_account_mapping: vec![],
+            roles: vec![],
+            operators: vec![],
+            snapshot_hash: ByteBuf::new(),
+            next: None,
+        };
+        let (section, previous_hash) = match cursor {
+            Some(cursor) => (cursor.section, cursor.snapshot_hash),
+            None => {
+                chunk.config = Some(ByteBuf::from(self.snapshot_config()));
+                (SnapshotSection::Tokens { after: None }, ByteBuf::new())
+            }
+        };
+        let next = match section {
+            SnapshotSection::Tokens { after } => {
+                let (page, last) = snapshot_page(&self.tokens, after);
+                chunk.tokens = page.into_iter().map(|(_, token)| token).collect();
+                Some(match last {
+                    Some(_) => SnapshotSection::Tokens { after: last },
+                    None => SnapshotSection::BurnedTokens { after: None },
+                })
+            }
+            SnapshotSection::BurnedTokens { after } => {
+                let (page, last) = snapshot_page(&self.burned_tokens, after);
+                chunk.burned_tokens = page.into_iter().map(|(_, burned)| burned).collect();
+                Some(match last {
+                    Some(_) => SnapshotSection::BurnedTokens { after: last },
+                    None => SnapshotSection::TxnLog { after: None },
+                })
+            }
+            SnapshotSection::TxnLog { after } => {
+                let (page, last) = snapshot_page(&self.txn_log, after);
+                chunk.txn_log = page.into_iter().map(|(_, txn)| txn).collect();
+                Some(match last {
+                    Some(_) => SnapshotSection::TxnLog { after: last },
+                    None => SnapshotSection::ArchivedRanges { after: None },
+                })
+            }
+            SnapshotSection::ArchivedRanges { after } => {
+                let (page, last) = snapshot_page(&self.archived_ranges, after);
+                chunk.archived_ranges = page.into_iter().map(|(_, range)| range).collect();
+                Some(match last {
+                    Some(_) => SnapshotSection::ArchivedRanges { after: last },
+                    None => SnapshotSection::CollectionApprovals { after: None },
+                })
+            }
+            SnapshotSection::CollectionApprovals { after } => {
+                let (page, last) = snapshot_page(&self.collection_approvals, after);
+                chunk.collection_approvals = page;
+                Some(match last {
+                    Some(_) => SnapshotSection::CollectionApprovals { after: last },
+                    None => SnapshotSection::ExtAccountMapping { after: None },
+                })
+            }
+            SnapshotSection::ExtAccountMapping { after } => {
+                let (page, last) = snapshot_page(&self.ext_account_mapping, after);
+                chunk.ext_account_mapping = page;
+                Some(match last {
+                    Some(_) => SnapshotSection::ExtAccountMapping { after: last },
+                    None => SnapshotSection::Roles { after: None },
+                })
+            }
+            SnapshotSection::Roles { after } => {
+                let (page, last) = snapshot_page(&self.roles, after);
+                chunk.roles = page.into_iter().map(|(key, _)| key).collect();
+                Some(match last {
+                    Some(_) => SnapshotSection::Roles { after: last },
+                    None => SnapshotSection::Operators { after: None },
+                })
+            }
+            SnapshotSection::Operators { after } => {
+                let (page, last) = snapshot_page(&self.operators, after);
+                chunk.operators = page.into_iter().map(|(key, _)| key).collect();
+                last.map(|_| SnapshotSection::Operators { after: last })
+            }
+        };
+        chunk.snapshot_hash = ByteBuf::from(chunk_hash(&previous_hash, &chunk).to_vec());
+        chunk.next = next.map(|section| SnapshotCursor {
+            section,
+            snapshot_hash: chunk.snapshot_hash.clone(),
+        });
+        chunk
+    }
+
+    // the roles and operator scopes of the collection are dropped for the ones of the
+    // snapshot, only the importer carries the import on until it is finished
+    pub fn begin_import(&mut self, importer: Principal) -> SnapshotResult {
+        if !self.tokens.is_empty()
+            || !self.burned_tokens.is_empty()
+            || !self.txn_log.is_empty()
+            || !self.archived_ranges.is_empty()
+            || !self.collection_approvals.is_empty()
+            || !self.ext_account_mapping.is_empty()
+            || self.scalars.txn_count > 0
+        {
+            return Err(SnapshotError::NotEmpty);
+        }
+        self.roles.clear_new();
+        self.operators.clear_new();
+        self.scalars.importer = Some(importer);
+        self.scalars.import_hash = None;
+        Ok(())
+    }
+
+    pub fn is_importer(&self, principal: &Principal) -> bool {
+        self.scalars.importer.as_ref() == Some(principal)
+    }
+
+    // chunks are only restored in the order they were exported, a chunk that doesn't chain to
+    // the ones imported before it is rejected as a whole
+    pub fn import_snapshot(&mut self, chunk: SnapshotChunk) -> SnapshotResult {
+        if self.scalars.importer.is_none() {
+            return Err(SnapshotError::ImportNotStarted);
+        }
+        let previous_hash = self.scalars.import_hash.map(|hash| hash.to_vec());
+        let restored_hash = chunk_hash(&previous_hash.unwrap_or_default(), &chunk);
+        if chunk.snapshot_hash.as_slice() != restored_hash {
+            return Err(SnapshotError::HashMismatch {
+                restored_hash: restored_hash.to_vec(),
+            });
+        }
+        if let Some(config) = chunk.config {
+            let scalars: Scalars = ciborium::de::from_reader(config.as_slice())
+                .map_err(|_| SnapshotError::InvalidConfig)?;
+            self.scalars = scalars.with_runtime_state_of(&self.scalars);
+            // snapshots of collections that kept the archived ranges in their scalars
+            self.migrate_archived_ranges();
+        }
+        for token in chunk.tokens {
+            if let Some(valid_until) = token.valid_until {
+                self.token_expiries
+                    .insert((valid_until, token.token_id), ());
+            }
+            self.save_token(token);
+        }
+        for burned in chunk.burned_tokens {
+            self.burned_tokens.insert(burned.token_id, burned);
+        }
+        for txn in chunk.txn_log {
+            self.txn_log.insert(txn.txn_id, txn);
+        }
+        for range in chunk.archived_ranges {
+            self.archived_ranges.insert(range.start, range);
+        }
+        for (key, approval) in chunk.collection_approvals {
+            self.collection_approvals.insert(key, approval);
+        }
+        for (account_id, principal) in chunk.ext_account_mapping {
+            self.ext_account_mapping.insert(account_id, principal);
+        }
+        for key in chunk.roles {
+            self.roles.insert(key, ());
+        }
+        for key in chunk.operators {
+            self.operators.insert(key, ());
+        }
+        self.scalars.import_hash = Some(restored_hash);
+        Ok(())
+    }
+
+    // leaves the import mode once the imported chunks hash to the last exported one. The
+    // supply comes with the config and the certified view was kept in sync by every chunk
+    pub fn finish_import(&mut self, snapshot_hash: &[u8]) -> SnapshotResult {
+        if self.scalars.importer.is_none() {
+            return Err(SnapshotError::ImportNotStarted);
+        }
+        let restored_hash = self.scalars.import_hash.unwrap_or_default();
+        if restored_hash.as_slice() != snapshot_hash {
+            return Err(SnapshotError::HashMismatch {
+                restored_hash: restored_hash.to_vec(),
+            });
+        }
+        self.scalars.importer = None;
+        self.scalars.import_hash = None;
+        self.update_certified_data();
+        Ok(())
+    }
 
     pub fn icrc7_symbol(&self) -> String {
-        self.icrc7_symbol.clone()
+        self.scalars.icrc7_symbol.clone()
     }
 
     pub fn icrc7_name(&self) -> String {
-        self.icrc7_name.clone()
+        self.scalars.icrc7_name.clone()
     }
 
     pub fn icrc7_description(&self) -> Option<String> {
-        self.icrc7_description.clone()
+        self.scalars.icrc7_description.clone()
     }
 
     pub fn icrc7_total_supply(&self) -> u128 {
-        self.icrc7_total_supply
+        self.scalars.icrc7_total_supply
     }
 
     pub fn icrc7_supply_cap(&self) -> Option<u128> {
-        self.icrc7_supply_cap
+        self.scalars.icrc7_supply_cap
     }
 
     pub fn icrc7_logo(&self) -> Option<String> {
-        self.icrc7_logo.clone()
+        self.scalars.icrc7_logo.clone()
     }
 
     pub fn icrc7_minting_authority(&self) -> Option<Account> {
-        self.minting_authority
+        self.scalars.minting_authority
     }
 
     pub fn icrc7_max_query_batch_size(&self) -> Option<u128> {
-        self.icrc7_max_query_batch_size
+        self.scalars.icrc7_max_query_batch_size
     }
 
     pub fn icrc7_max_update_batch_size(&self) -> Option<u128> {
-        self.icrc7_max_update_batch_size
+        self.scalars.icrc7_max_update_batch_size
     }
 
     pub fn icrc7_default_take_value(&self) -> Option<u128> {
-        self.icrc7_default_take_value
+        self.scalars.icrc7_default_take_value
     }
 
     pub fn icrc7_max_take_value(&self) -> Option<u128> {
-        self.icrc7_max_take_value
+        self.scalars.icrc7_max_take_value
     }
 
     pub fn icrc7_max_memo_size(&self) -> Option<u128> {
-        self.icrc7_max_memo_size
+        self.scalars.icrc7_max_memo_size
     }
 
     pub fn icrc7_atomic_batch_transfers(&self) -> Option<bool> {
-        self.icrc7_atomic_batch_transfers
+        self.scalars.icrc7_atomic_batch_transfers
+    }
+
+    // tokens are transferable unless the collection was created as soulbound
+    pub fn icrc7_transferable(&self) -> bool {
+        self.scalars.icrc7_transferable.unwrap_or(true)
+    }
+
+    // royalty owed when the token is sold for `sale_price`, None if nothing is owed
+    pub fn royalty_info(&self, token_id: u128, sale_price: u128) -> Option<RoyaltyInfo> {
+        let token = self.tokens.get(&token_id)?;
+        let royalty = token.royalties.or(self.scalars.icrc7_royalties)?;
+        Some(RoyaltyInfo {
+            recipient: royalty.recipient,
+            amount: royalty.amount(sale_price),
+        })
+    }
+
+    pub fn icrc7_tx_window(&self) -> Option<u128> {
+        Some(
+            self.scalars
+                .tx_window
+                .unwrap_or(State::DEFAULT_TX_WINDOW)
+                .into(),
+        )
+    }
+
+    pub fn icrc7_permitted_drift(&self) -> Option<u128> {
+        Some(
+            self.scalars
+                .permitted_drift
+                .unwrap_or(State::DEFAULT_PERMITTED_DRIFT)
+                .into(),
+        )
+    }
+
+    // take applied to paginated queries called without one, bounded by the max take value
+    pub fn icrc7_max_default_take_value(&self) -> u128 {
+        self.scalars
+            .icrc7_default_take_value
+            .unwrap_or(State::DEFAULT_TAKE_VALUE)
+            .min(
+                self.scalars
+                    .icrc7_max_take_value
+                    .unwrap_or(State::DEFAULT_MAX_TAKE_VALUE),
+            )
+    }
+
+    // every collection-level property under its ICRC-7 key. Limits are reported with the
+    // defaults applied, as enforced by the canister, while unset optional properties are left out
+    pub fn icrc7_collection_metadata(&self) -> Vec<(String, Value)> {
+        let mut metadata = vec![
+            (
+                "icrc7:symbol".into(),
+                Value::text(&self.scalars.icrc7_symbol),
+            ),
+            ("icrc7:name".into(), Value::text(&self.scalars.icrc7_name)),
+        ];
+        if let Some(ref description) = self.scalars.icrc7_description {
+            metadata.push(("icrc7:description".into(), Value::text(description)));
+        }
+        if let Some(ref logo) = self.scalars.icrc7_logo {
+            metadata.push(("icrc7:logo".into(), Value::text(logo)));
+        }
+        metadata.push((
+            "icrc7:total_supply".into(),
+            Value::nat(self.scalars.icrc7_total_supply),
+        ));
+        if let Some(supply_cap) = self.scalars.icrc7_supply_cap {
+            metadata.push(("icrc7:supply_cap".into(), Value::nat(supply_cap)));
+        }
+        metadata.extend([
+            (
+                "icrc7:max_query_batch_size".into(),
+                Value::nat(
+                    self.scalars
+                        .icrc7_max_query_batch_size
+                        .unwrap_or(State::DEFAULT_MAX_QUERY_BATCH_SIZE),
+                ),
+            ),
+            (
+                "icrc7:max_update_batch_size".into(),
+                Value::nat(
+                    self.scalars
+                        .icrc7_max_update_batch_size
+                        .unwrap_or(State::DEFAULT_MAX_UPDATE_BATCH_SIZE),
+                ),
+            ),
+            (
+                "icrc7:default_take_value".into(),
+                Value::nat(self.icrc7_max_default_take_value()),
+            ),
+            (
+                "icrc7:max_take_value".into(),
+                Value::nat(
+                    self.scalars
+                        .icrc7_max_take_value
+                        .unwrap_or(State::DEFAULT_MAX_TAKE_VALUE),
+                ),
+            ),
+            (
+                "icrc7:max_memo_size".into(),
+                Value::nat(
+                    self.scalars
+                        .icrc7_max_memo_size
+                        .unwrap_or(State::DEFAULT_MAX_MEMO_SIZE),
+                ),
+            ),
+        ]);
+        if let Some(atomic_batch_transfers) = self.scalars.icrc7_atomic_batch_transfers {
+            metadata.push((
+                "icrc7:atomic_batch_transfers".into(),
+                Value::text(atomic_batch_transfers),
+            ));
+        }
+        metadata.push((
+            Icrc7Token::TRANSFERABLE_KEY.into(),
+            Value::text(self.icrc7_transferable()),
+        ));
+        if let Some(royalty) = self.scalars.icrc7_royalties {
+            metadata.extend(royalty.metadata());
+        }
+        metadata.push((
+            "icrc7:burn_expired_tokens".into(),
+            Value::text(self.burns_expired_tokens()),
+        ));
+        metadata.push((
+            "icrc7:tx_window".into(),
+            Value::nat(self.scalars.tx_window.unwrap_or(State::DEFAULT_TX_WINDOW)),
+        ));
+        metadata.push((
+            "icrc7:permitted_drift".into(),
+            Value::nat(
+                self.scalars
+                    .permitted_drift
+                    .unwrap_or(State::DEFAULT_PERMITTED_DRIFT),
+            ),
+        ));
+        metadata
     }
 
     pub fn icrc7_owner_of(&self, token_id: &[u128]) -> Vec<Option<Account>> {
         let mut res = vec![None; token_id.len()];
         for (index, id) in token_id.iter().enumerate() {
             if let Some(ref token) = self.tokens.get(id) {
-                res.insert(index, Some(token.token_owner))
+                res[index] = Some(token.token_owner)
             }
         }
         res
     }
 
     pub fn get_archive_log_canister(&self) -> Option<Principal> {
-        self.archive_log_canister
+        self.scalars.archive_log_canister
     }
 
     pub fn get_sync_pending_txn_ids(&self) -> Option<Vec<u128>> {
-        self.sync_pending_txn_ids.clone()
+        self.scalars.sync_pending_txn_ids.clone()
     }
 
-    pub fn set_sync_pending_txn_ids(&mut self, txn_ids: Option<Vec<u128>>) -> bool {
-        self.sync_pending_txn_ids = txn_ids;
-        true
+    pub fn archive_batch_size(&self) -> u128 {
+        self.scalars
+            .archive_batch_size
+            .unwrap_or(State::DEFAULT_ARCHIVE_BATCH_SIZE)
+            .max(1)
     }
 
-    fn txn_deduplication_check(
-        &self,
-        allowed_past_time: &u64,
-        caller: &Account,
-        args: &TransferArg,
-    ) -> Result<(), TransferError> {
-        let mut count = self.txn_count;
-        while count != 0 {
-            let txn = self.txn_log.get(&count).unwrap();
-            if txn.at < *allowed_past_time {
-                return Ok(());
-            }
-            match txn.txn_type {
-                TransactionType::Transfer {
-                    ref tid,
-                    ref from,
-                    ref to,
-                } => {
-                    if &args.token_id == tid
-                        && caller == from
-                        && &args.to == to
-                        && args.memo == txn.memo
-                        && args.created_at_time == Some(txn.at)
-                    {
-                        return Err(TransferError::Duplicate {
-                            duplicate_of: count,
-                        });
-                    } else {
-                        count -= 1;
-                        continue;
-                    }
-                }
-                _ => {
-                    count -= 1;
-                    continue;
-                }
-            }
-        }
-        Ok(())
+    pub fn burns_expired_tokens(&self) -> bool {
+        self.scalars.burn_expired_tokens.unwrap_or(false)
     }
 
-    fn get_txn_id(&mut self) -> u128 {
-        self.txn_count += 1;
-        self.txn_count
+    pub fn expiry_check_interval(&self) -> u64 {
+        self.scalars
+            .expiry_check_interval
+            .unwrap_or(State::DEFAULT_EXPIRY_CHECK_INTERVAL)
+            .max(1)
     }
 
-    fn log_transaction(
-        &mut self,
-        txn_type: TransactionType,
-        at: u64,
-        memo: Option<Vec<u8>>,
-    ) -> u128 {
-        let txn_id = self.get_txn_id();
-        let txn = Transaction::new(txn_id, txn_type, at, memo);
-        self.txn_log.insert(txn_id, txn);
-        txn_id
+    pub fn approval_sweep_batch_size(&self) -> u128 {
+        self.scalars
+            .approval_sweep_batch_size
+            .unwrap_or(State::DEFAULT_APPROVAL_SWEEP_BATCH_SIZE)
+            .max(1)
     }
 
-    fn get_current_txn_count(&self) -> u128 {
-        self.txn_count - self.archive_txn_count
+    pub fn approval_sweep_interval(&self) -> u64 {
+        self.scalars
+            .approval_sweep_interval
+            .unwrap_or(State::DEFAULT_APPROVAL_SWEEP_INTERVAL)
+            .max(1)
     }
 
-    fn mock_transfer(
-        &self,
-        current_time: &u64,
-        caller: &Account,
-        arg: &TransferArg,
-    ) -> Result<(), TransferError> {
-        if let Some(time) = arg.created_at_time {
-            let allowed_past_time = *current_time
-                - self.tx_window.unwrap_or(State::DEFAULT_TX_WINDOW)
-                - self
-                    .permitted_drift
-                    .unwrap_or(State::DEFAULT_PERMITTED_DRIFT);
-            let allowed_future_time = *current_time
-                + self
-                    .permitted_drift
-                    .unwrap_or(State::DEFAULT_PERMITTED_DRIFT);
-            if time < allowed_past_time {
-                return Err(TransferError::TooOld);
-            } else if time > allowed_future_time {
-                return Err(TransferError::CreatedInFuture {
-                    ledger_time: *current_time,
-                });
-            }
-            self.txn_deduplication_check(&allowed_past_time, caller, arg)?;
-        }
-        // checking is token for the corresponding ID exists or not
-        if self.tokens.get(&arg.token_id).is_none() {
-            return Err(TransferError::NonExistingTokenId);
+    // drops the expired approvals of the next batch of tokens and of the next batch of
+    // collection approvals, a pass ends after the last token
+    pub fn sweep_expired_approvals(&mut self, current_time: u64) -> u128 {
+        if self.jobs_suspended() {
+            return 0;
         }
-        if let Some(ref memo) = arg.memo {
-            let max_memo_size = self
-                .icrc7_max_memo_size
-                .unwrap_or(State::DEFAULT_MAX_MEMO_SIZE);
-            if memo.len() as u128 > max_memo_size {
-                return Err(TransferError::GenericError {
-                    error_code: 3,
-                    message: "Exceeds Max Memo Size".into(),
-                });
+        let batch_size = self.approval_sweep_batch_size() as usize;
+        let start = self.scalars.approval_sweep_cursor.unwrap_or(0);
+        let mut batch: Vec<Icrc7Token> = self
+            .tokens
+            .range(start..)
+            .take(batch_size + 1)
+            .map(|(_, token)| token)
+            .collect();
+        let next_token_id = match batch.len() > batch_size {
+            true => batch.pop().map(|token| token.token_id),
+            false => None,
+        };
+        let mut pruned = 0;
+        for mut token in batch {
+            let (active, expired): (Vec<Approval>, Vec<Approval>) = token
+                .approvals
+                .drain(..)
+                .partition(|approval| approval.is_active(current_time));
+            if expired.is_empty() {
+                continue;
             }
+            token.approvals = active;
+            for approval in expired {
+                self.log_transaction(
+                    TransactionType::ApprovalExpired {
+                        tid: token.token_id,
+                        from: token.token_owner,
+                        to: approval.account,
+                    },
+                    current_time,
+                    None,
+                );
+                pruned += 1;
+            }
+            // approvals are not part of the certified data, the token is stored as is
+            self.tokens.insert(token.token_id, token);
         }
-        // checking if receiver and sender have same address
-        if arg.to == *caller {
-            return Err(TransferError::InvalidRecipient);
-        }
-        let token = self.tokens.get(&arg.token_id).unwrap();
-        // checking if the caller is authorized or is approve to make transaction
-        if token.token_owner != *caller && !token.approval_check(*current_time, caller) {
-            return Err(TransferError::Unauthorized);
+        pruned += self.sweep_expired_collection_approvals(current_time, batch_size);
+        if next_token_id.is_none() {
+            self.scalars.approval_sweep_passes += 1;
         }
-        Ok(())
+        self.scalars.approval_sweep_cursor = next_token_id;
+        self.scalars.approval_sweep_pruned += pruned;
+        self.scalars.approval_sweep_last_run_at = Some(current_time);
+        pruned
     }
 
-    pub fn icrc7_transfer(
-        &mut self,
-        caller: &Principal,
-        mut args: Vec<TransferArg>,
+    fn sweep_expired_collection_approvals(&mut self, current_time: u64, batch_size: usize) -> u128 {
+        let mut batch: Vec<(CollectionApprovalKey, Approval)> =
+            match self.scalars.approval_sweep_collection_cursor {
+                Some(start) => self
+                    .collection_approvals
+                    .range(start..)
+                    .take(batch_size + 1)
+                    .collect(),
+                None => self
+                    .collection_approvals
+                    .iter()
+                    .take(batch_size + 1)
+                    .collect(),
+            };
+        self.scalars.approval_sweep_collection_cursor = match batch.len() > batch_size {
+            true => batch.pop().map(|(key, _)| key),
+            false => None,
+        };
+        let mut pruned = 0;
+        for (key, approval) in batch {
+            if approval.is_active(current_time) {
+                continue;
+            }
+            self.collection_approvals.remove(&key);
+            self.log_transaction(
+                TransactionType::CollectionApprovalExpired {
+                    from: key.owner,
+                    to: key.spender,
+                },
+                current_time,
+                None,
+            );
+            pruned += 1;
+        }
+        pruned
+    }
+
+    // the stable copy of the scalars is only rewritten when the sweep pruned approvals or
+    // moved on to another batch, the pass counters and last run follow the next write
+    pub fn run_approval_sweep(&mut self, current_time: u64) {
+        let cursors = (
+            self.scalars.approval_sweep_cursor,
+            self.scalars.approval_sweep_collection_cursor,
+        );
+        let pruned = self.sweep_expired_approvals(current_time);
+        if pruned > 0
+            || cursors
+                != (
+                    self.scalars.approval_sweep_cursor,
+                    self.scalars.approval_sweep_collection_cursor,
+                )
+        {
+            self.save_scalars();
+        }
+    }
+
+    pub fn has_role(&self, principal: &Principal, role: Role) -> bool {
+        self.roles.contains_key(&RoleKey {
+            principal: *principal,
+            role,
+        })
+    }
+
+    // returns false if the principal already had the role
+    pub fn grant_role(&mut self, principal: Principal, role: Role) -> bool {
+        self.roles.insert(RoleKey { principal, role }, ()).is_none()
+    }
+
+    // returns false if the principal didn't have the role. The last admin can't be revoked,
+    // as nobody could grant the roles anymore
+    pub fn revoke_role(&mut self, principal: Principal, role: Role) -> bool {
+        if role == Role::Admin && self.role_holders(Role::Admin) == vec![principal] {
+            return false;
+        }
+        self.roles.remove(&RoleKey { principal, role }).is_some()
+    }
+
+    fn role_holders(&self, role: Role) -> Vec<Principal> {
+        self.roles
+            .iter()
+            .filter(|(key, _)| key.role == role)
+            .map(|(key, _)| key.principal)
+            .collect()
+    }
+
+    pub fn icrc7_list_roles(&self) -> Vec<RoleAssignment> {
+        let mut assignments: Vec<RoleAssignment> = vec![];
+        for (key, _) in self.roles.iter() {
+            match assignments.last_mut() {
+                Some(last) if last.principal == key.principal => last.roles.push(key.role),
+                _ => assignments.push(RoleAssignment {
+                    principal: key.principal,
+                    roles: vec![key.role],
+                }),
+            }
+        }
+        assignments
+    }
+
+    // sets up a freshly installed collection, the installer being the minting authority and
+    // the only trusted caller unless the argument says otherwise
+    pub fn init_collection(&mut self, arg: InitArg, installer: Principal) {
+        let minting_authority = account_transformer(arg.minting_account.unwrap_or(Account {
+            owner: installer,
+            subaccount: None,
+        }));
+        self.scalars.minting_authority = Some(minting_authority);
+        self.scalars.icrc7_symbol = arg.icrc7_symbol;
+        self.scalars.icrc7_name = arg.icrc7_name;
+        self.scalars.icrc7_description = arg.icrc7_description;
+        self.scalars.icrc7_logo = arg.icrc7_logo;
+        self.scalars.icrc7_supply_cap = arg.icrc7_supply_cap;
+        self.scalars.icrc7_max_query_batch_size = arg.icrc7_max_query_batch_size;
+        self.scalars.icrc7_max_update_batch_size = arg.icrc7_max_update_batch_size;
+        self.scalars.icrc7_max_take_value = arg.icrc7_max_take_value;
+        self.scalars.icrc7_default_take_value = arg.icrc7_default_take_value;
+        self.scalars.icrc7_max_memo_size = arg.icrc7_max_memo_size;
+        self.scalars.icrc7_atomic_batch_transfers = arg.icrc7_atomic_batch_transfers;
+        self.scalars.icrc7_transferable = arg.icrc7_transferable;
+        self.scalars.icrc7_royalties = arg.icrc7_royalties;
+        self.scalars.tx_window = arg.tx_window;
+        self.scalars.permitted_drift = arg.permitted_drift;
+        self.scalars.archive_max_local_txns = arg.archive_max_local_txns;
+        self.scalars.archive_batch_size = arg.archive_batch_size;
+        self.scalars.archive_check_interval = arg.archive_check_interval;
+        self.scalars.token_id_strategy = arg.token_id_strategy.unwrap_or_default();
+        self.scalars.burn_expired_tokens = arg.burn_expired_tokens;
+        self.scalars.expiry_check_interval = arg.expiry_check_interval;
+        self.scalars.approval_sweep_batch_size = arg.approval_sweep_batch_size;
+        self.scalars.approval_sweep_interval = arg.approval_sweep_interval;
+        self.scalars.schema_version = SCHEMA_VERSION;
+        self.init_roles();
+        for assignment in arg.roles.unwrap_or_default() {
+            for role in assignment.roles {
+                self.grant_role(assignment.principal, role);
+            }
+        }
+        for assignment in arg.operators.unwrap_or_default() {
+            for scope in assignment.scopes {
+                self.grant_operator_scope(assignment.operator, scope);
+            }
+        }
+        self.set_trusted_callers(arg.trusted_callers.unwrap_or_else(|| vec![installer]));
+    }
+
+    pub fn upgrade_collection(&mut self, arg: Option<UpgradeArg>) {
+        self.migrate_legacy_state();
+        run_migrations(self);
+        self.restore_last_block_hash();
+        if let Some(trusted_callers) = arg.and_then(|arg| arg.trusted_callers) {
+            self.set_trusted_callers(trusted_callers);
+        }
+        // certified data is not preserved across upgrades, the trees are rebuilt in batches
+        // by the certification timer and the certified queries wait for them
+        self.certified_trees = CertifiedTrees::default();
+        self.certification_cursor = Some(0);
+        self.update_certified_data();
+    }
+
+    // collections installed before the blocks were chained keep a log without a tip, the
+    // next block is chained to the last one logged
+    pub fn restore_last_block_hash(&mut self) {
+        if self.scalars.last_block_hash.is_some() {
+            return;
+        }
+        if let Some((_, txn)) = self.txn_log.last_key_value() {
+            self.scalars.last_block_hash = Some(txn.block().hash());
+        }
+    }
+
+    // the minting authority holds every role until an admin hands them over
+    pub fn init_roles(&mut self) {
+        if let Some(authority) = self.scalars.minting_authority {
+            for role in Role::ALL {
+                self.grant_role(authority.owner, role);
+            }
+        }
+    }
+
+    // collections upgraded from before the roles get them from the minting authority and
+    // the metadata editors
+    pub fn migrate_roles(&mut self) {
+        if self.roles.is_empty() {
+            self.init_roles();
+        }
+        for editor in std::mem::take(&mut self.scalars.metadata_editors) {
+            self.grant_role(editor.owner, Role::MetadataEditor);
+        }
+    }
+
+    // collections upgraded from before the archived ranges had their own map, so that
+    // archiving no longer rewrites a list growing with every batch in the scalars
+    pub fn migrate_archived_ranges(&mut self) {
+        for range in std::mem::take(&mut self.scalars.archived_ranges) {
+            self.archived_ranges.insert(range.start, range);
+        }
+    }
+
+    pub fn is_trusted_caller(&self, principal: &Principal) -> bool {
+        self.trusted_callers.contains_key(principal)
+    }
+
+    pub fn icrc7_trusted_callers(&self) -> Vec<Principal> {
+        self.trusted_callers
+            .iter()
+            .map(|(principal, _)| principal)
+            .collect()
+    }
+
+    pub fn add_trusted_callers(&mut self, principals: Vec<Principal>) {
+        for principal in principals {
+            self.trusted_callers.insert(principal, ());
+        }
+    }
+
+    pub fn remove_trusted_callers(&mut self, principals: Vec<Principal>) {
+        for principal in principals {
+            self.trusted_callers.remove(&principal);
+        }
+    }
+
+    pub fn set_trusted_callers(&mut self, principals: Vec<Principal>) {
+        self.trusted_callers.clear_new();
+        self.add_trusted_callers(principals);
+    }
+
+    // returns false if the operator already had the scope
+    pub fn grant_operator_scope(&mut self, operator: Principal, scope: OperatorScope) -> bool {
+        self.operators
+            .insert(OperatorKey { operator, scope }, ())
+            .is_none()
+    }
+
+    // returns false if the operator didn't have the scope
+    pub fn revoke_operator_scope(&mut self, operator: Principal, scope: OperatorScope) -> bool {
+        self.operators
+            .remove(&OperatorKey { operator, scope })
+            .is_some()
+    }
+
+    pub fn icrc7_list_operators(&self) -> Vec<OperatorAssignment> {
+        let mut assignments: Vec<OperatorAssignment> = vec![];
+        for (key, _) in self.operators.iter() {
+            match assignments.last_mut() {
+                Some(last) if last.operator == key.operator => last.scopes.push(key.scope),
+                _ => assignments.push(OperatorAssignment {
+                    operator: key.operator,
+                    scopes: vec![key.scope],
+                }),
+            }
+        }
+        assignments
+    }
+
+    // who an update acts for: the caller itself, or the given principal when the caller is
+    // an operator granted the scope of the update. None if the caller can't act for it
+    pub fn actor(
+        &self,
+        caller: Principal,
+        on_behalf_of: Option<Principal>,
+        scope: OperatorScope,
+    ) -> Option<Actor> {
+        match on_behalf_of {
+            None => Some(caller.into()),
+            Some(principal) if principal == caller => Some(caller.into()),
+            Some(principal) => self
+                .operators
+                .contains_key(&OperatorKey {
+                    operator: caller,
+                    scope,
+                })
+                .then_some(Actor {
+                    principal,
+                    operator: Some(caller),
+                }),
+        }
+    }
+
+    pub fn set_metadata_editors(&mut self, editors: Vec<Account>) {
+        for principal in self.role_holders(Role::MetadataEditor) {
+            self.revoke_role(principal, Role::MetadataEditor);
+        }
+        for editor in editors {
+            self.grant_role(editor.owner, Role::MetadataEditor);
+        }
+    }
+
+    pub fn set_minting_authority(&mut self, minting_account: Account) {
+        self.grant_role(minting_account.owner, Role::Minter);
+        self.scalars.minting_authority = Some(minting_account);
+    }
+
+    pub fn icrc7_approval_sweep_status(&self) -> ApprovalSweepStatus {
+        ApprovalSweepStatus {
+            next_token_id: self.scalars.approval_sweep_cursor,
+            next_collection_approval: self.scalars.approval_sweep_collection_cursor,
+            batch_size: self.approval_sweep_batch_size(),
+            pruned_approvals: self.scalars.approval_sweep_pruned,
+            completed_passes: self.scalars.approval_sweep_passes,
+            last_swept_at: self.scalars.approval_sweep_last_run_at,
+        }
+    }
+
+    pub fn archive_check_interval(&self) -> u64 {
+        self.scalars
+            .archive_check_interval
+            .unwrap_or(State::DEFAULT_ARCHIVE_CHECK_INTERVAL)
+    }
+
+    fn archive_max_local_txns(&self) -> u128 {
+        self.scalars
+            .archive_max_local_txns
+            .unwrap_or(State::DEFAULT_ARCHIVE_MAX_LOCAL_TXNS)
+    }
+
+    // the background jobs leave the collection as is while it is paused or being imported
+    pub fn jobs_suspended(&self) -> bool {
+        self.scalars.paused || self.scalars.importer.is_some()
+    }
+
+    // whether the archiving job should move a batch now
+    pub fn archive_due(&self, current_time: u64) -> bool {
+        !self.jobs_suspended()
+            && self.scalars.archive_log_canister.is_some()
+            && self.scalars.sync_pending_txn_ids.is_none()
+            && self.get_current_txn_count() > self.archive_max_local_txns()
+            && self
+                .scalars
+                .archive_next_attempt_at
+                .is_none_or(|next_attempt_at| current_time >= next_attempt_at)
+    }
+
+    // failed syncs are retried with an exponential backoff, reset by the next success
+    pub fn record_archive_result(&mut self, result: &SyncReceipt, current_time: u64) {
+        match result {
+            Ok(_) => {
+                self.scalars.archive_failures = 0;
+                self.scalars.archive_last_error = None;
+                self.scalars.archive_last_success_at = Some(current_time);
+                self.scalars.archive_next_attempt_at = None;
+            }
+            Err(e) => {
+                self.scalars.archive_failures = self.scalars.archive_failures.saturating_add(1);
+                self.scalars.archive_last_error = Some(e.clone());
+                let delay = State::ARCHIVE_RETRY_BASE_DELAY
+                    .saturating_mul(1 << (self.scalars.archive_failures - 1).min(16))
+                    .min(State::ARCHIVE_RETRY_MAX_DELAY);
+                self.scalars.archive_next_attempt_at = Some(current_time.saturating_add(delay));
+            }
+        }
+    }
+
+    pub fn icrc7_archive_status(&self) -> ArchiveStatus {
+        ArchiveStatus {
+            archive_log_canister: self.scalars.archive_log_canister,
+            local_txn_count: self.get_current_txn_count(),
+            archived_txn_count: self.scalars.archive_txn_count,
+            max_local_txns: self.archive_max_local_txns(),
+            batch_size: self.archive_batch_size(),
+            sync_pending: self.scalars.sync_pending_txn_ids.is_some(),
+            consecutive_failures: self.scalars.archive_failures,
+            last_error: self.scalars.archive_last_error.clone(),
+            last_archived_at: self.scalars.archive_last_success_at,
+            next_attempt_at: self.scalars.archive_next_attempt_at,
+        }
+    }
+
+    pub fn set_sync_pending_txn_ids(&mut self, txn_ids: Option<Vec<u128>>) -> bool {
+        self.scalars.sync_pending_txn_ids = txn_ids;
+        true
+    }
+
+    fn created_at_time_check(&self, current_time: u64, time: u64) -> Result<u64, TimeError> {
+        let permitted_drift = self
+            .scalars
+            .permitted_drift
+            .unwrap_or(State::DEFAULT_PERMITTED_DRIFT);
+        let allowed_past_time = current_time
+            .saturating_sub(self.scalars.tx_window.unwrap_or(State::DEFAULT_TX_WINDOW))
+            .saturating_sub(permitted_drift);
+        let allowed_future_time = current_time + permitted_drift;
+        if time < allowed_past_time {
+            return Err(TimeError::TooOld);
+        } else if time > allowed_future_time {
+            return Err(TimeError::CreatedInFuture {
+                ledger_time: current_time,
+            });
+        }
+        Ok(allowed_past_time)
+    }
+
+    // returns the id of the transaction recorded inside the deduplication window for the same
+    // request, if any. Requests without a created_at_time are never deduplicated
+    fn txn_deduplication_check(&self, request: &DedupRequest) -> Option<u128> {
+        self.dedup_txns.get(&request.key()?)
+    }
+
+    fn record_deduplication(&mut self, request: &DedupRequest, txn_id: u128, current_time: u64) {
+        let (Some(key), Some(created_at_time)) = (request.key(), request.created_at_time) else {
+            return;
+        };
+        self.dedup_txns.insert(key, txn_id);
+        self.dedup_expiries.insert((created_at_time, key), ());
+        self.prune_deduplication(current_time);
+    }
+
+    // entries created before the window can no longer match, as such requests are rejected
+    // as too old
+    fn prune_deduplication(&mut self, current_time: u64) {
+        let allowed_past_time = current_time
+            .saturating_sub(self.scalars.tx_window.unwrap_or(State::DEFAULT_TX_WINDOW))
+            .saturating_sub(
+                self.scalars
+                    .permitted_drift
+                    .unwrap_or(State::DEFAULT_PERMITTED_DRIFT),
+            );
+        let expired: Vec<(u64, Hash)> = self
+            .dedup_expiries
+            .range(..(allowed_past_time, [0; 32]))
+            .take(State::DEDUP_PRUNE_BATCH_SIZE)
+            .map(|(entry, _)| entry)
+            .collect();
+        for (created_at_time, key) in expired {
+            self.dedup_expiries.remove(&(created_at_time, key));
+            self.dedup_txns.remove(&key);
+        }
+    }
+
+    fn exceeds_max_memo_size(&self, memo: &Option<Vec<u8>>) -> bool {
+        match memo {
+            Some(memo) => {
+                memo.len() as u128
+                    > self
+                        .scalars
+                        .icrc7_max_memo_size
+                        .unwrap_or(State::DEFAULT_MAX_MEMO_SIZE)
+            }
+            None => false,
+        }
+    }
+
+    // common checks of the batch update methods, returns the error code and message of the
+    // GenericBatchError to reply with
+    fn batch_check(
+        &self,
+        caller: &Principal,
+        batch_size: usize,
+        max_batch_size: u128,
+    ) -> Option<(u128, String)> {
+        if batch_size == 0 {
+            return Some((1, "No Arguments Provided".into()));
+        }
+        if batch_size as u128 > max_batch_size {
+            return Some((2, "Exceed Max allowed Update Batch Size".into()));
+        }
+        if *caller == Principal::anonymous() {
+            return Some((100, "Anonymous Identity".into()));
+        }
+        None
+    }
+
+    fn get_txn_id(&mut self) -> u128 {
+        self.scalars.txn_count += 1;
+        self.scalars.txn_count
+    }
+
+    fn log_transaction(
+        &mut self,
+        txn_type: TransactionType,
+        at: u64,
+        memo: Option<Vec<u8>>,
+    ) -> u128 {
+        self.log_operated_transaction(txn_type, at, memo, None)
+    }
+
+    fn log_operated_transaction(
+        &mut self,
+        txn_type: TransactionType,
+        at: u64,
+        memo: Option<Vec<u8>>,
+        operator: Option<Principal>,
+    ) -> u128 {
+        let txn_id = self.get_txn_id();
+        let mut txn = Transaction::new(txn_id, txn_type, at, memo);
+        txn.operator = operator;
+        txn.phash = self.scalars.last_block_hash.map(|hash| hash.to_vec());
+        self.scalars.last_block_hash = Some(txn.block().hash());
+        self.txn_log.insert(txn_id, txn);
+        self.update_certified_data();
+        txn_id
+    }
+
+    // certified tip of the log, as required by icrc3_get_tip_certificate
+    fn tip_hash_tree(&self) -> Option<HashTree<'static>> {
+        let last_block_hash = self.scalars.last_block_hash?;
+        let mut last_block_index = vec![];
+        Nat::from(self.scalars.txn_count - 1)
+            .encode(&mut last_block_index)
+            .expect("cannot encode the last block index");
+        Some(fork(
+            labeled(
+                b"last_block_hash",
+                HashTree::Leaf(Cow::Owned(last_block_hash.to_vec())),
+            ),
+            labeled(
+                b"last_block_index",
+                HashTree::Leaf(Cow::Owned(last_block_index)),
+            ),
+        ))
+    }
+
+    fn tip_tree(&self) -> HashTree<'static> {
+        self.tip_hash_tree().unwrap_or(HashTree::Empty)
+    }
+
+    pub fn update_certified_data(&self) {
+        let root_hash = self.certified_trees.root_hash(self.tip_tree());
+        set_certified_data(&root_hash);
+    }
+
+    fn certify_balance(&mut self, account: &Account) {
+        let balance = self.icrc7_balance_of(&[*account])[0];
+        self.certified_trees.set_balance(account, balance);
+    }
+
+    // stores the token, keeping the certified view of the collection in sync
+    fn save_token(&mut self, token: Icrc7Token) {
+        let previous_owner = self
+            .tokens
+            .get(&token.token_id)
+            .map(|previous| previous.token_owner);
+        let token_owner = token.token_owner;
+        self.certified_trees
+            .set_owner(token.token_id, Some(&token_owner));
+        self.certified_trees
+            .set_metadata(token.token_id, Some(&token.token_metadata()));
+        let token_id = token.token_id;
+        self.tokens.insert(token_id, token);
+        if previous_owner != Some(token_owner) {
+            if let Some(previous_owner) = previous_owner {
+                self.owner_tokens.remove(&OwnerTokenKey {
+                    owner: previous_owner,
+                    token_id,
+                });
+                self.certify_balance(&previous_owner);
+            }
+            self.owner_tokens.insert(
+                OwnerTokenKey {
+                    owner: token_owner,
+                    token_id,
+                },
+                (),
+            );
+            self.certify_balance(&token_owner);
+        }
+        self.update_certified_data();
+    }
+
+    // counterpart of save_token, the token leaves the collection along with its approvals
+    fn remove_token(&mut self, token_id: u128) -> Option<Icrc7Token> {
+        let token = self.tokens.remove(&token_id)?;
+        if let Some(valid_until) = token.valid_until {
+            self.token_expiries.remove(&(valid_until, token_id));
+        }
+        self.certified_trees.set_owner(token_id, None);
+        self.certified_trees.set_metadata(token_id, None);
+        self.owner_tokens.remove(&OwnerTokenKey {
+            owner: token.token_owner,
+            token_id,
+        });
+        self.certify_balance(&token.token_owner);
+        self.update_certified_data();
+        Some(token)
+    }
+
+    // collections created before the supply was tracked get it recounted once on upgrade
+    pub fn recount_supply(&mut self) {
+        self.scalars.icrc7_total_supply = self.tokens.len() as u128;
+    }
+
+    // collections created before the owner index existed get it built on upgrade
+    pub fn index_owners(&mut self) {
+        if self.owner_tokens.len() == self.tokens.len() {
+            return;
+        }
+        let stale_keys: Vec<OwnerTokenKey> = self.owner_tokens.iter().map(|(key, _)| key).collect();
+        for key in stale_keys.iter() {
+            self.owner_tokens.remove(key);
+        }
+        for (token_id, token) in self.tokens.iter() {
+            self.owner_tokens.insert(
+                OwnerTokenKey {
+                    owner: token.token_owner,
+                    token_id,
+                },
+                (),
+            );
+        }
+    }
+
+    // tokens of the account with an id greater or equal to `start`, in ascending order
+    fn owned_tokens(&self, account: Account, start: u128) -> impl Iterator<Item = u128> + '_ {
+        self.owner_tokens
+            .range(
+                OwnerTokenKey {
+                    owner: account,
+                    token_id: start,
+                }..=OwnerTokenKey {
+                    owner: account,
+                    token_id: u128::MAX,
+                },
+            )
+            .map(|(key, _)| key.token_id)
+    }
+
+    // adds the next batch of tokens to the certified trees, returns whether tokens are left.
+    // the balance of an owner is certified along with their first token, later changes to it
+    // are certified by save_token and remove_token
+    pub fn certify_batch(&mut self) -> bool {
+        let Some(start) = self.certification_cursor else {
+            return false;
+        };
+        let batch_size = State::CERTIFICATION_BATCH_SIZE;
+        let mut batch: Vec<(u128, Icrc7Token)> =
+            self.tokens.range(start..).take(batch_size + 1).collect();
+        let next_token_id = match batch.len() > batch_size {
+            true => batch.pop().map(|(token_id, _)| token_id),
+            false => None,
+        };
+        for (token_id, token) in batch {
+            self.certified_trees
+                .set_owner(token_id, Some(&token.token_owner));
+            self.certified_trees
+                .set_metadata(token_id, Some(&token.token_metadata()));
+            if self.owned_tokens(token.token_owner, 0).next() == Some(token_id) {
+                self.certify_balance(&token.token_owner);
+            }
+        }
+        self.certification_cursor = next_token_id;
+        self.update_certified_data();
+        next_token_id.is_some()
+    }
+
+    pub fn is_certified(&self) -> bool {
+        self.certification_cursor.is_none()
+    }
+
+    fn get_current_txn_count(&self) -> u128 {
+        self.scalars.txn_count - self.scalars.archive_txn_count
+    }
+
+    fn mock_transfer(
+        &self,
+        current_time: &u64,
+        caller: &Account,
+        arg: &TransferArg,
+    ) -> Result<(), TransferError> {
+        if let Some(time) = arg.created_at_time {
+            self.created_at_time_check(*current_time, time)?;
+            let request = DedupRequest::transfer(caller, arg.token_id, &arg.to, &arg.memo, time);
+            if let Some(duplicate_of) = self.txn_deduplication_check(&request) {
+                return Err(TransferError::Duplicate { duplicate_of });
+            }
+        }
+        // checking is token for the corresponding ID exists or not
+        if self.tokens.get(&arg.token_id).is_none() {
+            return Err(TransferError::NonExistingTokenId);
+        }
+        if let Some(ref memo) = arg.memo {
+            let max_memo_size = self
+                .scalars
+                .icrc7_max_memo_size
+                .unwrap_or(State::DEFAULT_MAX_MEMO_SIZE);
+            if memo.len() as u128 > max_memo_size {
+                return Err(TransferError::GenericError {
+                    error_code: 3,
+                    message: "Exceeds Max Memo Size".into(),
+                });
+            }
+        }
+        // checking if receiver and sender have same address
+        if arg.to == *caller {
+            return Err(TransferError::InvalidRecipient);
+        }
+        let token = self.tokens.get(&arg.token_id).unwrap();
+        // only the owner can move a token here, approved spenders go through icrc37_transfer_from
+        if token.token_owner != *caller {
+            return Err(TransferError::Unauthorized);
+        }
+        if !token.is_transferable() {
+            return Err(TransferError::NonTransferable);
+        }
+        Ok(())
+    }
+
+    pub fn icrc7_transfer(
+        &mut self,
+        caller: &Actor,
+        mut args: Vec<TransferArg>,
     ) -> Vec<Option<TransferResult>> {
         // checking if the argument length in 0
         if args.is_empty() {
@@ -384,7 +1912,8 @@ impl State {
             }))];
         }
         let max_update_batch_size = self
-            .icrc7_max_query_batch_size
+            .scalars
+            .icrc7_max_update_batch_size
             .unwrap_or(State::DEFAULT_MAX_UPDATE_BATCH_SIZE);
         let mut txn_results = vec![None; args.len()];
         if args.len() as u128 > max_update_batch_size {
@@ -394,25 +1923,32 @@ impl State {
             }));
             return txn_results;
         }
-        if *caller == Principal::anonymous() {
+        if caller.principal == Principal::anonymous() {
             txn_results[0] = Some(Err(TransferError::GenericBatchError {
                 error_code: 100,
                 message: "Anonymous Identity".into(),
             }));
             return txn_results;
         }
-        let current_time = ic_cdk::api::time();
+        let current_time = time();
+        let mut token_ids = HashSet::new();
         for (index, arg) in args.iter_mut().enumerate() {
             let caller_account = account_transformer(Account {
-                owner: *caller,
+                owner: caller.principal,
                 subaccount: arg.from_subaccount,
             });
             arg.to = account_transformer(arg.to);
-            if let Err(e) = self.mock_transfer(&current_time, &caller_account, arg) {
+            // items are checked against the state before the batch, so a token moves once
+            if !token_ids.insert(arg.token_id) {
+                txn_results[index] = Some(Err(TransferError::GenericError {
+                    error_code: 12,
+                    message: "Duplicate Token Id In Batch".into(),
+                }));
+            } else if let Err(e) = self.mock_transfer(&current_time, &caller_account, arg) {
                 txn_results[index] = Some(Err(e));
             }
         }
-        if let Some(true) = self.icrc7_atomic_batch_transfers {
+        if let Some(true) = self.scalars.icrc7_atomic_batch_transfers {
             if txn_results
                 .iter()
                 .any(|res| res.is_some() && res.as_ref().unwrap().is_err())
@@ -422,7 +1958,7 @@ impl State {
         }
         for (index, arg) in args.iter().enumerate() {
             let caller_account = account_transformer(Account {
-                owner: *caller,
+                owner: caller.principal,
                 subaccount: arg.from_subaccount,
             });
             let time = arg.created_at_time.unwrap_or(current_time);
@@ -438,8 +1974,8 @@ impl State {
             let mut token = self.tokens.get(&arg.token_id).unwrap();
             token.transfer(arg.to);
             token.approvals.clear();
-            self.tokens.insert(arg.token_id, token);
-            let txn_id = self.log_transaction(
+            self.save_token(token);
+            let txn_id = self.log_operated_transaction(
                 TransactionType::Transfer {
                     tid: arg.token_id,
                     from: caller_account,
@@ -447,29 +1983,66 @@ impl State {
                 },
                 time,
                 arg.memo.clone(),
+                caller.operator,
             );
+            if let Some(created_at_time) = arg.created_at_time {
+                let request = DedupRequest::transfer(
+                    &caller_account,
+                    arg.token_id,
+                    &arg.to,
+                    &arg.memo,
+                    created_at_time,
+                );
+                self.record_deduplication(&request, txn_id, current_time);
+            }
             txn_results[index] = Some(Ok(txn_id));
         }
         txn_results
     }
 
-    fn mock_mint(&self, caller: &Account, arg: &MintArg) -> Result<(), MintError> {
-        if let Some(cap) = self.icrc7_supply_cap {
-            if cap == self.icrc7_total_supply {
+    // id the token of `arg` gets when minted after the tokens up to `next_token_id`
+    fn assign_token_id(&self, next_token_id: u128, arg: &MintArg) -> Result<u128, MintError> {
+        match (arg.token_id, self.scalars.token_id_strategy) {
+            (Some(token_id), _) => Ok(token_id),
+            (None, TokenIdStrategy::Sequential) => Ok(next_token_id),
+            (None, TokenIdStrategy::CallerProvided) => Err(MintError::GenericError {
+                error_code: 8,
+                message: "Token Id Required".into(),
+            }),
+        }
+    }
+
+    // a Minter may mint for itself, and an operator with the Mint scope may mint for a Minter.
+    // An operator that is itself a Minter may mint on behalf of anyone within its scope
+    fn can_mint(&self, principal: &Principal, operator: Option<Principal>) -> bool {
+        self.has_role(principal, Role::Minter)
+            || operator.is_some_and(|operator| self.has_role(&operator, Role::Minter))
+    }
+
+    fn mock_mint(
+        &self,
+        caller: &Account,
+        operator: Option<Principal>,
+        token_id: u128,
+        arg: &MintArg,
+    ) -> Result<(), MintError> {
+        if let Some(cap) = self.scalars.icrc7_supply_cap {
+            if cap == self.scalars.icrc7_total_supply {
                 return Err(MintError::SupplyCapReached);
             }
         }
-        if self.minting_authority.is_none() {
+        if self.scalars.minting_authority.is_none() {
             return Err(MintError::GenericBatchError {
                 error_code: 6,
                 message: "Minting Authority Not Set".into(),
             });
         }
-        if Some(*caller) != self.minting_authority {
+        if !self.can_mint(&caller.owner, operator) {
             return Err(MintError::Unauthorized);
         }
         if let Some(ref memo) = arg.memo {
             let allowed_memo_length = self
+                .scalars
                 .icrc7_max_memo_size
                 .unwrap_or(State::DEFAULT_MAX_MEMO_SIZE);
             if memo.len() as u128 > allowed_memo_length {
@@ -479,51 +2052,400 @@ impl State {
                 });
             }
         }
-        if arg.token_id < self.next_token_id {
-            return Err(MintError::TokenIdMinimumLimit);
+        if let Some(created_at_time) = arg.created_at_time {
+            self.created_at_time_check(time(), created_at_time)?;
+            if let Some(duplicate_of) =
+                self.txn_deduplication_check(&DedupRequest::mint(caller, arg))
+            {
+                return Err(MintError::Duplicate { duplicate_of });
+            }
+        }
+        if arg.royalties.is_some_and(|royalty| !royalty.is_valid()) {
+            return Err(MintError::GenericError {
+                error_code: 10,
+                message: "Royalty Exceeds 10000 Basis Points".into(),
+            });
         }
-        if self.tokens.get(&arg.token_id).is_some() {
+        if arg
+            .valid_until
+            .is_some_and(|valid_until| valid_until <= time())
+        {
+            return Err(MintError::GenericError {
+                error_code: 11,
+                message: "Token Already Expired".into(),
+            });
+        }
+        if token_id < self.scalars.next_token_id {
+            return Err(MintError::TokenIdMinimumLimit);
+        }
+        if self.tokens.contains_key(&token_id) || self.burned_tokens.contains_key(&token_id) {
             return Err(MintError::TokenIdAlreadyExist);
         }
         Ok(())
     }
 
-    pub fn mint(&mut self, caller: &Principal, mut arg: MintArg) -> MintResult {
-        let caller = account_transformer(Account {
-            owner: *caller,
+    pub fn mint(&mut self, caller: &Actor, mut arg: MintArg) -> MintResult {
+        let caller_account = account_transformer(Account {
+            owner: caller.principal,
             subaccount: arg.from_subaccount,
         });
         arg.to = account_transformer(arg.to);
-        self.mock_mint(&caller, &arg)?;
-        let token_name = arg.token_name.unwrap_or_else(|| {
-            let name = format!("{} {}", self.icrc7_symbol, arg.token_id);
-            name
-        });
+        let token_id = self.assign_token_id(self.scalars.next_token_id, &arg)?;
+        self.mock_mint(&caller_account, caller.operator, token_id, &arg)?;
+        self.apply_mint(caller_account, token_id, arg, caller.operator);
+        Ok(token_id)
+    }
+
+    // mints a token whose arguments were validated by mock_mint
+    fn apply_mint(
+        &mut self,
+        caller: Account,
+        token_id: u128,
+        mut arg: MintArg,
+        operator: Option<Principal>,
+    ) {
+        let current_time = time();
+        let transferable = arg.transferable.unwrap_or(self.icrc7_transferable());
         let token = Icrc7Token::new(
-            arg.token_id,
-            token_name.clone(),
-            arg.token_description.clone(),
-            arg.token_logo,
+            token_id,
+            std::mem::take(&mut arg.metadata),
             arg.to,
+            transferable,
+            arg.royalties,
+            arg.valid_until,
         );
-        self.tokens.insert(arg.token_id, token);
-        self.next_token_id = arg.token_id + 1;
-        let txn_id = self.log_transaction(
+        self.save_token(token);
+        if let Some(valid_until) = arg.valid_until {
+            self.token_expiries.insert((valid_until, token_id), ());
+        }
+        self.scalars.next_token_id = token_id + 1;
+        self.scalars.icrc7_total_supply += 1;
+        let txn_id = self.log_operated_transaction(
             TransactionType::Mint {
-                tid: arg.token_id,
+                tid: token_id,
                 from: caller,
                 to: arg.to,
             },
-            ic_cdk::api::time(),
-            arg.memo,
+            current_time,
+            arg.memo.clone(),
+            operator,
         );
-        Ok(txn_id)
+        self.record_deduplication(&DedupRequest::mint(&caller, &arg), txn_id, current_time);
+    }
+
+    pub fn mint_batch(
+        &mut self,
+        caller: &Actor,
+        mut args: Vec<MintArg>,
+    ) -> Vec<Option<MintResult>> {
+        if args.is_empty() {
+            return vec![Some(Err(MintError::GenericBatchError {
+                error_code: 1,
+                message: "No Arguments Provided".into(),
+            }))];
+        }
+        let max_update_batch_size = self
+            .scalars
+            .icrc7_max_update_batch_size
+            .unwrap_or(State::DEFAULT_MAX_UPDATE_BATCH_SIZE);
+        let mut mint_results = vec![None; args.len()];
+        if args.len() as u128 > max_update_batch_size {
+            mint_results[0] = Some(Err(MintError::GenericBatchError {
+                error_code: 2,
+                message: "Exceed Max allowed Update Batch Size".into(),
+            }));
+            return mint_results;
+        }
+        // every item is checked against the state left by the valid items before it, so that
+        // ids must be increasing and the supply cap accounts for the whole batch
+        let mut next_token_id = self.scalars.next_token_id;
+        let mut total_supply = self.scalars.icrc7_total_supply;
+        let mut token_ids = vec![0; args.len()];
+        for (index, arg) in args.iter_mut().enumerate() {
+            let caller_account = account_transformer(Account {
+                owner: caller.principal,
+                subaccount: arg.from_subaccount,
+            });
+            arg.to = account_transformer(arg.to);
+            let result = self
+                .assign_token_id(next_token_id, arg)
+                .and_then(|token_id| {
+                    match self.mock_mint(&caller_account, caller.operator, token_id, arg) {
+                        Err(e) => Err(e),
+                        Ok(_) if token_id < next_token_id => Err(MintError::TokenIdMinimumLimit),
+                        Ok(_) if self.scalars.icrc7_supply_cap == Some(total_supply) => {
+                            Err(MintError::SupplyCapReached)
+                        }
+                        Ok(_) => Ok(token_id),
+                    }
+                });
+            match result {
+                Err(e) => mint_results[index] = Some(Err(e)),
+                Ok(token_id) => {
+                    // the validated id is kept so that the item is minted exactly as checked
+                    token_ids[index] = token_id;
+                    next_token_id = token_id + 1;
+                    total_supply += 1;
+                }
+            }
+        }
+        if let Some(true) = self.scalars.icrc7_atomic_batch_transfers {
+            if mint_results.iter().any(|res| res.is_some()) {
+                return mint_results;
+            }
+        }
+        for (index, arg) in args.into_iter().enumerate() {
+            if let Some(Err(e)) = &mint_results[index] {
+                match e {
+                    MintError::GenericBatchError { .. } => return mint_results,
+                    _ => continue,
+                }
+            }
+            let caller_account = account_transformer(Account {
+                owner: caller.principal,
+                subaccount: arg.from_subaccount,
+            });
+            self.apply_mint(caller_account, token_ids[index], arg, caller.operator);
+            mint_results[index] = Some(Ok(token_ids[index]));
+        }
+        mint_results
+    }
+
+    fn mock_update_collection(
+        &self,
+        arg: &UpdateCollectionArg,
+    ) -> Result<(), UpdateCollectionError> {
+        if let Some(supply_cap) = arg.icrc7_supply_cap {
+            if supply_cap < self.scalars.icrc7_total_supply {
+                return Err(UpdateCollectionError::SupplyCapBelowTotalSupply {
+                    total_supply: self.scalars.icrc7_total_supply,
+                });
+            }
+        }
+        if arg.icrc7_name.as_ref().is_some_and(|name| name.is_empty()) {
+            return Err(UpdateCollectionError::GenericError {
+                error_code: 1,
+                message: "Empty Collection Name".into(),
+            });
+        }
+        let limits = [
+            arg.icrc7_max_query_batch_size,
+            arg.icrc7_max_update_batch_size,
+            arg.icrc7_max_take_value,
+            arg.icrc7_default_take_value,
+        ];
+        if limits.contains(&Some(0)) {
+            return Err(UpdateCollectionError::GenericError {
+                error_code: 2,
+                message: "Limits Must Be Positive".into(),
+            });
+        }
+        let max_take_value = arg
+            .icrc7_max_take_value
+            .or(self.scalars.icrc7_max_take_value)
+            .unwrap_or(State::DEFAULT_MAX_TAKE_VALUE);
+        let default_take_value = arg
+            .icrc7_default_take_value
+            .or(self.scalars.icrc7_default_take_value)
+            .unwrap_or(State::DEFAULT_TAKE_VALUE);
+        if default_take_value > max_take_value {
+            return Err(UpdateCollectionError::GenericError {
+                error_code: 3,
+                message: "Default Take Value Exceeds Max Take Value".into(),
+            });
+        }
+        if arg
+            .icrc7_royalties
+            .is_some_and(|royalty| !royalty.is_valid())
+        {
+            return Err(UpdateCollectionError::GenericError {
+                error_code: 5,
+                message: "Royalty Exceeds 10000 Basis Points".into(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn update_collection(
+        &mut self,
+        caller: &Principal,
+        arg: UpdateCollectionArg,
+    ) -> UpdateCollectionResult {
+        self.mock_update_collection(&arg)?;
+        let mut changes: Vec<(String, Value)> = vec![];
+        if let Some(name) = arg.icrc7_name {
+            changes.push(("icrc7:name".into(), Value::text(&name)));
+            self.scalars.icrc7_name = name;
+        }
+        if let Some(description) = arg.icrc7_description {
+            changes.push(("icrc7:description".into(), Value::text(&description)));
+            self.scalars.icrc7_description = Some(description);
+        }
+        if let Some(logo) = arg.icrc7_logo {
+            changes.push(("icrc7:logo".into(), Value::text(&logo)));
+            self.scalars.icrc7_logo = Some(logo);
+        }
+        if let Some(supply_cap) = arg.icrc7_supply_cap {
+            changes.push(("icrc7:supply_cap".into(), Value::nat(supply_cap)));
+            self.scalars.icrc7_supply_cap = Some(supply_cap);
+        }
+        if let Some(size) = arg.icrc7_max_query_batch_size {
+            changes.push(("icrc7:max_query_batch_size".into(), Value::nat(size)));
+            self.scalars.icrc7_max_query_batch_size = Some(size);
+        }
+        if let Some(size) = arg.icrc7_max_update_batch_size {
+            changes.push(("icrc7:max_update_batch_size".into(), Value::nat(size)));
+            self.scalars.icrc7_max_update_batch_size = Some(size);
+        }
+        if let Some(take) = arg.icrc7_max_take_value {
+            changes.push(("icrc7:max_take_value".into(), Value::nat(take)));
+            self.scalars.icrc7_max_take_value = Some(take);
+        }
+        if let Some(take) = arg.icrc7_default_take_value {
+            changes.push(("icrc7:default_take_value".into(), Value::nat(take)));
+            self.scalars.icrc7_default_take_value = Some(take);
+        }
+        if let Some(size) = arg.icrc7_max_memo_size {
+            changes.push(("icrc7:max_memo_size".into(), Value::nat(size)));
+            self.scalars.icrc7_max_memo_size = Some(size);
+        }
+        if let Some(atomic) = arg.icrc7_atomic_batch_transfers {
+            changes.push(("icrc7:atomic_batch_transfers".into(), Value::text(atomic)));
+            self.scalars.icrc7_atomic_batch_transfers = Some(atomic);
+        }
+        if let Some(royalty) = arg.icrc7_royalties {
+            changes.extend(royalty.metadata());
+            self.scalars.icrc7_royalties = Some(royalty);
+        }
+        if let Some(tx_window) = arg.tx_window {
+            changes.push(("icrc7:tx_window".into(), Value::nat(tx_window)));
+            self.scalars.tx_window = Some(tx_window);
+        }
+        if let Some(permitted_drift) = arg.permitted_drift {
+            changes.push(("icrc7:permitted_drift".into(), Value::nat(permitted_drift)));
+            self.scalars.permitted_drift = Some(permitted_drift);
+        }
+        if changes.is_empty() {
+            return Err(UpdateCollectionError::GenericError {
+                error_code: 4,
+                message: "No Changes Provided".into(),
+            });
+        }
+        let from = account_transformer(Account {
+            owner: *caller,
+            subaccount: None,
+        });
+        Ok(self.log_transaction(
+            TransactionType::UpdateCollection { from, changes },
+            time(),
+            None,
+        ))
+    }
+
+    fn mock_update_token_metadata(
+        &self,
+        caller: &Account,
+        arg: &UpdateTokenMetadataArg,
+    ) -> Result<(), UpdateMetadataError> {
+        if !self.has_role(&caller.owner, Role::MetadataEditor) {
+            return Err(UpdateMetadataError::Unauthorized);
+        }
+        let (token_id, key, _) = arg;
+        if !self.tokens.contains_key(token_id) {
+            return Err(UpdateMetadataError::NonExistingTokenId);
+        }
+        // these are reported by icrc7_token_metadata but are not part of the stored metadata
+        if [
+            Icrc7Token::TRANSFERABLE_KEY,
+            Icrc7Token::VALID_UNTIL_KEY,
+            Icrc7Token::EXPIRED_KEY,
+            Royalty::BASIS_POINTS_KEY,
+            Royalty::RECIPIENT_KEY,
+        ]
+        .contains(&key.as_str())
+        {
+            return Err(UpdateMetadataError::GenericError {
+                error_code: 9,
+                message: "Reserved Metadata Key".into(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn update_token_metadata(
+        &mut self,
+        caller: &Actor,
+        args: Vec<UpdateTokenMetadataArg>,
+    ) -> Vec<Option<UpdateMetadataResult>> {
+        if args.is_empty() {
+            return vec![Some(Err(UpdateMetadataError::GenericBatchError {
+                error_code: 1,
+                message: "No Arguments Provided".into(),
+            }))];
+        }
+        let max_update_batch_size = self
+            .scalars
+            .icrc7_max_update_batch_size
+            .unwrap_or(State::DEFAULT_MAX_UPDATE_BATCH_SIZE);
+        let mut update_results = vec![None; args.len()];
+        if args.len() as u128 > max_update_batch_size {
+            update_results[0] = Some(Err(UpdateMetadataError::GenericBatchError {
+                error_code: 2,
+                message: "Exceed Max allowed Update Batch Size".into(),
+            }));
+            return update_results;
+        }
+        let caller_account = account_transformer(Account {
+            owner: caller.principal,
+            subaccount: None,
+        });
+        for (index, arg) in args.iter().enumerate() {
+            if let Err(e) = self.mock_update_token_metadata(&caller_account, arg) {
+                update_results[index] = Some(Err(e));
+            }
+        }
+        if let Some(true) = self.scalars.icrc7_atomic_batch_transfers {
+            if update_results.iter().any(|res| res.is_some()) {
+                return update_results;
+            }
+        }
+        let current_time = time();
+        for (index, (token_id, key, value)) in args.into_iter().enumerate() {
+            if update_results[index].is_some() {
+                continue;
+            }
+            let mut token = self.tokens.get(&token_id).unwrap();
+            token.set_metadata(key.clone(), value.clone());
+            self.save_token(token);
+            let txn_id = self.log_operated_transaction(
+                TransactionType::Update {
+                    tid: token_id,
+                    from: caller_account,
+                    key,
+                    value,
+                },
+                current_time,
+                None,
+                caller.operator,
+            );
+            update_results[index] = Some(Ok(txn_id));
+        }
+        update_results
     }
 
     fn mock_burn(&self, caller: &Account, arg: &BurnArg) -> Result<(), BurnError> {
+        if let Some(created_at_time) = arg.created_at_time {
+            self.created_at_time_check(time(), created_at_time)?;
+            if let Some(duplicate_of) =
+                self.txn_deduplication_check(&DedupRequest::burn(caller, arg))
+            {
+                return Err(BurnError::Duplicate { duplicate_of });
+            }
+        }
         if let Some(ref memo) = arg.memo {
             if memo.len() as u128
                 > self
+                    .scalars
                     .icrc7_max_memo_size
                     .unwrap_or(State::DEFAULT_MAX_MEMO_SIZE)
             {
@@ -551,7 +2473,18 @@ impl State {
                 message: "No Arguments Provided".into(),
             }))];
         }
+        let max_update_batch_size = self
+            .scalars
+            .icrc7_max_update_batch_size
+            .unwrap_or(State::DEFAULT_MAX_UPDATE_BATCH_SIZE);
         let mut txn_results = vec![None; args.len()];
+        if args.len() as u128 > max_update_batch_size {
+            txn_results[0] = Some(Err(BurnError::GenericBatchError {
+                error_code: 2,
+                message: "Exceed Max allowed Update Batch Size".into(),
+            }));
+            return txn_results;
+        }
         if *caller == Principal::anonymous() {
             txn_results[0] = Some(Err(BurnError::GenericBatchError {
                 error_code: 100,
@@ -565,10 +2498,10 @@ impl State {
                 subaccount: arg.from_subaccount,
             });
             if let Err(e) = self.mock_burn(&caller, arg) {
-                txn_results.insert(index, Some(Err(e)))
+                txn_results[index] = Some(Err(e))
             }
         }
-        if let Some(true) = self.icrc7_atomic_batch_transfers {
+        if let Some(true) = self.scalars.icrc7_atomic_batch_transfers {
             if txn_results
                 .iter()
                 .any(|res| res.is_some() && res.as_ref().unwrap().is_err())
@@ -581,7 +2514,6 @@ impl State {
                 owner: *caller,
                 subaccount: arg.from_subaccount,
             });
-            let burn_address = burn_account();
             if let Some(Err(e)) = txn_results.get(index).unwrap() {
                 match e {
                     BurnError::GenericBatchError {
@@ -591,28 +2523,132 @@ impl State {
                     _ => continue,
                 }
             }
-            let mut token = self.tokens.get(&arg.token_id).unwrap();
-            token.burn(burn_address);
-            let tid = self.log_transaction(
-                TransactionType::Burn {
-                    tid: arg.token_id,
-                    from: caller,
-                    to: burn_address,
-                },
-                ic_cdk::api::time(),
-                arg.memo.clone(),
-            );
-            txn_results.insert(index, Some(Ok(tid)))
+            // a token listed twice in the batch is gone by its second occurrence
+            txn_results[index] = match self.burn_token(arg.token_id, caller, arg.memo.clone()) {
+                Some(txn_id) => {
+                    self.record_deduplication(&DedupRequest::burn(&caller, arg), txn_id, time());
+                    Some(Ok(txn_id))
+                }
+                None => Some(Err(BurnError::NonExistingTokenId)),
+            };
         }
         txn_results
     }
 
+    // moves the token to the burned ones and logs the burn, None if the token does not exist
+    fn burn_token(&mut self, token_id: u128, from: Account, memo: Option<Vec<u8>>) -> Option<u128> {
+        let token = self.remove_token(token_id)?;
+        let burned_at = time();
+        let txn_id = self.log_transaction(
+            TransactionType::Burn {
+                tid: token_id,
+                from,
+                to: burn_account(),
+            },
+            burned_at,
+            memo,
+        );
+        self.burned_tokens.insert(
+            token_id,
+            BurnedToken {
+                token_id,
+                owner: token.token_owner,
+                metadata: token.metadata,
+                burned_at,
+                txn_id,
+            },
+        );
+        self.scalars.icrc7_total_supply -= 1;
+        Some(txn_id)
+    }
+
+    // burns, on behalf of their owners, up to a batch of the tokens expired at `current_time`
+    pub fn sweep_expired_tokens(&mut self, current_time: u64) -> Vec<u128> {
+        if self.jobs_suspended() {
+            return vec![];
+        }
+        let batch_size = self
+            .scalars
+            .icrc7_max_update_batch_size
+            .unwrap_or(State::DEFAULT_MAX_UPDATE_BATCH_SIZE);
+        let expired: Vec<u128> = self
+            .token_expiries
+            .range(..=(current_time, u128::MAX))
+            .take(batch_size as usize)
+            .map(|((_, token_id), _)| token_id)
+            .collect();
+        for token_id in expired.iter() {
+            if let Some(token) = self.tokens.get(token_id) {
+                self.burn_token(*token_id, token.token_owner, None);
+            }
+        }
+        expired
+    }
+
+    // None for the tokens that do not exist
+    pub fn icrc7_is_valid(&self, token_ids: &[u128]) -> Vec<Option<bool>> {
+        if token_ids.len() as u128
+            > self
+                .scalars
+                .icrc7_max_query_batch_size
+                .unwrap_or(State::DEFAULT_MAX_QUERY_BATCH_SIZE)
+        {
+            ic_cdk::trap("Exceeds Max Query Batch Size")
+        }
+        let current_time = time();
+        token_ids
+            .iter()
+            .map(|token_id| {
+                self.tokens
+                    .get(token_id)
+                    .map(|token| !token.is_expired(current_time))
+            })
+            .collect()
+    }
+
+    pub fn icrc7_burned_count(&self) -> u128 {
+        self.burned_tokens.len() as u128
+    }
+
+    // burned tokens in ascending id order, `prev` is the last id of the previous page
+    pub fn icrc7_burned_tokens(&self, prev: Option<u128>, take: Option<u128>) -> Vec<BurnedToken> {
+        let take = take.unwrap_or(State::DEFAULT_TAKE_VALUE);
+        if take
+            > self
+                .scalars
+                .icrc7_max_take_value
+                .unwrap_or(State::DEFAULT_MAX_TAKE_VALUE)
+        {
+            ic_cdk::trap("Exceeds Max Take Value")
+        }
+        let start = match prev {
+            None => 0,
+            Some(prev) => match prev.checked_add(1) {
+                Some(start) => start,
+                None => return vec![],
+            },
+        };
+        self.burned_tokens
+            .range(start..)
+            .take(take as usize)
+            .map(|(_, token)| token)
+            .collect()
+    }
+
     fn mock_approve(&self, caller: &Account, arg: &ApprovalArg) -> Result<(), ApprovalError> {
         if arg.spender == *caller {
             return Err(ApprovalError::InvalidSpender);
         };
+        if let Some(created_at_time) = arg.created_at_time {
+            self.created_at_time_check(time(), created_at_time)?;
+            let request = DedupRequest::approve(caller, arg);
+            if let Some(duplicate_of) = self.txn_deduplication_check(&request) {
+                return Err(ApprovalError::Duplicate { duplicate_of });
+            }
+        }
         if let Some(ref memo) = arg.memo {
             let max_memo_size = self
+                .scalars
                 .icrc7_max_memo_size
                 .unwrap_or(State::DEFAULT_MAX_MEMO_SIZE);
             if memo.len() as u128 > max_memo_size {
@@ -630,110 +2666,848 @@ impl State {
                         tokens_ids: vec![arg.token_id],
                     });
                 }
-                Ok(())
+                if !token.is_transferable() {
+                    return Err(ApprovalError::NonTransferable);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn approve(
+        &mut self,
+        caller: &Principal,
+        mut args: Vec<ApprovalArg>,
+    ) -> Vec<Option<ApproveResult>> {
+        if args.is_empty() {
+            return vec![Some(Err(ApprovalError::GenericBatchError {
+                error_code: 1,
+                message: "No Arguments Provided".into(),
+            }))];
+        }
+        let mut txn_results = vec![None; args.len()];
+        if *caller == Principal::anonymous() {
+            txn_results[0] = Some(Err(ApprovalError::GenericBatchError {
+                error_code: 100,
+                message: "Anonymous Identity".into(),
+            }));
+            return txn_results;
+        }
+        for (index, arg) in args.iter_mut().enumerate() {
+            let caller = account_transformer(Account {
+                owner: *caller,
+                subaccount: arg.from_subaccount,
+            });
+            if let Err(e) = self.mock_approve(&caller, arg) {
+                txn_results[index] = Some(Err(e));
+            }
+        }
+        if let Some(true) = self.scalars.icrc7_atomic_batch_transfers {
+            if txn_results
+                .iter()
+                .any(|res| res.is_some() && res.as_ref().unwrap().is_err())
+            {
+                return txn_results;
+            }
+        }
+        for (index, arg) in args.iter().enumerate() {
+            let caller = account_transformer(Account {
+                owner: *caller,
+                subaccount: arg.from_subaccount,
+            });
+            if let Some(Err(e)) = txn_results.get(index).unwrap() {
+                match e {
+                    &ApprovalError::GenericBatchError {
+                        error_code: _,
+                        message: _,
+                    } => return txn_results,
+                    _ => continue,
+                }
+            }
+            let mut token = self.tokens.get(&arg.token_id).unwrap();
+            let mut approve_arg = Approval::new(arg.spender, arg.expires_at);
+            approve_arg.memo = arg.memo.clone();
+            token.approve(approve_arg);
+            self.save_token(token);
+            let current_time = time();
+            let tid = self.log_transaction(
+                TransactionType::Approval {
+                    tid: arg.token_id,
+                    from: caller,
+                    to: arg.spender,
+                },
+                current_time,
+                arg.memo.clone(),
+            );
+            self.record_deduplication(&DedupRequest::approve(&caller, arg), tid, current_time);
+            txn_results[index] = Some(Ok(tid));
+        }
+        txn_results
+    }
+
+    fn collection_approval_check(
+        &self,
+        current_time: u64,
+        owner: &Account,
+        spender: &Account,
+    ) -> bool {
+        self.collection_approvals
+            .get(&CollectionApprovalKey {
+                owner: *owner,
+                spender: *spender,
+            })
+            .is_some_and(|approval| approval.is_active(current_time))
+    }
+
+    // a spender can move a token if it is approved either on the token itself or on the whole
+    // collection of the token owner
+    fn is_approved(&self, current_time: u64, token: &Icrc7Token, spender: &Account) -> bool {
+        token.approval_check(current_time, spender)
+            || self.collection_approval_check(current_time, &token.token_owner, spender)
+    }
+
+    fn owner_collection_approvals(
+        &self,
+        owner: &Account,
+    ) -> Vec<(CollectionApprovalKey, Approval)> {
+        let start = CollectionApprovalKey {
+            owner: *owner,
+            spender: Account {
+                owner: Principal::management_canister(),
+                subaccount: None,
+            },
+        };
+        self.collection_approvals
+            .range(start..)
+            .take_while(|(key, _)| key.owner == *owner)
+            .collect()
+    }
+
+    fn mock_approve_token(
+        &self,
+        current_time: &u64,
+        caller: &Account,
+        arg: &ApproveTokenArg,
+    ) -> Result<(), ApproveTokenError> {
+        let info = &arg.approval_info;
+        if account_transformer(info.spender) == *caller {
+            return Err(ApproveTokenError::InvalidSpender);
+        }
+        if self.exceeds_max_memo_size(&info.memo) {
+            return Err(ApproveTokenError::GenericError {
+                error_code: 3,
+                message: "Exceeds Max Memo Size".into(),
+            });
+        }
+        self.created_at_time_check(*current_time, info.created_at_time)?;
+        let request = DedupRequest::approve_token(caller, arg);
+        if let Some(duplicate_of) = self.txn_deduplication_check(&request) {
+            return Err(ApproveTokenError::Duplicate { duplicate_of });
+        }
+        let token = match self.tokens.get(&arg.token_id) {
+            None => return Err(ApproveTokenError::NonExistingTokenId),
+            Some(token) => token,
+        };
+        if token.token_owner != *caller {
+            return Err(ApproveTokenError::Unauthorized);
+        }
+        if !token.is_transferable() {
+            return Err(ApproveTokenError::NonTransferable);
+        }
+        let spender = account_transformer(info.spender);
+        if !token.approvals.iter().any(|a| a.account == spender)
+            && token.approvals.len() as u128 >= State::DEFAULT_MAX_APPROVALS_PER_TOKEN_OR_COLLECTION
+        {
+            return Err(ApproveTokenError::GenericError {
+                error_code: 4,
+                message: "Exceeds Max Approvals".into(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn icrc37_approve_tokens(
+        &mut self,
+        caller: &Principal,
+        args: Vec<ApproveTokenArg>,
+    ) -> Vec<Option<ApproveTokenResult>> {
+        let max_update_batch_size = self
+            .scalars
+            .icrc7_max_update_batch_size
+            .unwrap_or(State::DEFAULT_MAX_UPDATE_BATCH_SIZE);
+        if let Some((error_code, message)) =
+            self.batch_check(caller, args.len(), max_update_batch_size)
+        {
+            return vec![Some(Err(ApproveTokenError::GenericBatchError {
+                error_code,
+                message,
+            }))];
+        }
+        let current_time = time();
+        let mut txn_results = vec![None; args.len()];
+        for (index, arg) in args.iter().enumerate() {
+            let caller_account = account_transformer(Account {
+                owner: *caller,
+                subaccount: arg.approval_info.from_subaccount,
+            });
+            if let Err(e) = self.mock_approve_token(&current_time, &caller_account, arg) {
+                txn_results[index] = Some(Err(e));
+            }
+        }
+        if let Some(true) = self.scalars.icrc7_atomic_batch_transfers {
+            if txn_results.iter().any(|res| matches!(res, Some(Err(_)))) {
+                return txn_results;
+            }
+        }
+        for (index, arg) in args.into_iter().enumerate() {
+            if txn_results[index].is_some() {
+                continue;
+            }
+            let caller_account = account_transformer(Account {
+                owner: *caller,
+                subaccount: arg.approval_info.from_subaccount,
+            });
+            let info = &arg.approval_info;
+            let spender = account_transformer(info.spender);
+            let mut token = self.tokens.get(&arg.token_id).unwrap();
+            token.approve(Approval {
+                expires_at: info.expires_at,
+                account: spender,
+                memo: info.memo.clone(),
+                created_at_time: Some(info.created_at_time),
+            });
+            self.save_token(token);
+            let txn_id = self.log_transaction(
+                TransactionType::Approval {
+                    tid: arg.token_id,
+                    from: caller_account,
+                    to: spender,
+                },
+                current_time,
+                info.memo.clone(),
+            );
+            let request = DedupRequest::approve_token(&caller_account, &arg);
+            self.record_deduplication(&request, txn_id, current_time);
+            txn_results[index] = Some(Ok(txn_id));
+        }
+        txn_results
+    }
+
+    fn mock_approve_collection(
+        &self,
+        current_time: &u64,
+        caller: &Account,
+        arg: &ApproveCollectionArg,
+    ) -> Result<(), ApproveCollectionError> {
+        let info = &arg.approval_info;
+        let spender = account_transformer(info.spender);
+        if spender == *caller {
+            return Err(ApproveCollectionError::InvalidSpender);
+        }
+        if self.exceeds_max_memo_size(&info.memo) {
+            return Err(ApproveCollectionError::GenericError {
+                error_code: 3,
+                message: "Exceeds Max Memo Size".into(),
+            });
+        }
+        self.created_at_time_check(*current_time, info.created_at_time)?;
+        let request = DedupRequest::approve_collection(caller, arg);
+        if let Some(duplicate_of) = self.txn_deduplication_check(&request) {
+            return Err(ApproveCollectionError::Duplicate { duplicate_of });
+        }
+        let key = CollectionApprovalKey {
+            owner: *caller,
+            spender,
+        };
+        if !self.collection_approvals.contains_key(&key)
+            && self.owner_collection_approvals(caller).len() as u128
+                >= State::DEFAULT_MAX_APPROVALS_PER_TOKEN_OR_COLLECTION
+        {
+            return Err(ApproveCollectionError::GenericError {
+                error_code: 4,
+                message: "Exceeds Max Approvals".into(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn icrc37_approve_collection(
+        &mut self,
+        caller: &Principal,
+        args: Vec<ApproveCollectionArg>,
+    ) -> Vec<Option<ApproveCollectionResult>> {
+        let max_update_batch_size = self
+            .scalars
+            .icrc7_max_update_batch_size
+            .unwrap_or(State::DEFAULT_MAX_UPDATE_BATCH_SIZE);
+        if let Some((error_code, message)) =
+            self.batch_check(caller, args.len(), max_update_batch_size)
+        {
+            return vec![Some(Err(ApproveCollectionError::GenericBatchError {
+                error_code,
+                message,
+            }))];
+        }
+        let current_time = time();
+        let mut txn_results = vec![None; args.len()];
+        for (index, arg) in args.iter().enumerate() {
+            let caller_account = account_transformer(Account {
+                owner: *caller,
+                subaccount: arg.approval_info.from_subaccount,
+            });
+            if let Err(e) = self.mock_approve_collection(&current_time, &caller_account, arg) {
+                txn_results[index] = Some(Err(e));
+            }
+        }
+        if let Some(true) = self.scalars.icrc7_atomic_batch_transfers {
+            if txn_results.iter().any(|res| matches!(res, Some(Err(_)))) {
+                return txn_results;
+            }
+        }
+        for (index, arg) in args.into_iter().enumerate() {
+            if txn_results[index].is_some() {
+                continue;
+            }
+            let caller_account = account_transformer(Account {
+                owner: *caller,
+                subaccount: arg.approval_info.from_subaccount,
+            });
+            let info = &arg.approval_info;
+            let spender = account_transformer(info.spender);
+            self.collection_approvals.insert(
+                CollectionApprovalKey {
+                    owner: caller_account,
+                    spender,
+                },
+                Approval {
+                    expires_at: info.expires_at,
+                    account: spender,
+                    memo: info.memo.clone(),
+                    created_at_time: Some(info.created_at_time),
+                },
+            );
+            let txn_id = self.log_transaction(
+                TransactionType::CollectionApproval {
+                    from: caller_account,
+                    to: spender,
+                },
+                current_time,
+                info.memo.clone(),
+            );
+            let request = DedupRequest::approve_collection(&caller_account, &arg);
+            self.record_deduplication(&request, txn_id, current_time);
+            txn_results[index] = Some(Ok(txn_id));
+        }
+        txn_results
+    }
+
+    fn mock_revoke_token_approval(
+        &self,
+        current_time: &u64,
+        caller: &Account,
+        arg: &RevokeTokenApprovalArg,
+    ) -> Result<(), RevokeTokenApprovalError> {
+        if self.exceeds_max_memo_size(&arg.memo) {
+            return Err(RevokeTokenApprovalError::GenericError {
+                error_code: 3,
+                message: "Exceeds Max Memo Size".into(),
+            });
+        }
+        if let Some(time) = arg.created_at_time {
+            self.created_at_time_check(*current_time, time)?;
+        }
+        let token = match self.tokens.get(&arg.token_id) {
+            None => return Err(RevokeTokenApprovalError::NonExistingTokenId),
+            Some(token) => token,
+        };
+        if token.token_owner != *caller {
+            return Err(RevokeTokenApprovalError::Unauthorized);
+        }
+        let approval_exists = match arg.spender {
+            Some(spender) => {
+                let spender = account_transformer(spender);
+                token.approvals.iter().any(|a| a.account == spender)
+            }
+            None => !token.approvals.is_empty(),
+        };
+        if !approval_exists {
+            return Err(RevokeTokenApprovalError::ApprovalDoesNotExist);
+        }
+        Ok(())
+    }
+
+    pub fn icrc37_revoke_token_approvals(
+        &mut self,
+        caller: &Principal,
+        args: Vec<RevokeTokenApprovalArg>,
+    ) -> Vec<Option<RevokeTokenApprovalResult>> {
+        if let Some((error_code, message)) =
+            self.batch_check(caller, args.len(), State::DEFAULT_MAX_REVOKE_APPROVALS)
+        {
+            return vec![Some(Err(RevokeTokenApprovalError::GenericBatchError {
+                error_code,
+                message,
+            }))];
+        }
+        let current_time = time();
+        let mut txn_results = vec![None; args.len()];
+        for (index, arg) in args.iter().enumerate() {
+            let caller_account = account_transformer(Account {
+                owner: *caller,
+                subaccount: arg.from_subaccount,
+            });
+            if let Err(e) = self.mock_revoke_token_approval(&current_time, &caller_account, arg) {
+                txn_results[index] = Some(Err(e));
+            }
+        }
+        if let Some(true) = self.scalars.icrc7_atomic_batch_transfers {
+            if txn_results.iter().any(|res| matches!(res, Some(Err(_)))) {
+                return txn_results;
+            }
+        }
+        for (index, arg) in args.into_iter().enumerate() {
+            if txn_results[index].is_some() {
+                continue;
+            }
+            let caller_account = account_transformer(Account {
+                owner: *caller,
+                subaccount: arg.from_subaccount,
+            });
+            let spender = arg.spender.map(account_transformer);
+            let mut token = self.tokens.get(&arg.token_id).unwrap();
+            token.revoke(spender);
+            self.save_token(token);
+            let txn_id = self.log_transaction(
+                TransactionType::Revoke {
+                    tid: arg.token_id,
+                    from: caller_account,
+                    to: spender,
+                },
+                current_time,
+                arg.memo,
+            );
+            txn_results[index] = Some(Ok(txn_id));
+        }
+        txn_results
+    }
+
+    fn mock_revoke_collection_approval(
+        &self,
+        current_time: &u64,
+        caller: &Account,
+        arg: &RevokeCollectionApprovalArg,
+    ) -> Result<(), RevokeCollectionApprovalError> {
+        if self.exceeds_max_memo_size(&arg.memo) {
+            return Err(RevokeCollectionApprovalError::GenericError {
+                error_code: 3,
+                message: "Exceeds Max Memo Size".into(),
+            });
+        }
+        if let Some(time) = arg.created_at_time {
+            self.created_at_time_check(*current_time, time)?;
+        }
+        let approval_exists = match arg.spender {
+            Some(spender) => self
+                .collection_approvals
+                .contains_key(&CollectionApprovalKey {
+                    owner: *caller,
+                    spender: account_transformer(spender),
+                }),
+            None => !self.owner_collection_approvals(caller).is_empty(),
+        };
+        if !approval_exists {
+            return Err(RevokeCollectionApprovalError::ApprovalDoesNotExist);
+        }
+        Ok(())
+    }
+
+    pub fn icrc37_revoke_collection_approvals(
+        &mut self,
+        caller: &Principal,
+        args: Vec<RevokeCollectionApprovalArg>,
+    ) -> Vec<Option<RevokeCollectionApprovalResult>> {
+        if let Some((error_code, message)) =
+            self.batch_check(caller, args.len(), State::DEFAULT_MAX_REVOKE_APPROVALS)
+        {
+            return vec![Some(Err(
+                RevokeCollectionApprovalError::GenericBatchError {
+                    error_code,
+                    message,
+                },
+            ))];
+        }
+        let current_time = time();
+        let mut txn_results = vec![None; args.len()];
+        for (index, arg) in args.iter().enumerate() {
+            let caller_account = account_transformer(Account {
+                owner: *caller,
+                subaccount: arg.from_subaccount,
+            });
+            if let Err(e) =
+                self.mock_revoke_collection_approval(&current_time, &caller_account, arg)
+            {
+                txn_results[index] = Some(Err(e));
+            }
+        }
+        if let Some(true) = self.scalars.icrc7_atomic_batch_transfers {
+            if txn_results.iter().any(|res| matches!(res, Some(Err(_)))) {
+                return txn_results;
+            }
+        }
+        for (index, arg) in args.into_iter().enumerate() {
+            if txn_results[index].is_some() {
+                continue;
+            }
+            let caller_account = account_transformer(Account {
+                owner: *caller,
+                subaccount: arg.from_subaccount,
+            });
+            let spender = arg.spender.map(account_transformer);
+            match spender {
+                Some(spender) => {
+                    self.collection_approvals.remove(&CollectionApprovalKey {
+                        owner: caller_account,
+                        spender,
+                    });
+                }
+                None => {
+                    for (key, _) in self.owner_collection_approvals(&caller_account) {
+                        self.collection_approvals.remove(&key);
+                    }
+                }
             }
+            let txn_id = self.log_transaction(
+                TransactionType::CollectionRevoke {
+                    from: caller_account,
+                    to: spender,
+                },
+                current_time,
+                arg.memo,
+            );
+            txn_results[index] = Some(Ok(txn_id));
         }
+        txn_results
     }
 
-    pub fn approve(
+    fn mock_transfer_from(
+        &self,
+        current_time: &u64,
+        spender: &Account,
+        arg: &TransferFromArg,
+    ) -> Result<(), TransferFromError> {
+        if let Some(time) = arg.created_at_time {
+            self.created_at_time_check(*current_time, time)?;
+            let request = DedupRequest::transfer(spender, arg.token_id, &arg.to, &arg.memo, time);
+            if let Some(duplicate_of) = self.txn_deduplication_check(&request) {
+                return Err(TransferFromError::Duplicate { duplicate_of });
+            }
+        }
+        let token = match self.tokens.get(&arg.token_id) {
+            None => return Err(TransferFromError::NonExistingTokenId),
+            Some(token) => token,
+        };
+        if self.exceeds_max_memo_size(&arg.memo) {
+            return Err(TransferFromError::GenericError {
+                error_code: 3,
+                message: "Exceeds Max Memo Size".into(),
+            });
+        }
+        if arg.to == arg.from {
+            return Err(TransferFromError::InvalidRecipient);
+        }
+        if token.token_owner != arg.from {
+            return Err(TransferFromError::Unauthorized);
+        }
+        if !self.is_approved(*current_time, &token, spender) {
+            return Err(TransferFromError::Unauthorized);
+        }
+        if !token.is_transferable() {
+            return Err(TransferFromError::NonTransferable);
+        }
+        Ok(())
+    }
+
+    pub fn icrc37_transfer_from(
         &mut self,
         caller: &Principal,
-        mut args: Vec<ApprovalArg>,
-    ) -> Vec<Option<ApproveResult>> {
-        if args.is_empty() {
-            return vec![Some(Err(ApprovalError::GenericBatchError {
-                error_code: 1,
-                message: "No Arguments Provided".into(),
+        mut args: Vec<TransferFromArg>,
+    ) -> Vec<Option<TransferFromResult>> {
+        let max_update_batch_size = self
+            .scalars
+            .icrc7_max_update_batch_size
+            .unwrap_or(State::DEFAULT_MAX_UPDATE_BATCH_SIZE);
+        if let Some((error_code, message)) =
+            self.batch_check(caller, args.len(), max_update_batch_size)
+        {
+            return vec![Some(Err(TransferFromError::GenericBatchError {
+                error_code,
+                message,
             }))];
         }
+        let current_time = time();
         let mut txn_results = vec![None; args.len()];
-        if *caller == Principal::anonymous() {
-            txn_results[0] = Some(Err(ApprovalError::GenericBatchError {
-                error_code: 100,
-                message: "Anonymous Identity".into(),
-            }));
-            return txn_results;
-        }
+        let mut token_ids = HashSet::new();
         for (index, arg) in args.iter_mut().enumerate() {
-            let caller = account_transformer(Account {
+            let spender = account_transformer(Account {
                 owner: *caller,
-                subaccount: arg.from_subaccount,
+                subaccount: arg.spender_subaccount,
             });
-            if let Err(e) = self.mock_approve(&caller, arg) {
-                txn_results.insert(index, Some(Err(e)))
+            arg.from = account_transformer(arg.from);
+            arg.to = account_transformer(arg.to);
+            // items are checked against the state before the batch, so a token moves once
+            if !token_ids.insert(arg.token_id) {
+                txn_results[index] = Some(Err(TransferFromError::GenericError {
+                    error_code: 12,
+                    message: "Duplicate Token Id In Batch".into(),
+                }));
+            } else if let Err(e) = self.mock_transfer_from(&current_time, &spender, arg) {
+                txn_results[index] = Some(Err(e));
             }
         }
-        if let Some(true) = self.icrc7_atomic_batch_transfers {
-            if txn_results
-                .iter()
-                .any(|res| res.is_some() && res.as_ref().unwrap().is_err())
-            {
+        if let Some(true) = self.scalars.icrc7_atomic_batch_transfers {
+            if txn_results.iter().any(|res| matches!(res, Some(Err(_)))) {
                 return txn_results;
             }
         }
-        for (index, arg) in args.iter().enumerate() {
-            let caller = account_transformer(Account {
+        for (index, arg) in args.into_iter().enumerate() {
+            if txn_results[index].is_some() {
+                continue;
+            }
+            let spender = account_transformer(Account {
                 owner: *caller,
-                subaccount: arg.from_subaccount,
+                subaccount: arg.spender_subaccount,
             });
-            if let Some(Err(e)) = txn_results.get(index).unwrap() {
-                match e {
-                    &ApprovalError::GenericBatchError {
-                        error_code: _,
-                        message: _,
-                    } => return txn_results,
-                    _ => continue,
-                }
-            }
             let mut token = self.tokens.get(&arg.token_id).unwrap();
-            let approve_arg = Approval {
-                account: arg.spender,
-                expires_at: arg.expires_at,
-            };
-            token.approve(approve_arg);
-            self.tokens.insert((*arg).clone().token_id, token);
-            let tid = self.log_transaction(
-                TransactionType::Approval {
+            token.transfer(arg.to);
+            self.save_token(token);
+            let txn_id = self.log_transaction(
+                TransactionType::TransferFrom {
                     tid: arg.token_id,
-                    from: caller,
-                    to: arg.spender,
+                    from: arg.from,
+                    to: arg.to,
+                    spender,
                 },
-                ic_cdk::api::time(),
+                arg.created_at_time.unwrap_or(current_time),
                 arg.memo.clone(),
             );
-            txn_results.insert(index, Some(Ok(tid)))
+            if let Some(created_at_time) = arg.created_at_time {
+                let request = DedupRequest::transfer(
+                    &spender,
+                    arg.token_id,
+                    &arg.to,
+                    &arg.memo,
+                    created_at_time,
+                );
+                self.record_deduplication(&request, txn_id, current_time);
+            }
+            txn_results[index] = Some(Ok(txn_id));
         }
         txn_results
     }
 
-    pub fn icrc7_token_metadata(&self, token_ids: &[u128]) -> Vec<Option<Icrc7TokenMetadata>> {
-        if token_ids.len() as u128
+    pub fn icrc37_is_approved(&self, args: &[IsApprovedArg]) -> Vec<bool> {
+        if args.len() as u128
+            > self
+                .scalars
+                .icrc7_max_query_batch_size
+                .unwrap_or(State::DEFAULT_MAX_QUERY_BATCH_SIZE)
+        {
+            ic_cdk::trap("Exceeds Max Query Batch Size")
+        }
+        let current_time = time();
+        args.iter()
+            .map(|arg| match self.tokens.get(&arg.token_id) {
+                None => false,
+                Some(ref token) => {
+                    let from = Account {
+                        owner: token.token_owner.owner,
+                        subaccount: arg.from_subaccount,
+                    };
+                    token.token_owner == from
+                        && self.is_approved(current_time, token, &account_transformer(arg.spender))
+                }
+            })
+            .collect()
+    }
+
+    pub fn icrc37_get_token_approvals(
+        &self,
+        token_id: u128,
+        prev: Option<TokenApproval>,
+        take: Option<u128>,
+    ) -> Vec<TokenApproval> {
+        let take = take.unwrap_or(State::DEFAULT_TAKE_VALUE);
+        if take
+            > self
+                .scalars
+                .icrc7_max_take_value
+                .unwrap_or(State::DEFAULT_MAX_TAKE_VALUE)
+        {
+            ic_cdk::trap("Exceeds Max Take Value")
+        }
+        let token = match self.tokens.get(&token_id) {
+            None => return vec![],
+            Some(token) => token,
+        };
+        let current_time = time();
+        let mut approvals: Vec<&Approval> = token
+            .approvals
+            .iter()
+            .filter(|approval| approval.is_active(current_time))
+            .collect();
+        approvals.sort_by_key(|approval| approval.account);
+        approvals
+            .into_iter()
+            .filter(|approval| match prev {
+                Some(ref prev) => approval.account > prev.approval_info.spender,
+                None => true,
+            })
+            .take(take as usize)
+            .map(|approval| TokenApproval {
+                token_id,
+                approval_info: ApprovalInfo::new(&token.token_owner, approval),
+            })
+            .collect()
+    }
+
+    pub fn icrc37_get_collection_approvals(
+        &self,
+        owner: Account,
+        prev: Option<CollectionApproval>,
+        take: Option<u128>,
+    ) -> Vec<CollectionApproval> {
+        let take = take.unwrap_or(State::DEFAULT_TAKE_VALUE);
+        if take
+            > self
+                .scalars
+                .icrc7_max_take_value
+                .unwrap_or(State::DEFAULT_MAX_TAKE_VALUE)
+        {
+            ic_cdk::trap("Exceeds Max Take Value")
+        }
+        let owner = account_transformer(owner);
+        let current_time = time();
+        self.owner_collection_approvals(&owner)
+            .into_iter()
+            .filter(|(key, approval)| {
+                approval.is_active(current_time)
+                    && match prev {
+                        Some(ref prev) => key.spender > prev.spender,
+                        None => true,
+                    }
+            })
+            .take(take as usize)
+            .map(|(_, approval)| ApprovalInfo::new(&owner, &approval))
+            .collect()
+    }
+
+    fn check_query_batch_size(&self, len: usize) {
+        if len as u128
             > self
+                .scalars
                 .icrc7_max_query_batch_size
                 .unwrap_or(State::DEFAULT_MAX_QUERY_BATCH_SIZE)
         {
             ic_cdk::trap("Exceeds Max Query Batch Size")
         }
+    }
+
+    pub fn icrc7_token_metadata(&self, token_ids: &[u128]) -> Vec<Option<Icrc7TokenMetadata>> {
+        self.check_query_batch_size(token_ids.len());
+        // expiry depends on the time of the call, so it is left out of the certified metadata
+        let current_time = time();
         let mut metadata_list = vec![None; token_ids.len()];
         for (index, tid) in token_ids.iter().enumerate() {
             if let Some(ref token) = self.tokens.get(tid) {
-                metadata_list.insert(index, Some(token.token_metadata()))
+                let mut metadata = token.token_metadata();
+                if token.valid_until.is_some() {
+                    metadata.insert(
+                        Icrc7Token::EXPIRED_KEY.into(),
+                        Value::text(token.is_expired(current_time)),
+                    );
+                }
+                metadata_list[index] = Some(metadata)
             }
         }
         metadata_list
     }
 
-    pub fn icrc7_balance_of(&self, accounts: &[Account]) -> Vec<u128> {
-        let mut count_list = vec![0; accounts.len()];
-        accounts.iter().enumerate().for_each(|(index, account)| {
-            self.tokens.iter().for_each(|(_id, ref token)| {
-                if token.token_owner == *account {
-                    let current_count = count_list[index];
-                    count_list[index] = current_count + 1;
-                }
+    // certified queries are only answered in non-replicated query calls, where a certificate
+    // exists, and once the certified trees are rebuilt after an upgrade
+    pub fn icrc7_owner_of_certified(&self, token_ids: &[u128]) -> Option<CertifiedOwnerOfResponse> {
+        if !self.is_certified() {
+            return None;
+        }
+        let certificate = data_certificate()?;
+        let owners = self.icrc7_owner_of(token_ids);
+        let witness = self
+            .certified_trees
+            .owner_witness(self.tip_tree(), token_ids);
+        Some(CertifiedOwnerOfResponse {
+            owners,
+            certificate: DataCertificate {
+                certificate: ByteBuf::from(certificate),
+                hash_tree: encode_hash_tree(&witness),
+            },
+        })
+    }
+
+    pub fn icrc7_token_metadata_certified(
+        &self,
+        token_ids: &[u128],
+    ) -> Option<CertifiedTokenMetadataResponse> {
+        if !self.is_certified() {
+            return None;
+        }
+        let certificate = data_certificate()?;
+        self.check_query_batch_size(token_ids.len());
+        // the certified leaves hold the stored metadata, without the expiry computed at query time
+        let metadata = token_ids
+            .iter()
+            .map(|token_id| {
+                self.tokens
+                    .get(token_id)
+                    .map(|token| token.token_metadata())
             })
-        });
-        count_list
+            .collect();
+        let witness = self
+            .certified_trees
+            .metadata_witness(self.tip_tree(), token_ids);
+        Some(CertifiedTokenMetadataResponse {
+            metadata,
+            certificate: DataCertificate {
+                certificate: ByteBuf::from(certificate),
+                hash_tree: encode_hash_tree(&witness),
+            },
+        })
+    }
+
+    pub fn icrc7_balance_of_certified(
+        &self,
+        accounts: &[Account],
+    ) -> Option<CertifiedBalanceOfResponse> {
+        if !self.is_certified() {
+            return None;
+        }
+        let certificate = data_certificate()?;
+        let balances = self.icrc7_balance_of(accounts);
+        let witness = self
+            .certified_trees
+            .balance_witness(self.tip_tree(), accounts);
+        Some(CertifiedBalanceOfResponse {
+            balances,
+            certificate: DataCertificate {
+                certificate: ByteBuf::from(certificate),
+                hash_tree: encode_hash_tree(&witness),
+            },
+        })
+    }
+
+    pub fn icrc7_balance_of(&self, accounts: &[Account]) -> Vec<u128> {
+        accounts
+            .iter()
+            .map(|account| self.owned_tokens(*account, 0).count() as u128)
+            .collect()
     }
 
     pub fn icrc7_tokens(&self, prev: Option<u128>, take: Option<u128>) -> Vec<u128> {
@@ -741,20 +3515,18 @@ impl State {
         if take > State::DEFAULT_MAX_TAKE_VALUE {
             ic_cdk::trap("Exceeds Max Take Value")
         }
-        let mut list: Vec<u128> = self.tokens.iter().map(|(k, _)| k).collect();
-        list.sort();
-        match prev {
-            Some(prev) => match list.iter().position(|id| *id == prev) {
-                None => vec![],
-                Some(index) => list
-                    .iter()
-                    .copied()
-                    .skip(index)
-                    .take(take as usize)
-                    .collect(),
+        let start = match prev {
+            None => 0,
+            Some(prev) => match prev.checked_add(1) {
+                Some(start) => start,
+                None => return vec![],
             },
-            None => list[0..take as usize].to_vec(),
-        }
+        };
+        self.tokens
+            .range(start..)
+            .take(take as usize)
+            .map(|(token_id, _)| token_id)
+            .collect()
     }
 
     pub fn icrc7_tokens_of(
@@ -763,29 +3535,20 @@ impl State {
         prev: Option<u128>,
         take: Option<u128>,
     ) -> Vec<u128> {
-        let mut owned_tokens = vec![];
-        for (id, token) in self.tokens.iter() {
-            if token.token_owner == account {
-                owned_tokens.push(id);
-            }
-        }
-        let take = take.unwrap_or(owned_tokens.clone().len() as u128);
+        let take = take.unwrap_or(State::DEFAULT_TAKE_VALUE);
         if take > State::DEFAULT_MAX_TAKE_VALUE {
             ic_cdk::trap("Exceeds Max Take Value")
         }
-        owned_tokens.sort();
-        match prev {
-            None => owned_tokens[0..take as usize].to_vec(),
-            Some(prev) => match owned_tokens.iter().position(|id| *id == prev) {
-                None => vec![],
-                Some(index) => owned_tokens
-                    .iter()
-                    .copied()
-                    .skip(index)
-                    .take(take as usize)
-                    .collect(),
+        let start = match prev {
+            None => 0,
+            Some(prev) => match prev.checked_add(1) {
+                Some(start) => start,
+                None => return vec![],
             },
-        }
+        };
+        self.owned_tokens(account, start)
+            .take(take as usize)
+            .collect()
     }
 
     pub fn icrc7_txn_logs(&self, page_number: u32, page_size: u32) -> Vec<Transaction> {
@@ -804,6 +3567,136 @@ impl State {
         tx_logs
     }
 
+    // transactions with an id in [start, start + length), the ranges already moved to an
+    // archive are returned as callbacks to query from the archive canister
+    pub fn icrc7_txn_history(&self, start: u128, length: u128) -> TxnHistory {
+        let end = start
+            .saturating_add(length.min(State::MAX_TXN_HISTORY_LENGTH))
+            .min(self.scalars.txn_count.saturating_add(1));
+        let mut archived_txn_logs = vec![];
+        for (_, range) in self.archived_ranges.iter() {
+            let range_start = start.max(range.start);
+            let range_end = end.min(range.end + 1);
+            if range_start < range_end {
+                archived_txn_logs.push(ArchivedTxnLogs {
+                    start: range_start,
+                    length: range_end - range_start,
+                    callback: GetTxnLogsFn::new(range.canister_id, "get_txn_logs".into()),
+                });
+            }
+        }
+        let txn_logs = if start < end {
+            self.txn_log.range(start..end).map(|(_, txn)| txn).collect()
+        } else {
+            vec![]
+        };
+        TxnHistory {
+            txn_count: self.scalars.txn_count,
+            txn_logs,
+            archived_txn_logs,
+        }
+    }
+
+    // block ids are 0-based while txn ids start from 1, so block `n` is txn `n + 1`
+    pub fn icrc3_get_blocks(&self, args: GetBlocksArgs) -> GetBlocksResult {
+        let mut blocks = vec![];
+        let mut archived_blocks = vec![];
+        for arg in args {
+            let start = u128::try_from(&arg.start.0).unwrap_or(u128::MAX);
+            let length = u128::try_from(&arg.length.0).unwrap_or(u128::MAX);
+            let end = start.saturating_add(length).min(self.scalars.txn_count);
+            if start >= end {
+                continue;
+            }
+            for (_, range) in self.archived_ranges.iter() {
+                let range_start = start.max(range.start - 1);
+                let range_end = end.min(range.end);
+                if range_start < range_end {
+                    archived_blocks.push(ArchivedBlocks {
+                        args: vec![GetBlocksRequest {
+                            start: Nat::from(range_start),
+                            length: Nat::from(range_end - range_start),
+                        }],
+                        callback: GetBlocksFn::new(range.canister_id, "icrc3_get_blocks".into()),
+                    });
+                }
+            }
+            for (txn_id, txn) in self.txn_log.range(start + 1..=end) {
+                if blocks.len() as u128 >= State::MAX_BLOCKS_PER_RESPONSE {
+                    break;
+                }
+                blocks.push(BlockWithId {
+                    id: Nat::from(txn_id - 1),
+                    block: txn.block(),
+                });
+            }
+        }
+        GetBlocksResult {
+            log_length: Nat::from(self.scalars.txn_count),
+            blocks,
+            archived_blocks,
+        }
+    }
+
+    pub fn icrc3_get_archives(&self, args: GetArchivesArgs) -> GetArchivesResult {
+        let mut archives: Vec<Icrc3ArchiveInfo> = vec![];
+        for (_, range) in self.archived_ranges.iter() {
+            match archives
+                .iter_mut()
+                .find(|archive| archive.canister_id == range.canister_id)
+            {
+                Some(archive) => archive.end = Nat::from(range.end - 1),
+                None => archives.push(Icrc3ArchiveInfo {
+                    canister_id: range.canister_id,
+                    start: Nat::from(range.start - 1),
+                    end: Nat::from(range.end - 1),
+                }),
+            }
+        }
+        match args.from {
+            None => archives,
+            Some(from) => archives
+                .into_iter()
+                .skip_while(|archive| archive.canister_id != from)
+                .skip(1)
+                .collect(),
+        }
+    }
+
+    pub fn icrc3_get_tip_certificate(&self) -> Option<DataCertificate> {
+        let certificate = data_certificate()?;
+        let tree = self.certified_trees.tip_witness(self.tip_hash_tree()?);
+        Some(DataCertificate {
+            certificate: ByteBuf::from(certificate),
+            hash_tree: encode_hash_tree(&tree),
+        })
+    }
+
+    pub fn icrc3_supported_block_types(&self) -> Vec<SupportedBlockType> {
+        let icrc7_url = "https://github.com/dfinity/ICRC/ICRCs/ICRC-7";
+        let icrc37_url = "https://github.com/dfinity/ICRC/ICRCs/ICRC-37";
+        [
+            ("7mint", icrc7_url),
+            ("7burn", icrc7_url),
+            ("7xfer", icrc7_url),
+            ("37approve", icrc37_url),
+            ("37approve_coll", icrc37_url),
+            ("37revoke", icrc37_url),
+            ("37revoke_coll", icrc37_url),
+            ("37xfer", icrc37_url),
+            ("7update_token", icrc7_url),
+            ("7update_collection", icrc7_url),
+            ("37approve_expired", icrc37_url),
+            ("37approve_coll_expired", icrc37_url),
+        ]
+        .into_iter()
+        .map(|(block_type, url)| SupportedBlockType {
+            block_type: block_type.into(),
+            url: url.into(),
+        })
+        .collect()
+    }
+
     pub fn get_txn_logs(&self, size: usize) -> Vec<Transaction> {
         let tx_logs: Vec<Transaction> = self
             .txn_log
@@ -819,8 +3712,28 @@ impl State {
         for txn_id in txn_ids {
             self.txn_log.remove(txn_id);
         }
-        self.sync_pending_txn_ids = None;
-        self.archive_txn_count += txn_ids.len() as u128;
+        if let (Some(canister_id), Some(first), Some(last)) = (
+            self.scalars.archive_log_canister,
+            txn_ids.iter().min(),
+            txn_ids.iter().max(),
+        ) {
+            let range = match self.archived_ranges.last_key_value() {
+                Some((_, range)) if range.canister_id == canister_id && range.end + 1 == *first => {
+                    ArchivedTxnRange {
+                        end: *last,
+                        ..range
+                    }
+                }
+                _ => ArchivedTxnRange {
+                    canister_id,
+                    start: *first,
+                    end: *last,
+                },
+            };
+            self.archived_ranges.insert(range.start, range);
+        }
+        self.scalars.sync_pending_txn_ids = None;
+        self.scalars.archive_txn_count += txn_ids.len() as u128;
         true
     }
 
@@ -855,15 +3768,15 @@ impl State {
             ));
         };
 
-        let current_time = ic_cdk::api::time();
-        let canister_id = ic_cdk::api::id();
+        let current_time = time();
+        let canister_id = canister_id();
 
         let caller_account = account_transformer(Account {
             owner: *caller,
             subaccount: Some(*DEFAULT_SUBACCOUNT),
         });
 
-        let _from_account = match user_transformer(arg.from) {
+        let from_account = match user_transformer(arg.from) {
             Some(account) => account,
             None => {
                 return ExtTransferResult::Err(ExtTransferError::Other(
@@ -888,6 +3801,14 @@ impl State {
             Err(_) => return ExtTransferResult::Err(ExtTransferError::InvalidToken(arg.token)),
         };
 
+        if self
+            .tokens
+            .get(&token_id)
+            .is_some_and(|token| !token.is_transferable())
+        {
+            return ExtTransferResult::Err(ExtTransferError::NonTransferable);
+        }
+
         let icrc7_arg = TransferArg {
             from_subaccount: Some(*DEFAULT_SUBACCOUNT),
             to: to_account,
@@ -896,28 +3817,51 @@ impl State {
             created_at_time: Some(current_time),
         };
 
-        if self
-            .mock_transfer(&current_time, &caller_account, &icrc7_arg)
-            .is_err()
-        {
-            return ExtTransferResult::Err(ExtTransferError::Other(
-                "mock_transfer error".to_string(),
-            ));
-        }
+        // the owner moves its own token, anyone else must be an approved spender
+        let txn_type = if caller_account == from_account {
+            if self
+                .mock_transfer(&current_time, &caller_account, &icrc7_arg)
+                .is_err()
+            {
+                return ExtTransferResult::Err(ExtTransferError::Other(
+                    "mock_transfer error".to_string(),
+                ));
+            }
+            TransactionType::Transfer {
+                tid: icrc7_arg.token_id,
+                from: from_account,
+                to: icrc7_arg.to,
+            }
+        } else {
+            let transfer_from_arg = TransferFromArg {
+                spender_subaccount: Some(*DEFAULT_SUBACCOUNT),
+                from: from_account,
+                to: icrc7_arg.to,
+                token_id,
+                memo: icrc7_arg.memo.clone(),
+                created_at_time: icrc7_arg.created_at_time,
+            };
+            if self
+                .mock_transfer_from(&current_time, &caller_account, &transfer_from_arg)
+                .is_err()
+            {
+                return ExtTransferResult::Err(ExtTransferError::Unauthorized(
+                    AccountIdentifier::from_principal(caller, &Some(*DEFAULT_SUBACCOUNT)).to_hex(),
+                ));
+            }
+            TransactionType::TransferFrom {
+                tid: icrc7_arg.token_id,
+                from: from_account,
+                to: icrc7_arg.to,
+                spender: caller_account,
+            }
+        };
 
         let mut token = self.tokens.get(&icrc7_arg.token_id).unwrap();
         token.transfer(icrc7_arg.to);
         token.approvals.clear();
-        self.tokens.insert(icrc7_arg.token_id, token);
-        self.log_transaction(
-            TransactionType::Transfer {
-                tid: icrc7_arg.token_id,
-                from: caller_account,
-                to: icrc7_arg.to,
-            },
-            current_time,
-            Some(arg.memo.clone()),
-        );
+        self.save_token(token);
+        self.log_transaction(txn_type, current_time, Some(arg.memo.clone()));
         ExtTransferResult::Ok(arg.amount)
     }
 
@@ -930,8 +3874,8 @@ impl State {
             return false;
         };
 
-        let current_time = ic_cdk::api::time();
-        let canister_id = ic_cdk::api::id();
+        let current_time = time();
+        let canister_id = canister_id();
 
         let caller_account = account_transformer(Account {
             owner: *caller,
@@ -954,6 +3898,7 @@ impl State {
             token_id,
             expires_at: None,
             memo: None,
+            created_at_time: None,
         };
 
         if self.mock_approve(&caller_account, &icrc7_arg).is_err() {
@@ -961,12 +3906,9 @@ impl State {
         }
 
         let mut token = self.tokens.get(&icrc7_arg.token_id).unwrap();
-        let approve_arg = Approval {
-            account: icrc7_arg.spender,
-            expires_at: None,
-        };
+        let approve_arg = Approval::new(icrc7_arg.spender, None);
         token.approve(approve_arg);
-        self.tokens.insert(token_id, token);
+        self.save_token(token);
 
         self.log_transaction(
             TransactionType::Approval {
@@ -981,7 +3923,7 @@ impl State {
     }
 
     pub fn ext_balance(&self, arg: ExtBalanceArg) -> ExtBalanceResult {
-        let canister_id = ic_cdk::api::id();
+        let canister_id = canister_id();
 
         let token_id = match arg.token.parse_token_index(canister_id) {
             Ok(token_id) => token_id,
@@ -1009,8 +3951,8 @@ impl State {
     }
 
     pub fn ext_allowance(&self, arg: ExtAllowanceArg) -> ExtAllowanceResult {
-        let canister_id = ic_cdk::api::id();
-        let current_time = ic_cdk::api::time();
+        let canister_id = canister_id();
+        let current_time = time();
 
         let token_id = match arg.token.parse_token_index(canister_id) {
             Ok(token_id) => token_id,
@@ -1036,7 +3978,7 @@ impl State {
             return ExtAllowanceResult::Err(ExtCommonError::Other("Invalid owner".to_string()));
         }
 
-        if token.approval_check(current_time, &to_account) {
+        if self.is_approved(current_time, &token, &to_account) {
             ExtAllowanceResult::Ok(1)
         } else {
             ExtAllowanceResult::Ok(0)
@@ -1044,7 +3986,7 @@ impl State {
     }
 
     pub fn ext_bearer(&self, token: TokenIdentifier) -> ExtBearerResult {
-        let canister_id = ic_cdk::api::id();
+        let canister_id = canister_id();
 
         let token_id = match token.parse_token_index(canister_id) {
             Ok(token_id) => token_id,
@@ -1067,7 +4009,7 @@ impl State {
     }
 
     pub fn ext_metadata(&self, token: TokenIdentifier) -> ExtMetadataResult {
-        let canister_id = ic_cdk::api::id();
+        let canister_id = canister_id();
 
         let token_id = match token.parse_token_index(canister_id) {
             Ok(token_id) => token_id,
@@ -1077,9 +4019,7 @@ impl State {
         let token = self.tokens.get(&token_id);
 
         if let Some(token_info) = token {
-            let metadata = token_info
-                .token_description
-                .unwrap_or_else(|| String::from(""));
+            let metadata = token_info.description().unwrap_or_else(|| String::from(""));
 
             ExtMetadataResult::Ok(ExtMetadata::Nonfungible(ExtMetadataType::new(metadata)))
         } else {
@@ -1087,8 +4027,13 @@ impl State {
         }
     }
 
-    pub fn ext_update_metadata(&mut self, token: TokenIdentifier, description: String) -> bool {
-        let canister_id = ic_cdk::api::id();
+    pub fn ext_update_metadata(
+        &mut self,
+        caller: &Principal,
+        token: TokenIdentifier,
+        description: String,
+    ) -> bool {
+        let canister_id = canister_id();
 
         let token_id = match token.parse_token_index(canister_id) {
             Ok(token_id) => token_id,
@@ -1098,8 +4043,22 @@ impl State {
         let token = self.tokens.get(&token_id);
 
         if let Some(mut token_info) = token {
-            token_info.token_description = Some(description);
-            self.tokens.insert(token_id, token_info);
+            token_info.set_description(description.clone());
+            self.save_token(token_info);
+            let caller_account = account_transformer(Account {
+                owner: *caller,
+                subaccount: None,
+            });
+            self.log_transaction(
+                TransactionType::Update {
+                    tid: token_id,
+                    from: caller_account,
+                    key: Icrc7Token::DESCRIPTION_KEY.into(),
+                    value: Some(Value::Text(description)),
+                },
+                time(),
+                None,
+            );
             true
         } else {
             false
@@ -1129,52 +4088,56 @@ impl State {
             None => return 0,
         };
 
-        let token_id = self.next_token_id;
+        let token_id = self.scalars.next_token_id;
 
-        let token_description = ext_arg
-            .metadata
-            .map(|bytes| String::from_utf8_lossy(&bytes).to_string());
+        let mut metadata = Icrc7TokenMetadata::new();
+        if let Some(bytes) = ext_arg.metadata {
+            metadata.insert(
+                Icrc7Token::DESCRIPTION_KEY.into(),
+                Value::Text(String::from_utf8_lossy(&bytes).to_string()),
+            );
+        }
 
         let arg = MintArg {
             from_subaccount: None,
             to: to_account,
-            token_id,
+            token_id: Some(token_id),
             memo: None,
-            token_name: None,
-            token_description,
-            token_logo: None,
+            metadata,
+            transferable: None,
+            royalties: None,
+            valid_until: None,
+            created_at_time: None,
         };
 
-        match self.mock_mint(&caller, &arg) {
+        match self.mock_mint(&caller, None, token_id, &arg) {
             Ok(_) => (),
             Err(_) => {
                 return 0;
             }
         }
 
-        let token_name = arg.token_name.unwrap_or_else(|| {
-            let name = format!("{} {}", self.icrc7_symbol, arg.token_id);
-            name
-        });
         let token = Icrc7Token::new(
-            arg.token_id,
-            token_name.clone(),
-            arg.token_description.clone(),
-            arg.token_logo,
+            token_id,
+            arg.metadata,
             arg.to,
+            self.icrc7_transferable(),
+            None,
+            None,
         );
-        self.tokens.insert(arg.token_id, token);
-        self.next_token_id = arg.token_id + 1;
+        self.save_token(token);
+        self.scalars.next_token_id = token_id + 1;
+        self.scalars.icrc7_total_supply += 1;
         self.log_transaction(
             TransactionType::Mint {
-                tid: arg.token_id,
+                tid: token_id,
                 from: caller,
                 to: arg.to,
             },
-            ic_cdk::api::time(),
+            time(),
             arg.memo,
         );
-        arg.token_id as u32
+        token_id as u32
     }
 
     pub fn ext_batch_mint(
@@ -1202,10 +4165,7 @@ impl State {
         if !token_indexs.is_empty() {
             self.tokens.iter().for_each(|(id, ref token)| {
                 if token_indexs.contains(&(id as u32)) {
-                    let metadata = token
-                        .token_description
-                        .clone()
-                        .unwrap_or_else(|| String::from(""));
+                    let metadata = token.description().unwrap_or_else(|| String::from(""));
                     token_list.push((
                         id as u32,
                         ExtMetadata::Nonfungible(ExtMetadataType::new(metadata)),
@@ -1220,10 +4180,7 @@ impl State {
     pub fn ext_get_tokens(&self) -> Vec<(ExtTokenIndex, ExtMetadata)> {
         let mut token_list = vec![];
         self.tokens.iter().for_each(|(id, ref token)| {
-            let metadata = token
-                .token_description
-                .clone()
-                .unwrap_or_else(|| String::from(""));
+            let metadata = token.description().unwrap_or_else(|| String::from(""));
             token_list.push((
                 id as u32,
                 ExtMetadata::Nonfungible(ExtMetadataType::new(metadata)),
@@ -1248,20 +4205,60 @@ thread_local! {
     pub static STATE: RefCell<State> = RefCell::default();
 }
 
+// runs an update against the state, then writes the scalars back to their stable cell
+pub fn mutate_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        let result = f(&mut s);
+        s.save_scalars();
+        result
+    })
+}
+
+// moves the oldest batch of the local log to the archive canister
+pub async fn archive_logs() -> SyncReceipt {
+    let archive_log_canister = STATE
+        .with(|s| s.borrow().get_archive_log_canister())
+        .ok_or(InsertTransactionError::NotSetArchiveCanister)?;
+
+    // check sync pending
+    let sync_pending_txn_ids = STATE.with(|s| s.borrow().get_sync_pending_txn_ids());
+    if sync_pending_txn_ids.is_some() {
+        return Err(InsertTransactionError::SyncPending);
+    }
+
+    let txn_logs: Vec<Transaction> = STATE.with(|s| {
+        let s = s.borrow();
+        s.get_txn_logs(s.archive_batch_size() as usize)
+    });
+
+    let txn_ids: Vec<u128> = txn_logs.iter().map(|log| log.txn_id).collect();
+
+    // set pending
+    mutate_state(|s| s.set_sync_pending_txn_ids(Some(txn_ids.clone())));
+
+    // remote call logs sync
+    let call_result = call_sync_logs(archive_log_canister, txn_logs).await;
+
+    match call_result {
+        Ok(count) => {
+            mutate_state(|s| s.remove_txn_logs(&txn_ids));
+            Ok(count)
+        }
+        Err(e) => {
+            mutate_state(|s| s.set_sync_pending_txn_ids(None));
+            Err(e)
+        }
+    }
+}
+
 pub async fn call_sync_logs(
     archive_log_canister: Principal,
     txn_logs: Vec<Transaction>,
 ) -> SyncReceipt {
     // sync logs
-    let call_result: Result<(SyncReceipt,), _> = ic_cdk::api::call::call(
-        archive_log_canister,
-        "insert_many_txn_log",
-        (txn_logs.clone(),),
-    )
-    .await;
-
-    match call_result {
-        Ok(_) => Ok(txn_logs.len() as u32),
-        Err((_rejection_code, _msg)) => Err(InsertTransactionError::RemoteError),
+    match insert_many_txn_log(archive_log_canister, txn_logs).await {
+        Ok(receipt) => receipt,
+        Err(_rejection) => Err(InsertTransactionError::RemoteError),
     }
 }
@@ -0,0 +1,1773 @@
+use std::{
+    borrow::Cow,
+    future::Future,
+    task::{Context, Poll, Waker},
+};
+
+use candid::{CandidType, Encode, Int, Principal};
+use ciborium::value::Value as CborValue;
+use ic_stable_structures::{memory_manager::MemoryId, writer::Writer, StableBTreeMap, Storable};
+use icrc7_common::archive::state::STATE as ARCHIVE;
+use icrc_ledger_types::icrc1::account::Account;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    certification::{account_key, metadata_value, token_key},
+    errors::InsertTransactionError,
+    errors::{
+        ApprovalError, ApproveCollectionError, ApproveTokenError, BurnError, MintError,
+        SnapshotError, TransferError, TransferFromError,
+    },
+    ext_types::TokenIdentifier,
+    guards::not_anonymous_caller,
+    icrc37_types::{ApprovalInfo, ApproveCollectionArg, ApproveTokenArg, TransferFromArg},
+    icrc3_types::{BlockWithId, GetArchivesArgs, GetBlocksRequest, Value},
+    icrc7_types::{
+        Actor, ArchivedTxnRange, BurnArg, Icrc7TokenMetadata, InitArg, MintArg, OperatorAssignment,
+        OperatorScope, Role, RoleAssignment, Royalty, RoyaltyInfo, SnapshotChunk, Transaction,
+        TransactionType, TransferArg,
+    },
+    memory::{get_upgrades_memory, Memory},
+    query_method,
+    schema::{run_migrations, SCHEMA_VERSION},
+    state::{archive_logs, mutate_state, Icrc7Token, Scalars, State, MEMORY_MANAGER, STATE},
+    utils::{
+        account_transformer, canister_id, certified_data, set_archive, set_caller, set_time, time,
+    },
+    Approval, ApprovalArg,
+};
+
+const MINTER: u8 = 100;
+
+fn principal(id: u8) -> Principal {
+    Principal::from_slice(&[id])
+}
+
+fn actor(id: u8) -> Actor {
+    principal(id).into()
+}
+
+fn account(id: u8) -> Account {
+    account_transformer(Account {
+        owner: principal(id),
+        subaccount: None,
+    })
+}
+
+fn collection() -> State {
+    let mut state = State::default();
+    state.scalars.minting_authority = Some(account(MINTER));
+    state.init_roles();
+    state
+}
+
+fn mint_arg(to: u8) -> MintArg {
+    MintArg {
+        from_subaccount: None,
+        to: account(to),
+        token_id: None,
+        memo: None,
+        metadata: Icrc7TokenMetadata::new(),
+        transferable: None,
+        royalties: None,
+        valid_until: None,
+        created_at_time: None,
+    }
+}
+
+fn mint(state: &mut State, to: u8) -> Result<u128, MintError> {
+    state.mint(&actor(MINTER), mint_arg(to))
+}
+
+fn burn(state: &mut State, caller: u8, token_ids: &[u128]) -> Vec<Result<u128, BurnError>> {
+    let args = token_ids
+        .iter()
+        .map(|token_id| BurnArg {
+            from_subaccount: None,
+            token_id: *token_id,
+            memo: None,
+            created_at_time: None,
+        })
+        .collect();
+    state
+        .burn(&principal(caller), args)
+        .into_iter()
+        .map(|result| result.expect("every burn gets a result"))
+        .collect()
+}
+
+#[test]
+fn mint_tracks_total_supply() {
+    let mut state = collection();
+    for expected_id in 0..3 {
+        assert!(matches!(mint(&mut state, 1), Ok(id) if id == expected_id));
+    }
+
+    assert_eq!(state.icrc7_total_supply(), 3);
+    assert_eq!(state.icrc7_tokens(None, None), vec![0, 1, 2]);
+    assert_eq!(state.icrc7_balance_of(&[account(1)]), vec![3]);
+}
+
+#[test]
+fn supply_cap_is_enforced() {
+    let mut state = collection();
+    state.scalars.icrc7_supply_cap = Some(2);
+    assert!(mint(&mut state, 1).is_ok());
+    assert!(mint(&mut state, 2).is_ok());
+
+    assert!(matches!(
+        mint(&mut state, 3),
+        Err(MintError::SupplyCapReached)
+    ));
+    assert_eq!(state.icrc7_total_supply(), 2);
+}
+
+// the second item asks for the id already taken by the first one
+fn batch_with_a_taken_id() -> Vec<MintArg> {
+    let mut taken = mint_arg(2);
+    taken.token_id = Some(0);
+    vec![mint_arg(1), taken, mint_arg(3)]
+}
+
+#[test]
+fn atomic_mint_batches_mint_all_or_nothing() {
+    let mut state = collection();
+    state.scalars.icrc7_atomic_batch_transfers = Some(true);
+    let results = state.mint_batch(&actor(MINTER), batch_with_a_taken_id());
+
+    assert!(matches!(
+        results[..],
+        [None, Some(Err(MintError::TokenIdMinimumLimit)), None]
+    ));
+    assert_eq!(state.icrc7_total_supply(), 0);
+    assert!(state.icrc7_tokens(None, None).is_empty());
+    assert_eq!(state.scalars.next_token_id, 0);
+    assert_eq!(state.scalars.txn_count, 0);
+    assert!(state.txn_log.is_empty());
+}
+
+#[test]
+fn best_effort_mint_batches_skip_invalid_items() {
+    let mut state = collection();
+    let results = state.mint_batch(&actor(MINTER), batch_with_a_taken_id());
+
+    assert!(matches!(
+        results[..],
+        [
+            Some(Ok(0)),
+            Some(Err(MintError::TokenIdMinimumLimit)),
+            Some(Ok(1))
+        ]
+    ));
+    assert_eq!(state.icrc7_total_supply(), 2);
+    assert_eq!(
+        state.icrc7_owner_of(&[0, 1]),
+        vec![Some(account(1)), Some(account(3))]
+    );
+    assert_eq!(state.txn_log.len(), 2);
+}
+
+#[test]
+fn burned_tokens_are_tombstoned() {
+    let mut state = collection();
+    mint(&mut state, 1).unwrap();
+    mint(&mut state, 1).unwrap();
+
+    let results = burn(&mut state, 1, &[0]);
+    assert!(matches!(results[..], [Ok(_)]));
+
+    assert_eq!(state.icrc7_owner_of(&[0, 1]), vec![None, Some(account(1))]);
+    assert!(state.icrc7_token_metadata(&[0])[0].is_none());
+    assert_eq!(state.icrc7_balance_of(&[account(1)]), vec![1]);
+    assert_eq!(state.icrc7_tokens(None, None), vec![1]);
+    assert_eq!(state.icrc7_tokens_of(account(1), None, None), vec![1]);
+    assert_eq!(state.icrc7_total_supply(), 1);
+    assert_eq!(state.icrc7_burned_count(), 1);
+
+    let burned = state.icrc7_burned_tokens(None, None);
+    assert_eq!(burned.len(), 1);
+    assert_eq!(burned[0].token_id, 0);
+    assert_eq!(burned[0].owner, account(1));
+    assert!(matches!(results[0], Ok(txn_id) if txn_id == burned[0].txn_id));
+    assert!(state.icrc7_burned_tokens(Some(0), None).is_empty());
+}
+
+#[test]
+fn token_pages_start_after_prev() {
+    let mut state = collection();
+    for to in [1, 2, 1, 1] {
+        mint(&mut state, to).unwrap();
+    }
+    assert_eq!(state.icrc7_tokens(Some(0), Some(2)), vec![1, 2]);
+    assert_eq!(state.icrc7_tokens_of(account(1), Some(0), None), vec![2, 3]);
+
+    // the last token of a page may be gone by the time the next page is read
+    burn(&mut state, 1, &[2]);
+
+    assert_eq!(state.icrc7_tokens(Some(2), None), vec![3]);
+    assert_eq!(state.icrc7_tokens_of(account(1), Some(2), None), vec![3]);
+    assert_eq!(state.icrc7_tokens_of(account(1), Some(1), None), vec![3]);
+    assert!(state.icrc7_tokens(Some(u128::MAX), None).is_empty());
+}
+
+#[test]
+fn burn_results_keep_their_position() {
+    let mut state = collection();
+    mint(&mut state, 1).unwrap();
+    mint(&mut state, 2).unwrap();
+
+    let results = burn(&mut state, 1, &[1, 0]);
+
+    assert!(matches!(results[..], [Err(BurnError::Unauthorized), Ok(_)]));
+    assert_eq!(state.icrc7_total_supply(), 1);
+}
+
+#[test]
+fn token_is_burned_only_once() {
+    let mut state = collection();
+    mint(&mut state, 1).unwrap();
+
+    let results = burn(&mut state, 1, &[0, 0]);
+    assert!(matches!(
+        results[..],
+        [Ok(_), Err(BurnError::NonExistingTokenId)]
+    ));
+
+    let results = burn(&mut state, 1, &[0]);
+    assert!(matches!(results[..], [Err(BurnError::NonExistingTokenId)]));
+    assert_eq!(state.icrc7_total_supply(), 0);
+    assert_eq!(state.icrc7_burned_count(), 1);
+}
+
+#[test]
+fn burned_ids_are_not_reused() {
+    let mut state = collection();
+    mint(&mut state, 1).unwrap();
+    burn(&mut state, 1, &[0]);
+
+    assert!(matches!(mint(&mut state, 1), Ok(1)));
+    let reminted = state.mint(
+        &actor(MINTER),
+        MintArg {
+            token_id: Some(0),
+            ..mint_arg(1)
+        },
+    );
+    assert!(reminted.is_err());
+}
+
+#[test]
+fn burning_frees_room_under_the_supply_cap() {
+    let mut state = collection();
+    state.scalars.icrc7_supply_cap = Some(1);
+    mint(&mut state, 1).unwrap();
+    burn(&mut state, 1, &[0]);
+
+    assert!(mint(&mut state, 1).is_ok());
+    assert_eq!(state.icrc7_total_supply(), 1);
+}
+
+#[test]
+fn supply_is_recounted_on_upgrade() {
+    let mut state = collection();
+    mint(&mut state, 1).unwrap();
+    mint(&mut state, 2).unwrap();
+    state.scalars.icrc7_total_supply = 0;
+
+    state.recount_supply();
+
+    assert_eq!(state.icrc7_total_supply(), 2);
+}
+
+#[test]
+fn token_royalties_override_the_collection() {
+    let mut state = collection();
+    state.scalars.icrc7_royalties = Some(Royalty {
+        recipient: account(50),
+        basis_points: 250,
+    });
+    mint(&mut state, 1).unwrap();
+    let token_royalty = Royalty {
+        recipient: account(51),
+        basis_points: 1_000,
+    };
+    state
+        .mint(
+            &actor(MINTER),
+            MintArg {
+                royalties: Some(token_royalty),
+                ..mint_arg(1)
+            },
+        )
+        .unwrap();
+
+    assert_eq!(
+        state.royalty_info(0, 1_999),
+        Some(RoyaltyInfo {
+            recipient: account(50),
+            amount: 49,
+        })
+    );
+    assert_eq!(
+        state.royalty_info(1, u128::MAX),
+        Some(RoyaltyInfo {
+            recipient: account(51),
+            amount: u128::MAX / 10,
+        })
+    );
+    assert_eq!(state.royalty_info(2, 1_000), None);
+}
+
+#[test]
+fn royalties_are_capped_at_the_full_price() {
+    let mut state = collection();
+    let arg = MintArg {
+        royalties: Some(Royalty {
+            recipient: account(50),
+            basis_points: 10_001,
+        }),
+        ..mint_arg(1)
+    };
+
+    assert!(matches!(
+        state.mint(&actor(MINTER), arg),
+        Err(MintError::GenericError { error_code: 10, .. })
+    ));
+}
+
+#[test]
+fn expired_tokens_are_reported() {
+    let mut state = collection();
+    let valid_until = time() + 10;
+    state
+        .mint(
+            &actor(MINTER),
+            MintArg {
+                valid_until: Some(valid_until),
+                ..mint_arg(1)
+            },
+        )
+        .unwrap();
+    mint(&mut state, 1).unwrap();
+
+    assert_eq!(
+        state.icrc7_is_valid(&[0, 1, 2]),
+        vec![Some(true), Some(true), None]
+    );
+    set_time(valid_until);
+    assert_eq!(state.icrc7_is_valid(&[0, 1]), vec![Some(false), Some(true)]);
+    let metadata = state.icrc7_token_metadata(&[0])[0].clone().unwrap();
+    assert!(metadata.contains_key("icrc7:valid_until"));
+    assert!(
+        matches!(metadata.get("icrc7:expired"), Some(Value::Text(expired)) if expired == "true")
+    );
+
+    let arg = MintArg {
+        valid_until: Some(valid_until),
+        ..mint_arg(1)
+    };
+    assert!(matches!(
+        state.mint(&actor(MINTER), arg),
+        Err(MintError::GenericError { error_code: 11, .. })
+    ));
+}
+
+#[test]
+fn sweeper_burns_expired_tokens() {
+    let mut state = collection();
+    let now = time();
+    for valid_until in [now + 20, now + 10, now + 30] {
+        state
+            .mint(
+                &actor(MINTER),
+                MintArg {
+                    valid_until: Some(valid_until),
+                    ..mint_arg(1)
+                },
+            )
+            .unwrap();
+    }
+
+    assert!(state.sweep_expired_tokens(now).is_empty());
+    // nothing is burned while the collection is paused
+    state.scalars.paused = true;
+    let txn_count = state.scalars.txn_count;
+    assert!(state.sweep_expired_tokens(now + 20).is_empty());
+    assert_eq!(state.icrc7_total_supply(), 3);
+    assert_eq!(state.scalars.txn_count, txn_count);
+    state.scalars.paused = false;
+    assert_eq!(state.sweep_expired_tokens(now + 20), vec![1, 0]);
+
+    assert_eq!(state.icrc7_tokens(None, None), vec![2]);
+    assert_eq!(state.icrc7_total_supply(), 1);
+    let burned = state.icrc7_burned_tokens(None, None);
+    assert_eq!(burned.len(), 2);
+    assert!(burned.iter().all(|token| token.owner == account(1)));
+    assert!(state.sweep_expired_tokens(now + 20).is_empty());
+}
+
+#[test]
+fn approval_sweeper_prunes_expired_approvals() {
+    let mut state = collection();
+    state.scalars.approval_sweep_batch_size = Some(2);
+    for _ in 0..3 {
+        mint(&mut state, 1).unwrap();
+    }
+    let now = time();
+    let args = [(0, 2, Some(now + 10)), (0, 3, None), (2, 2, Some(now + 10))]
+        .into_iter()
+        .map(|(token_id, spender, expires_at)| ApprovalArg {
+            token_id,
+            memo: None,
+            from_subaccount: None,
+            expires_at,
+            spender: account(spender),
+            created_at_time: None,
+        })
+        .collect();
+    assert!(state
+        .approve(&principal(1), args)
+        .iter()
+        .all(|result| matches!(result, Some(Ok(_)))));
+    let txn_count = state.scalars.txn_count;
+
+    assert_eq!(state.sweep_expired_approvals(now + 20), 1);
+    assert_eq!(state.icrc7_approval_sweep_status().next_token_id, Some(2));
+    assert_eq!(state.sweep_expired_approvals(now + 20), 1);
+
+    let status = state.icrc7_approval_sweep_status();
+    assert_eq!(status.next_token_id, None);
+    assert_eq!(status.pruned_approvals, 2);
+    assert_eq!(status.completed_passes, 1);
+    assert_eq!(status.last_swept_at, Some(now + 20));
+    assert_eq!(state.scalars.txn_count, txn_count + 2);
+    let approvals = state.tokens.get(&0).unwrap().approvals;
+    assert_eq!(approvals.len(), 1);
+    assert_eq!(approvals[0].account, account(3));
+}
+
+#[test]
+fn approval_sweeper_prunes_expired_collection_approvals() {
+    let mut state = collection();
+    state.scalars.approval_sweep_batch_size = Some(2);
+    let now = time();
+    for (owner, expires_at) in [(1, Some(now + 10)), (2, None), (3, Some(now + 10))] {
+        let arg = ApproveCollectionArg {
+            approval_info: ApprovalInfo {
+                expires_at,
+                ..approval_info(5)
+            },
+        };
+        let results = state.icrc37_approve_collection(&principal(owner), vec![arg]);
+        assert!(matches!(results[..], [Some(Ok(_))]));
+    }
+    let txn_count = state.scalars.txn_count;
+
+    assert_eq!(state.sweep_expired_approvals(now + 20), 1);
+    assert!(state
+        .icrc7_approval_sweep_status()
+        .next_collection_approval
+        .is_some());
+    assert_eq!(state.sweep_expired_approvals(now + 20), 1);
+
+    let status = state.icrc7_approval_sweep_status();
+    assert_eq!(status.next_collection_approval, None);
+    assert_eq!(status.pruned_approvals, 2);
+    assert_eq!(state.scalars.txn_count, txn_count + 2);
+    let txn = state.txn_log.get(&state.scalars.txn_count).unwrap();
+    assert_eq!(txn.txn_type.btype(), "37approve_coll_expired");
+    assert_eq!(state.collection_approvals.len(), 1);
+    assert_eq!(
+        state
+            .icrc37_get_collection_approvals(account(2), None, None)
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn idle_approval_sweeps_leave_the_stored_scalars_alone() {
+    let mut state = collection();
+    mint(&mut state, 1).unwrap();
+    state.save_scalars();
+
+    state.run_approval_sweep(time());
+
+    assert_eq!(state.scalars.approval_sweep_last_run_at, Some(time()));
+    assert_eq!(State::default().scalars.approval_sweep_last_run_at, None);
+
+    let results = state.icrc37_approve_collection(
+        &principal(1),
+        vec![ApproveCollectionArg {
+            approval_info: ApprovalInfo {
+                expires_at: Some(time() + 10),
+                ..approval_info(5)
+            },
+        }],
+    );
+    assert!(matches!(results[..], [Some(Ok(_))]));
+    state.run_approval_sweep(time() + 20);
+
+    assert_eq!(State::default().scalars.approval_sweep_pruned, 1);
+}
+
+fn transfer_arg(token_id: u128, to: u8, created_at_time: u64) -> TransferArg {
+    TransferArg {
+        from_subaccount: None,
+        to: account(to),
+        token_id,
+        memo: None,
+        created_at_time: Some(created_at_time),
+    }
+}
+
+#[test]
+fn duplicate_requests_are_rejected() {
+    let mut state = collection();
+    let now = time();
+    let arg = MintArg {
+        created_at_time: Some(now),
+        ..mint_arg(1)
+    };
+    let minted = state.mint(&actor(MINTER), arg.clone()).unwrap();
+    let mint_txn = state.scalars.txn_count;
+    assert!(matches!(
+        state.mint(&actor(MINTER), arg),
+        Err(MintError::Duplicate { duplicate_of }) if duplicate_of == mint_txn
+    ));
+
+    let results = state.icrc7_transfer(&actor(1), vec![transfer_arg(minted, 2, now)]);
+    let transfer_txn = state.scalars.txn_count;
+    assert!(matches!(results[..], [Some(Ok(txn_id))] if txn_id == transfer_txn));
+    let results = state.icrc7_transfer(&actor(1), vec![transfer_arg(minted, 2, now)]);
+    assert!(matches!(
+        results[..],
+        [Some(Err(TransferError::Duplicate { duplicate_of }))] if duplicate_of == transfer_txn
+    ));
+    // another creation time makes it a new request
+    let results = state.icrc7_transfer(&actor(2), vec![transfer_arg(minted, 1, now + 1)]);
+    assert!(matches!(results[..], [Some(Ok(_))]));
+}
+
+fn approval_info(spender: u8) -> ApprovalInfo {
+    ApprovalInfo {
+        spender: account(spender),
+        from_subaccount: None,
+        expires_at: None,
+        memo: None,
+        created_at_time: time(),
+    }
+}
+
+#[test]
+fn repeated_token_ids_move_a_token_once_per_batch() {
+    let mut state = collection();
+    let token_id = mint(&mut state, 1).unwrap();
+    let arg = |to| TransferArg {
+        created_at_time: None,
+        ..transfer_arg(token_id, to, time())
+    };
+    let txn_count = state.scalars.txn_count;
+    let results = state.icrc7_transfer(&actor(1), vec![arg(2), arg(3)]);
+    assert!(matches!(results[0], Some(Ok(_))));
+    assert!(matches!(
+        results[1],
+        Some(Err(TransferError::GenericError { error_code: 12, .. }))
+    ));
+    assert_eq!(state.icrc7_owner_of(&[token_id]), vec![Some(account(2))]);
+    assert_eq!(state.scalars.txn_count, txn_count + 1);
+
+    let approval = ApproveTokenArg {
+        token_id,
+        approval_info: approval_info(6),
+    };
+    let results = state.icrc37_approve_tokens(&principal(2), vec![approval]);
+    assert!(matches!(results[..], [Some(Ok(_))]));
+    let arg = |to| TransferFromArg {
+        spender_subaccount: None,
+        from: account(2),
+        to: account(to),
+        token_id,
+        memo: None,
+        created_at_time: None,
+    };
+    let txn_count = state.scalars.txn_count;
+    let results = state.icrc37_transfer_from(&principal(6), vec![arg(3), arg(5)]);
+    assert!(matches!(results[0], Some(Ok(_))));
+    assert!(matches!(
+        results[1],
+        Some(Err(TransferFromError::GenericError { error_code: 12, .. }))
+    ));
+    assert_eq!(state.icrc7_owner_of(&[token_id]), vec![Some(account(3))]);
+    assert_eq!(state.scalars.txn_count, txn_count + 1);
+}
+
+#[test]
+fn duplicate_icrc37_approvals_are_rejected() {
+    let mut state = collection();
+    let token_id = mint(&mut state, 1).unwrap();
+    let arg = ApproveTokenArg {
+        token_id,
+        approval_info: approval_info(2),
+    };
+    let results = state.icrc37_approve_tokens(&principal(1), vec![arg.clone()]);
+    let approve_txn = state.scalars.txn_count;
+    assert!(matches!(results[..], [Some(Ok(txn_id))] if txn_id == approve_txn));
+    let results = state.icrc37_approve_tokens(&principal(1), vec![arg]);
+    assert!(matches!(
+        results[..],
+        [Some(Err(ApproveTokenError::Duplicate { duplicate_of }))] if duplicate_of == approve_txn
+    ));
+
+    let arg = ApproveCollectionArg {
+        approval_info: approval_info(2),
+    };
+    let results = state.icrc37_approve_collection(&principal(1), vec![arg.clone()]);
+    let approve_txn = state.scalars.txn_count;
+    assert!(matches!(results[..], [Some(Ok(txn_id))] if txn_id == approve_txn));
+    let results = state.icrc37_approve_collection(&principal(1), vec![arg]);
+    assert!(matches!(
+        results[..],
+        [Some(Err(ApproveCollectionError::Duplicate { duplicate_of }))]
+            if duplicate_of == approve_txn
+    ));
+    assert_eq!(state.scalars.txn_count, approve_txn);
+}
+
+#[test]
+fn ext_metadata_updates_are_logged_for_the_caller() {
+    let mut state = collection();
+    let token_id = mint(&mut state, 1).unwrap();
+    state.grant_role(principal(6), Role::MetadataEditor);
+    let token = TokenIdentifier::parse_token_identifier(canister_id(), token_id);
+
+    assert!(state.ext_update_metadata(&principal(6), token, "updated".into()));
+
+    let txn = state.txn_log.get(&state.scalars.txn_count).unwrap();
+    assert!(matches!(
+        txn.txn_type,
+        TransactionType::Update { tid, from, value: Some(Value::Text(ref text)), .. }
+            if tid == token_id && from == account(6) && text == "updated"
+    ));
+}
+
+#[test]
+fn soulbound_tokens_are_neither_transferred_nor_approved() {
+    let mut state = collection();
+    let arg = MintArg {
+        transferable: Some(false),
+        ..mint_arg(1)
+    };
+    let token_id = state.mint(&actor(MINTER), arg).unwrap();
+    let txn_count = state.scalars.txn_count;
+
+    let transfer = TransferArg {
+        created_at_time: None,
+        ..transfer_arg(token_id, 2, time())
+    };
+    let results = state.icrc7_transfer(&actor(1), vec![transfer]);
+    assert!(matches!(
+        results[..],
+        [Some(Err(TransferError::NonTransferable))]
+    ));
+
+    let approval = ApproveTokenArg {
+        token_id,
+        approval_info: approval_info(6),
+    };
+    let results = state.icrc37_approve_tokens(&principal(1), vec![approval]);
+    assert!(matches!(
+        results[..],
+        [Some(Err(ApproveTokenError::NonTransferable))]
+    ));
+
+    let approval = ApprovalArg {
+        token_id,
+        memo: None,
+        from_subaccount: None,
+        expires_at: None,
+        spender: account(6),
+        created_at_time: None,
+    };
+    let results = state.approve(&principal(1), vec![approval]);
+    assert!(matches!(
+        results[..],
+        [Some(Err(ApprovalError::NonTransferable))]
+    ));
+
+    assert_eq!(state.icrc7_owner_of(&[token_id]), vec![Some(account(1))]);
+    assert!(state.tokens.get(&token_id).unwrap().approvals.is_empty());
+    assert_eq!(state.scalars.txn_count, txn_count);
+}
+
+#[test]
+fn deduplication_entries_are_pruned_out_of_the_window() {
+    let mut state = collection();
+    let now = time();
+    for created_at_time in [now, now + 1] {
+        let arg = MintArg {
+            created_at_time: Some(created_at_time),
+            ..mint_arg(1)
+        };
+        state.mint(&actor(MINTER), arg).unwrap();
+    }
+    assert_eq!(state.dedup_txns.len(), 2);
+
+    let later = now + State::DEFAULT_TX_WINDOW + State::DEFAULT_PERMITTED_DRIFT + 1;
+    set_time(later);
+    let arg = MintArg {
+        created_at_time: Some(later),
+        ..mint_arg(1)
+    };
+    state.mint(&actor(MINTER), arg).unwrap();
+
+    assert_eq!(state.dedup_txns.len(), 2);
+    assert_eq!(state.dedup_expiries.len(), 2);
+    assert_eq!(
+        state.dedup_expiries.first_key_value().unwrap().0 .0,
+        now + 1
+    );
+}
+
+#[test]
+fn minting_requires_the_minter_role() {
+    let mut state = collection();
+    assert!(matches!(
+        state.mint(&actor(2), mint_arg(1)),
+        Err(MintError::Unauthorized)
+    ));
+
+    assert!(state.grant_role(principal(2), Role::Minter));
+    assert!(!state.grant_role(principal(2), Role::Minter));
+    assert!(state.mint(&actor(2), mint_arg(1)).is_ok());
+
+    assert!(state.revoke_role(principal(2), Role::Minter));
+    assert!(!state.revoke_role(principal(2), Role::Minter));
+    assert!(matches!(
+        state.mint(&actor(2), mint_arg(1)),
+        Err(MintError::Unauthorized)
+    ));
+}
+
+#[test]
+fn last_admin_is_not_revoked() {
+    let mut state = collection();
+    assert!(!state.revoke_role(principal(MINTER), Role::Admin));
+
+    state.grant_role(principal(2), Role::Admin);
+    assert!(state.revoke_role(principal(MINTER), Role::Admin));
+    assert!(!state.revoke_role(principal(2), Role::Admin));
+}
+
+#[test]
+fn metadata_editors_are_migrated_to_roles() {
+    let mut state = State::default();
+    state.scalars.minting_authority = Some(account(MINTER));
+    state.scalars.metadata_editors = vec![account(2)];
+
+    state.migrate_roles();
+
+    assert!(state.scalars.metadata_editors.is_empty());
+    assert_eq!(
+        state.icrc7_list_roles(),
+        vec![
+            RoleAssignment {
+                principal: principal(2),
+                roles: vec![Role::MetadataEditor],
+            },
+            RoleAssignment {
+                principal: principal(MINTER),
+                roles: Role::ALL.to_vec(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn trusted_callers_are_replaced() {
+    let mut state = collection();
+    state.set_trusted_callers(vec![principal(1), principal(2)]);
+    assert!(state.is_trusted_caller(&principal(1)));
+
+    state.set_trusted_callers(vec![principal(3)]);
+    state.add_trusted_callers(vec![principal(4)]);
+    state.remove_trusted_callers(vec![principal(4)]);
+
+    assert_eq!(state.icrc7_trusted_callers(), vec![principal(3)]);
+}
+
+#[test]
+fn operators_act_only_within_their_scopes() {
+    const OPERATOR: u8 = 50;
+    let mut state = collection();
+    assert_eq!(
+        state.actor(principal(1), None, OperatorScope::Transfer),
+        Some(actor(1))
+    );
+    assert_eq!(
+        state.actor(
+            principal(OPERATOR),
+            Some(principal(1)),
+            OperatorScope::Transfer
+        ),
+        None
+    );
+
+    state.grant_operator_scope(principal(OPERATOR), OperatorScope::Transfer);
+
+    let operated = Actor {
+        principal: principal(1),
+        operator: Some(principal(OPERATOR)),
+    };
+    assert_eq!(
+        state.actor(
+            principal(OPERATOR),
+            Some(principal(1)),
+            OperatorScope::Transfer
+        ),
+        Some(operated)
+    );
+    assert_eq!(
+        state.actor(principal(OPERATOR), Some(principal(1)), OperatorScope::Mint),
+        None
+    );
+    let token_id = mint(&mut state, 1).unwrap();
+    let arg = TransferArg {
+        created_at_time: None,
+        ..transfer_arg(token_id, 2, time())
+    };
+    let results = state.icrc7_transfer(&operated, vec![arg]);
+    assert!(matches!(results[..], [Some(Ok(_))]));
+    assert_eq!(state.icrc7_owner_of(&[token_id]), vec![Some(account(2))]);
+    let txn = state.txn_log.get(&state.scalars.txn_count).unwrap();
+    assert_eq!(txn.operator, Some(principal(OPERATOR)));
+    assert!(matches!(txn.txn_type, TransactionType::Transfer { from, .. } if from == account(1)));
+}
+
+#[test]
+fn scalars_are_reloaded_from_their_cell() {
+    let mut state = collection();
+    mint(&mut state, 1).unwrap();
+    state.scalars.icrc7_name = "Stakes".into();
+    state.save_scalars();
+
+    let reloaded = State::default();
+
+    assert_eq!(reloaded.icrc7_name(), "Stakes");
+    assert_eq!(reloaded.icrc7_total_supply(), 1);
+    assert_eq!(reloaded.scalars.txn_count, state.scalars.txn_count);
+    assert_eq!(reloaded.scalars.minting_authority, Some(account(MINTER)));
+}
+
+#[test]
+fn legacy_serialized_state_is_migrated_once() {
+    let legacy = Scalars {
+        icrc7_name: "Legacy".into(),
+        txn_count: 7,
+        minting_authority: Some(account(MINTER)),
+        ..Scalars::default()
+    };
+    let mut state_bytes = vec![];
+    ciborium::ser::into_writer(&legacy, &mut state_bytes).unwrap();
+    let mut memory = get_upgrades_memory();
+    let mut writer = Writer::new(&mut memory, 0);
+    writer
+        .write(&(state_bytes.len() as u32).to_le_bytes())
+        .unwrap();
+    writer.write(&state_bytes).unwrap();
+
+    let mut state = State::default();
+    state.migrate_legacy_state();
+    assert_eq!(state.icrc7_name(), "Legacy");
+    assert_eq!(state.scalars.txn_count, 7);
+
+    // the legacy bytes are only read once, later changes are kept in the cell
+    state.scalars.icrc7_name = "Renamed".into();
+    state.save_scalars();
+    let mut state = State::default();
+    state.migrate_legacy_state();
+    assert_eq!(state.icrc7_name(), "Renamed");
+}
+
+// token layout written before the metadata map and the versioned envelope existed
+#[derive(CandidType)]
+struct BaselineToken {
+    token_id: u128,
+    token_name: String,
+    token_description: Option<String>,
+    token_logo: Option<String>,
+    token_owner: Account,
+    approvals: Vec<Approval>,
+}
+
+#[derive(CandidType)]
+enum BaselineTransactionType {
+    Mint {
+        tid: u128,
+        from: Account,
+        to: Account,
+    },
+}
+
+// transaction layout written before the block hashes and the operators existed
+#[derive(CandidType)]
+struct BaselineTransaction {
+    at: u64,
+    txn_id: u128,
+    op: String,
+    txn_type: BaselineTransactionType,
+    memo: Option<Vec<u8>>,
+}
+
+fn baseline_token(token_id: u128, owner: u8) -> Vec<u8> {
+    Encode!(&BaselineToken {
+        token_id,
+        token_name: format!("Stake #{token_id}"),
+        token_description: Some("Baseline".into()),
+        token_logo: None,
+        token_owner: account(owner),
+        approvals: vec![],
+    })
+    .unwrap()
+}
+
+fn baseline_transaction(txn_id: u128, token_id: u128, to: u8) -> Vec<u8> {
+    Encode!(&BaselineTransaction {
+        at: time(),
+        txn_id,
+        op: "7mint".into(),
+        txn_type: BaselineTransactionType::Mint {
+            tid: token_id,
+            from: account(MINTER),
+            to: account(to),
+        },
+        memo: None,
+    })
+    .unwrap()
+}
+
+fn raw_map(id: u8) -> StableBTreeMap<u128, Vec<u8>, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(id))))
+}
+
+#[test]
+fn unversioned_fixtures_are_decoded() {
+    let token = Icrc7Token::from_bytes(Cow::Owned(baseline_token(1, 2)));
+    assert_eq!(token.token_owner, account(2));
+    assert_eq!(
+        token.metadata.get(Icrc7Token::NAME_KEY),
+        Some(&Value::Text("Stake #1".into()))
+    );
+    assert_eq!(token.transferable, None);
+
+    let txn = Transaction::from_bytes(Cow::Owned(baseline_transaction(1, 1, 2)));
+    assert_eq!(txn.phash, None);
+    assert_eq!(txn.operator, None);
+    assert!(matches!(txn.txn_type, TransactionType::Mint { tid: 1, to, .. } if to == account(2)));
+
+    // unversioned values in the current layout are read as well
+    let current = Icrc7Token::from_bytes(Cow::Owned(Encode!(&token).unwrap()));
+    assert_eq!(current.metadata, token.metadata);
+
+    let enveloped = token.to_bytes();
+    assert!(enveloped.starts_with(b"SSV"));
+    assert_eq!(Icrc7Token::from_bytes(enveloped).token_id, 1);
+}
+
+#[test]
+fn migrations_upgrade_fixtures_once() {
+    let mut tokens = raw_map(1);
+    tokens.insert(1, baseline_token(1, 2));
+    tokens.insert(2, baseline_token(2, 3));
+    let mut txn_log = raw_map(2);
+    txn_log.insert(1, baseline_transaction(1, 1, 2));
+    txn_log.insert(2, baseline_transaction(2, 2, 3));
+
+    let mut state = State::default();
+    state.scalars.minting_authority = Some(account(MINTER));
+    state.scalars.metadata_editors = vec![account(4)];
+    state.scalars.txn_count = 2;
+    state.scalars.archived_ranges = vec![ArchivedTxnRange {
+        canister_id: principal(200),
+        start: 1,
+        end: 1,
+    }];
+    assert_eq!(state.schema_version(), 0);
+
+    assert_eq!(run_migrations(&mut state), vec![1, 2, 4, 5]);
+    assert_eq!(state.schema_version(), SCHEMA_VERSION);
+    assert_eq!(state.icrc7_tokens_of(account(3), None, None), vec![2]);
+    assert!(state.has_role(&principal(4), Role::MetadataEditor));
+    // the fixtures are left as they are until they are written again
+    assert!(raw_map(1)
+        .iter()
+        .all(|(_, bytes)| !bytes.starts_with(b"SSV")));
+    assert_eq!(state.txn_log.get(&2).unwrap().txn_id, 2);
+    let arg = TransferArg {
+        created_at_time: None,
+        ..transfer_arg(2, 4, time())
+    };
+    assert!(matches!(
+        state.icrc7_transfer(&actor(3), vec![arg])[..],
+        [Some(Ok(_))]
+    ));
+    assert!(raw_map(1).get(&2).unwrap().starts_with(b"SSV"));
+    assert!(!raw_map(1).get(&1).unwrap().starts_with(b"SSV"));
+    assert!(raw_map(2).get(&3).unwrap().starts_with(b"SSV"));
+    assert!(!raw_map(2).get(&1).unwrap().starts_with(b"SSV"));
+    assert!(state.scalars.archived_ranges.is_empty());
+    assert_eq!(
+        state.archived_ranges.get(&1).unwrap().canister_id,
+        principal(200)
+    );
+
+    assert!(run_migrations(&mut state).is_empty());
+}
+
+#[test]
+fn archived_ranges_are_kept_out_of_the_scalars() {
+    let mut state = collection();
+    for to in 1..=5 {
+        mint(&mut state, to).unwrap();
+    }
+    state.scalars.archive_log_canister = Some(principal(200));
+    state.save_scalars();
+    let scalars_len = state.scalars.to_bytes().len();
+
+    state.remove_txn_logs(&vec![1, 2]);
+    state.remove_txn_logs(&vec![3]);
+    state.scalars.archive_log_canister = Some(principal(201));
+    state.remove_txn_logs(&vec![4]);
+    state.save_scalars();
+
+    // contiguous batches to the same archive extend its range
+    let ranges: Vec<(u128, u128)> = state
+        .archived_ranges
+        .iter()
+        .map(|(_, range)| (range.start, range.end))
+        .collect();
+    assert_eq!(ranges, vec![(1, 3), (4, 4)]);
+    assert!(state.scalars.archived_ranges.is_empty());
+    assert_eq!(state.scalars.to_bytes().len(), scalars_len);
+
+    let archives = state.icrc3_get_archives(GetArchivesArgs { from: None });
+    assert_eq!(archives.len(), 2);
+    assert_eq!(archives[0].canister_id, principal(200));
+    assert_eq!(archives[0].end, 2u32);
+    assert_eq!(archives[1].start, 3u32);
+    let history = state.icrc7_txn_history(1, 5);
+    assert_eq!(history.archived_txn_logs.len(), 2);
+    assert_eq!(history.txn_logs.len(), 1);
+}
+
+fn export_snapshot(state: &State) -> Vec<SnapshotChunk> {
+    let mut chunks = vec![state.icrc7_export_snapshot(None)];
+    while let Some(cursor) = chunks.last().unwrap().next.clone() {
+        chunks.push(state.icrc7_export_snapshot(Some(cursor)));
+    }
+    chunks
+}
+
+#[test]
+fn background_jobs_wait_while_paused_or_importing() {
+    let mut state = collection();
+    let token_id = mint(&mut state, 1).unwrap();
+    let arg = ApprovalArg {
+        token_id,
+        memo: None,
+        from_subaccount: None,
+        expires_at: Some(time() + 10),
+        spender: account(2),
+        created_at_time: None,
+    };
+    assert!(matches!(
+        state.approve(&principal(1), vec![arg])[..],
+        [Some(Ok(_))]
+    ));
+    mint(&mut state, 1).unwrap();
+    state.scalars.archive_log_canister = Some(principal(200));
+    state.scalars.archive_max_local_txns = Some(1);
+    let later = time() + 20;
+    assert!(state.archive_due(later));
+
+    for (paused, importer) in [(true, None), (false, Some(principal(MINTER)))] {
+        state.scalars.paused = paused;
+        state.scalars.importer = importer;
+        let txn_count = state.scalars.txn_count;
+        assert!(!state.archive_due(later));
+        assert_eq!(state.sweep_expired_approvals(later), 0);
+        assert_eq!(state.icrc7_approval_sweep_status().last_swept_at, None);
+        assert_eq!(state.tokens.get(&token_id).unwrap().approvals.len(), 1);
+        assert_eq!(state.scalars.txn_count, txn_count);
+    }
+}
+
+#[test]
+fn snapshots_leave_out_the_runtime_state() {
+    let mut source = collection();
+    mint(&mut source, 1).unwrap();
+    let config = source.icrc7_export_snapshot(None).config;
+    source.scalars.paused = true;
+    source.scalars.archive_failures = 3;
+    source.scalars.archive_next_attempt_at = Some(time());
+    source.scalars.approval_sweep_passes = 2;
+    source.scalars.approval_sweep_last_run_at = Some(time());
+    let chunks = export_snapshot(&source);
+    assert_eq!(chunks[0].config, config);
+
+    std::thread::spawn(move || {
+        let mut target = collection();
+        target.scalars.approval_sweep_passes = 7;
+        target.begin_import(principal(MINTER)).unwrap();
+        target.import_snapshot(chunks[0].clone()).unwrap();
+        assert!(!target.scalars.paused);
+        assert_eq!(target.scalars.importer, Some(principal(MINTER)));
+        assert_eq!(target.scalars.archive_failures, 0);
+        assert_eq!(target.scalars.approval_sweep_passes, 7);
+        assert_eq!(target.scalars.approval_sweep_last_run_at, None);
+        assert_eq!(target.scalars.txn_count, 1);
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn snapshots_restore_a_collection_in_a_fresh_canister() {
+    let mut source = collection();
+    let token_ids: Vec<u128> = (0..(State::SNAPSHOT_CHUNK_SIZE + 5) as u8)
+        .map(|i| mint(&mut source, i % 4 + 1).unwrap())
+        .collect();
+    let arg = TransferArg {
+        created_at_time: None,
+        ..transfer_arg(token_ids[0], 3, time())
+    };
+    let results = source.icrc7_transfer(&actor(1), vec![arg]);
+    assert!(matches!(results[..], [Some(Ok(_))]));
+    source
+        .ext_account_mapping
+        .insert("account-id".into(), principal(1).to_text());
+    assert!(matches!(
+        burn(&mut source, 2, &token_ids[1..2])[..],
+        [Ok(_)]
+    ));
+    let approvals = vec![ApproveCollectionArg {
+        approval_info: approval_info(6),
+    }];
+    let results = source.icrc37_approve_collection(&principal(3), approvals);
+    assert!(matches!(results[..], [Some(Ok(_))]));
+    source.grant_role(principal(7), Role::Pauser);
+    source.grant_operator_scope(principal(9), OperatorScope::Transfer);
+    source.scalars.archive_log_canister = Some(principal(200));
+    source.remove_txn_logs(&vec![1, 2]);
+
+    let chunks = export_snapshot(&source);
+    assert!(chunks[0].config.is_some());
+    assert!(chunks[1..].iter().all(|chunk| chunk.config.is_none()));
+    let snapshot_hash = chunks.last().unwrap().snapshot_hash.clone();
+    let txn_count = source.scalars.txn_count;
+    let tokens_of_3 = source.icrc7_tokens_of(account(3), None, None);
+
+    // a new thread gets its own stable memory, like a freshly installed canister
+    std::thread::spawn(move || {
+        let mut target = collection();
+        target.grant_role(principal(8), Role::Pauser);
+        assert_eq!(
+            target.import_snapshot(chunks[0].clone()),
+            Err(SnapshotError::ImportNotStarted)
+        );
+        target.begin_import(principal(MINTER)).unwrap();
+        assert!(target.is_importer(&principal(MINTER)));
+        // chunks are chained to the ones before them
+        assert!(matches!(
+            target.import_snapshot(chunks[1].clone()),
+            Err(SnapshotError::HashMismatch { .. })
+        ));
+        assert!(target.tokens.is_empty());
+        for chunk in chunks.iter().take(chunks.len() - 1).cloned() {
+            target.import_snapshot(chunk).unwrap();
+        }
+        // the last chunk carries the operator scopes
+        assert!(matches!(
+            target.finish_import(&snapshot_hash),
+            Err(SnapshotError::HashMismatch { .. })
+        ));
+        assert!(target.is_importer(&principal(MINTER)));
+
+        target
+            .import_snapshot(chunks.last().cloned().unwrap())
+            .unwrap();
+        target.finish_import(&snapshot_hash).unwrap();
+
+        assert_eq!(target.scalars.importer, None);
+        assert_eq!(target.scalars.import_hash, None);
+        let response = target.icrc7_owner_of_certified(&token_ids).unwrap();
+        let tree = decode_hash_tree(&response.certificate.hash_tree);
+        assert_eq!(tree_root_hash(&tree), certified_data());
+        assert_eq!(target.scalars.txn_count, txn_count);
+        assert_eq!(
+            target.icrc7_total_supply(),
+            (State::SNAPSHOT_CHUNK_SIZE + 4) as u128
+        );
+        assert!(target.burned_tokens.contains_key(&token_ids[1]));
+        let archives = target.icrc3_get_archives(GetArchivesArgs { from: None });
+        assert_eq!(archives.len(), 1);
+        assert_eq!(archives[0].canister_id, principal(200));
+        let approvals = target.icrc37_get_collection_approvals(account(3), None, None);
+        assert_eq!(approvals.len(), 1);
+        assert_eq!(approvals[0].spender, account(6));
+        assert!(target.has_role(&principal(7), Role::Pauser));
+        assert!(!target.has_role(&principal(8), Role::Pauser));
+        assert_eq!(
+            target.actor(principal(9), Some(principal(1)), OperatorScope::Transfer),
+            Some(Actor {
+                principal: principal(1),
+                operator: Some(principal(9)),
+            })
+        );
+        assert_eq!(target.icrc7_tokens_of(account(3), None, None), tokens_of_3);
+        assert_eq!(
+            target.ext_account_mapping.get(&"account-id".to_string()),
+            Some(principal(1).to_text())
+        );
+        assert_eq!(
+            target.begin_import(principal(MINTER)),
+            Err(SnapshotError::NotEmpty)
+        );
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn imports_only_start_in_an_empty_collection() {
+    let mut state = collection();
+    state
+        .ext_account_mapping
+        .insert("account-id".into(), principal(1).to_text());
+    assert_eq!(
+        state.begin_import(principal(MINTER)),
+        Err(SnapshotError::NotEmpty)
+    );
+    state.ext_account_mapping.remove(&"account-id".to_string());
+    state.archived_ranges.insert(
+        1,
+        ArchivedTxnRange {
+            canister_id: principal(200),
+            start: 1,
+            end: 1,
+        },
+    );
+    assert_eq!(
+        state.begin_import(principal(MINTER)),
+        Err(SnapshotError::NotEmpty)
+    );
+    assert!(state.has_role(&principal(MINTER), Role::Admin));
+    assert_eq!(state.scalars.importer, None);
+}
+
+fn init_arg(minting_account: Account) -> InitArg {
+    InitArg {
+        minting_account: Some(minting_account),
+        icrc7_symbol: "SSE".into(),
+        icrc7_name: "Event".into(),
+        icrc7_description: None,
+        icrc7_logo: None,
+        icrc7_supply_cap: None,
+        icrc7_max_query_batch_size: None,
+        icrc7_max_update_batch_size: None,
+        icrc7_max_take_value: None,
+        icrc7_default_take_value: None,
+        icrc7_max_memo_size: None,
+        icrc7_atomic_batch_transfers: None,
+        icrc7_transferable: None,
+        icrc7_royalties: None,
+        tx_window: None,
+        permitted_drift: None,
+        archive_max_local_txns: None,
+        archive_batch_size: None,
+        archive_check_interval: None,
+        token_id_strategy: None,
+        burn_expired_tokens: None,
+        expiry_check_interval: None,
+        approval_sweep_batch_size: None,
+        approval_sweep_interval: None,
+        trusted_callers: None,
+        roles: None,
+        operators: None,
+    }
+}
+
+#[test]
+fn factory_collections_let_the_backend_mint_and_transfer() {
+    const MEMBER: u8 = 1;
+    const BACKEND: u8 = 60;
+    const FACTORY: u8 = 61;
+    // the argument the factory installs an event collection with
+    let arg = InitArg {
+        trusted_callers: Some(vec![principal(BACKEND), principal(FACTORY)]),
+        roles: Some(vec![
+            RoleAssignment {
+                principal: principal(BACKEND),
+                roles: vec![Role::Minter],
+            },
+            RoleAssignment {
+                principal: principal(FACTORY),
+                roles: vec![Role::Admin],
+            },
+        ]),
+        operators: Some(vec![OperatorAssignment {
+            operator: principal(BACKEND),
+            scopes: vec![OperatorScope::Transfer],
+        }]),
+        ..init_arg(account(MEMBER))
+    };
+    let mut state = State::default();
+    state.init_collection(arg, principal(FACTORY));
+
+    assert_eq!(state.scalars.minting_authority, Some(account(MEMBER)));
+    assert!(state.has_role(&principal(MEMBER), Role::Admin));
+    assert!(state.has_role(&principal(FACTORY), Role::Admin));
+    assert!(state.is_trusted_caller(&principal(BACKEND)));
+
+    // mint_icrc7_for_user mints as the backend itself
+    let token_id = state
+        .mint(
+            &actor(BACKEND),
+            MintArg {
+                transferable: Some(true),
+                ..mint_arg(MEMBER)
+            },
+        )
+        .unwrap();
+
+    // the backend's icrc7_transfer forwards the transfers on behalf of its caller
+    let operated = state
+        .actor(
+            principal(BACKEND),
+            Some(principal(MEMBER)),
+            OperatorScope::Transfer,
+        )
+        .unwrap();
+    let arg = TransferArg {
+        created_at_time: None,
+        ..transfer_arg(token_id, 2, time())
+    };
+    let results = state.icrc7_transfer(&operated, vec![arg]);
+    assert!(matches!(results[..], [Some(Ok(_))]));
+    assert_eq!(state.icrc7_owner_of(&[token_id]), vec![Some(account(2))]);
+}
+
+#[test]
+fn operated_mints_need_a_minter() {
+    const OPERATOR: u8 = 50;
+    let mut state = collection();
+    state.grant_operator_scope(principal(OPERATOR), OperatorScope::Mint);
+    let for_user = state
+        .actor(principal(OPERATOR), Some(principal(1)), OperatorScope::Mint)
+        .unwrap();
+    let for_minter = state
+        .actor(
+            principal(OPERATOR),
+            Some(principal(MINTER)),
+            OperatorScope::Mint,
+        )
+        .unwrap();
+
+    assert!(matches!(
+        state.mint(&for_user, mint_arg(2)),
+        Err(MintError::Unauthorized)
+    ));
+    assert!(state.mint(&for_minter, mint_arg(2)).is_ok());
+    let txn = state.txn_log.get(&state.scalars.txn_count).unwrap();
+    assert_eq!(txn.operator, Some(principal(OPERATOR)));
+    assert!(matches!(txn.txn_type, TransactionType::Mint { from, .. } if from == account(MINTER)));
+
+    // an operator that is a Minter itself may mint for anyone it operates for
+    state.grant_role(principal(OPERATOR), Role::Minter);
+    assert!(state.mint(&for_user, mint_arg(2)).is_ok());
+    let results = state.mint_batch(&for_user, vec![mint_arg(2), mint_arg(3)]);
+    assert!(matches!(results[..], [Some(Ok(_)), Some(Ok(_))]));
+}
+
+fn domain_sep(label: &str) -> Sha256 {
+    let mut hasher = Sha256::new();
+    hasher.update([label.len() as u8]);
+    hasher.update(label.as_bytes());
+    hasher
+}
+
+// hash tree of a certified response, decoded from its CBOR encoding
+fn decode_hash_tree(bytes: &[u8]) -> CborValue {
+    match ciborium::de::from_reader(bytes).unwrap() {
+        CborValue::Tag(55799, tree) => *tree,
+        _ => panic!("missing self-describe tag"),
+    }
+}
+
+// root hash of an encoded hash tree, computed the way the agents check it
+fn tree_root_hash(tree: &CborValue) -> Vec<u8> {
+    let nodes = tree.as_array().unwrap();
+    let bytes = |index: usize| nodes[index].as_bytes().unwrap().as_slice();
+    let hasher = match u8::try_from(nodes[0].as_integer().unwrap()).unwrap() {
+        0 => domain_sep("ic-hashtree-empty"),
+        1 => {
+            let mut hasher = domain_sep("ic-hashtree-fork");
+            hasher.update(tree_root_hash(&nodes[1]));
+            hasher.update(tree_root_hash(&nodes[2]));
+            hasher
+        }
+        2 => {
+            let mut hasher = domain_sep("ic-hashtree-labeled");
+            hasher.update(bytes(1));
+            hasher.update(tree_root_hash(&nodes[2]));
+            hasher
+        }
+        3 => {
+            let mut hasher = domain_sep("ic-hashtree-leaf");
+            hasher.update(bytes(1));
+            hasher
+        }
+        _ => return bytes(1).to_vec(),
+    };
+    hasher.finalize().to_vec()
+}
+
+// leaf revealed by an encoded hash tree under the given labels
+fn tree_lookup(tree: &CborValue, path: &[&[u8]]) -> Option<Vec<u8>> {
+    let nodes = tree.as_array().unwrap();
+    match u8::try_from(nodes[0].as_integer().unwrap()).unwrap() {
+        1 => tree_lookup(&nodes[1], path).or_else(|| tree_lookup(&nodes[2], path)),
+        2 if !path.is_empty() && nodes[1].as_bytes().unwrap() == path[0] => {
+            tree_lookup(&nodes[2], &path[1..])
+        }
+        3 if path.is_empty() => Some(nodes[1].as_bytes().unwrap().clone()),
+        _ => None,
+    }
+}
+
+#[test]
+fn certified_metadata_matches_the_leaves_of_expiring_tokens() {
+    let mut state = collection();
+    let arg = MintArg {
+        valid_until: Some(time() + 10),
+        ..mint_arg(1)
+    };
+    let token_id = state.mint(&actor(MINTER), arg).unwrap();
+    set_time(time() + 20);
+
+    let response = state.icrc7_token_metadata_certified(&[token_id]).unwrap();
+    let metadata = response.metadata[0].clone().unwrap();
+    assert!(!metadata.contains_key(Icrc7Token::EXPIRED_KEY));
+    assert!(state.icrc7_token_metadata(&[token_id])[0]
+        .as_ref()
+        .unwrap()
+        .contains_key(Icrc7Token::EXPIRED_KEY));
+
+    let tree = decode_hash_tree(&response.certificate.hash_tree);
+    assert_eq!(tree_root_hash(&tree), certified_data());
+    let leaf = tree_lookup(&tree, &[b"metadata", &token_key(token_id)]).unwrap();
+    assert_eq!(leaf, metadata_value(&metadata).hash().to_vec());
+}
+
+#[test]
+fn witnesses_rebuild_the_certified_root() {
+    let mut state = collection();
+    let first = mint(&mut state, 1).unwrap();
+    let second = mint(&mut state, 2).unwrap();
+    mint(&mut state, 1).unwrap();
+
+    let response = state.icrc7_owner_of_certified(&[first, second]).unwrap();
+    let tree = decode_hash_tree(&response.certificate.hash_tree);
+    assert_eq!(tree_root_hash(&tree), certified_data());
+    for (token_id, owner) in [(first, 1), (second, 2)] {
+        assert_eq!(
+            tree_lookup(&tree, &[b"owner", &token_key(token_id)]),
+            Some(Value::account(&account(owner)).hash().to_vec())
+        );
+    }
+
+    let response = state.icrc7_token_metadata_certified(&[second]).unwrap();
+    let tree = decode_hash_tree(&response.certificate.hash_tree);
+    assert_eq!(tree_root_hash(&tree), certified_data());
+    let metadata = response.metadata[0].clone().unwrap();
+    assert_eq!(
+        tree_lookup(&tree, &[b"metadata", &token_key(second)]),
+        Some(metadata_value(&metadata).hash().to_vec())
+    );
+
+    let response = state
+        .icrc7_balance_of_certified(&[account(1), account(3)])
+        .unwrap();
+    assert_eq!(response.balances, vec![2, 0]);
+    let tree = decode_hash_tree(&response.certificate.hash_tree);
+    assert_eq!(tree_root_hash(&tree), certified_data());
+    assert_eq!(
+        tree_lookup(&tree, &[b"balance", &account_key(&account(1))]),
+        Some(Value::nat(2u128).hash().to_vec())
+    );
+    // accounts without tokens have no leaf
+    assert_eq!(
+        tree_lookup(&tree, &[b"balance", &account_key(&account(3))]),
+        None
+    );
+}
+
+#[test]
+fn certified_queries_answer_every_caller() {
+    STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        *state = collection();
+        state.set_trusted_callers(vec![principal(200)]);
+        mint(&mut state, 1).unwrap();
+    });
+    set_caller(principal(200));
+    assert!(not_anonymous_caller().is_ok());
+
+    // end users get a certificate, but aren't trusted callers
+    for caller in [Principal::anonymous(), principal(6)] {
+        set_caller(caller);
+        assert!(not_anonymous_caller().is_err());
+        assert!(query_method::icrc7_owner_of_certified(vec![0]).is_some());
+        assert!(query_method::icrc7_token_metadata_certified(vec![0]).is_some());
+        assert!(query_method::icrc7_balance_of_certified(vec![account(1)]).is_some());
+    }
+}
+
+// examples of the ICRC-3 standard
+#[test]
+fn values_hash_like_the_icrc3_examples() {
+    let from = hex::decode("00abcdef0012340056789a00bcdef000012345678900abcdef01").unwrap();
+    let to = hex::decode("00ab0def0012340056789a00bcdef000012345678900abcdef01").unwrap();
+    let examples = [
+        (
+            Value::nat(42u32),
+            "684888c0ebb17f374298b65ee2807526c066094c701bcc7ebbe1c1095f494fc1",
+        ),
+        (
+            Value::Int(Int::from(-42)),
+            "de5a6f78116eca62d7fc5ce159d23ae6b889b365a1739ad2cf36f925a140d0cc",
+        ),
+        (
+            Value::text("Hello, World!"),
+            "dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f",
+        ),
+        (
+            Value::blob(vec![1, 2, 3, 4]),
+            "9f64a747e1b97f131fabb6b447296c9b6f0201e79fb3c5356e6c77e89b6a806a",
+        ),
+        (
+            Value::Array(vec![
+                Value::nat(3u32),
+                Value::text("foo"),
+                Value::blob(vec![5, 6]),
+            ]),
+            "514a04011caa503990d446b7dec5d79e19c221ae607fb08b2848c67734d468d6",
+        ),
+        (
+            Value::Map(vec![
+                ("from".into(), Value::blob(from)),
+                ("to".into(), Value::blob(to)),
+                ("amount".into(), Value::nat(42u32)),
+                ("created_at".into(), Value::nat(1699218263u64)),
+                ("memo".into(), Value::nat(0u32)),
+            ]),
+            "c56ece650e1de4269c5bdeff7875949e3e2033f85b2d193c2ff4f7f78bdcfc75",
+        ),
+    ];
+    for (value, hash) in examples {
+        assert_eq!(hex::encode(value.hash()), hash);
+    }
+}
+
+fn get_blocks(start: u128, length: u128) -> Vec<GetBlocksRequest> {
+    vec![GetBlocksRequest {
+        start: start.into(),
+        length: length.into(),
+    }]
+}
+
+fn block_ids(blocks: &[BlockWithId]) -> Vec<u128> {
+    blocks
+        .iter()
+        .map(|block| u128::try_from(&block.id.0).unwrap())
+        .collect()
+}
+
+#[test]
+fn blocks_are_chained_by_their_parent_hash() {
+    let mut state = collection();
+    for to in 1..=3 {
+        mint(&mut state, to).unwrap();
+    }
+    let txns: Vec<Transaction> = (1..=3)
+        .map(|txn_id| state.txn_log.get(&txn_id).unwrap())
+        .collect();
+    assert_eq!(txns[0].phash, None);
+    for pair in txns.windows(2) {
+        assert_eq!(pair[1].phash, Some(pair[0].block().hash().to_vec()));
+    }
+    assert_eq!(state.scalars.last_block_hash, Some(txns[2].block().hash()));
+
+    let blocks = state.icrc3_get_blocks(get_blocks(0, 3)).blocks;
+    let served: Vec<Value> = blocks.into_iter().map(|block| block.block).collect();
+    let logged: Vec<Value> = txns.iter().map(Transaction::block).collect();
+    assert_eq!(served, logged);
+}
+
+#[test]
+fn block_ranges_are_clamped_to_the_log() {
+    let mut state = collection();
+    for to in 1..=5 {
+        mint(&mut state, to).unwrap();
+    }
+    let ids = |args| block_ids(&state.icrc3_get_blocks(args).blocks);
+    assert_eq!(ids(get_blocks(1, 2)), vec![1, 2]);
+    assert_eq!(ids(get_blocks(3, 10)), vec![3, 4]);
+    assert!(ids(get_blocks(2, 0)).is_empty());
+    assert!(ids(get_blocks(5, 1)).is_empty());
+    assert!(ids(get_blocks(u128::MAX, u128::MAX)).is_empty());
+    let mut args = get_blocks(0, 1);
+    args.extend(get_blocks(4, 1));
+    assert_eq!(ids(args), vec![0, 4]);
+
+    let result = state.icrc3_get_blocks(get_blocks(0, 5));
+    assert_eq!(result.log_length, 5u32);
+    assert!(result.archived_blocks.is_empty());
+}
+
+#[test]
+fn blocks_chain_across_an_upgrade() {
+    let mut state = collection();
+    mint(&mut state, 1).unwrap();
+    mint(&mut state, 2).unwrap();
+    // a collection installed before the blocks were chained has no tip
+    state.scalars.last_block_hash = None;
+    state.save_scalars();
+    let last_block = state.txn_log.get(&state.scalars.txn_count).unwrap().block();
+
+    let mut state = State::default();
+    state.upgrade_collection(None);
+    mint(&mut state, 3).unwrap();
+
+    let txn = state.txn_log.get(&state.scalars.txn_count).unwrap();
+    assert_eq!(txn.phash, Some(last_block.hash().to_vec()));
+    assert_eq!(state.scalars.last_block_hash, Some(txn.block().hash()));
+}
+
+#[test]
+fn certified_view_is_rebuilt_in_batches_after_an_upgrade() {
+    let mut state = collection();
+    for index in 0..State::CERTIFICATION_BATCH_SIZE + 2 {
+        mint(&mut state, 1 + (index % 2) as u8).unwrap();
+    }
+    state.save_scalars();
+    let root_hash = certified_data();
+
+    let mut state = State::default();
+    state.upgrade_collection(None);
+    assert!(state.icrc7_owner_of_certified(&[0]).is_none());
+    assert!(state.icrc7_token_metadata_certified(&[0]).is_none());
+    assert!(state.icrc7_balance_of_certified(&[account(1)]).is_none());
+
+    assert!(state.certify_batch());
+    assert!(state.icrc7_owner_of_certified(&[0]).is_none());
+    assert!(!state.certify_batch());
+    assert!(!state.certify_batch());
+
+    assert_eq!(certified_data(), root_hash);
+    let response = state
+        .icrc7_balance_of_certified(&[account(1), account(2)])
+        .unwrap();
+    let tree = decode_hash_tree(&response.certificate.hash_tree);
+    assert_eq!(tree_root_hash(&tree), certified_data());
+    assert_eq!(
+        tree_lookup(&tree, &[b"balance", &account_key(&account(1))]),
+        Some(Value::nat(response.balances[0]).hash().to_vec())
+    );
+}
+
+// the archive answers within the call, so the futures of the collection are ready at once
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    match future
+        .as_mut()
+        .poll(&mut Context::from_waker(Waker::noop()))
+    {
+        Poll::Ready(output) => output,
+        Poll::Pending => panic!("the archive answers synchronously"),
+    }
+}
+
+#[test]
+fn archived_blocks_are_served_by_the_archive() {
+    let archive_id = principal(200);
+    // the archive canister runs on the memory of its own crate, next to the collection
+    ARCHIVE.with(|archive| archive.borrow_mut().parent_canister = Some(canister_id()));
+    set_archive(Box::new(|callee, txn_logs| {
+        assert_eq!(callee, principal(200));
+        ARCHIVE.with(|archive| {
+            archive.borrow().parent_check(&canister_id())?;
+            Ok(archive.borrow_mut().insert_many_txn_log(txn_logs))
+        })
+    }));
+    mutate_state(|s| {
+        s.scalars.minting_authority = Some(account(MINTER));
+        s.init_roles();
+        s.scalars.archive_log_canister = Some(archive_id);
+        s.scalars.archive_batch_size = Some(3);
+        for to in 1..=5 {
+            mint(s, to).unwrap();
+        }
+    });
+    let blocks = STATE.with(|s| s.borrow().icrc3_get_blocks(get_blocks(0, 5)).blocks);
+
+    assert_eq!(block_on(archive_logs()), Ok(3));
+
+    let result = STATE.with(|s| s.borrow().icrc3_get_blocks(get_blocks(0, 5)));
+    assert_eq!(block_ids(&result.blocks), vec![3, 4]);
+    assert_eq!(result.archived_blocks.len(), 1);
+    let callback = &result.archived_blocks[0].callback;
+    assert_eq!(callback.0.principal, archive_id);
+    assert_eq!(callback.0.method, "icrc3_get_blocks");
+    let archived = ARCHIVE.with(|archive| {
+        archive
+            .borrow()
+            .icrc3_get_blocks(result.archived_blocks[0].args.clone())
+    });
+    assert_eq!(block_ids(&archived.blocks), vec![0, 1, 2]);
+    // the blocks are the same on both canisters and still chained across them
+    let served: Vec<Value> = archived
+        .blocks
+        .into_iter()
+        .chain(result.blocks)
+        .map(|block| block.block)
+        .collect();
+    assert_eq!(
+        served,
+        blocks
+            .into_iter()
+            .map(|block| block.block)
+            .collect::<Vec<_>>()
+    );
+    let last_archived = ARCHIVE.with(|archive| archive.borrow().txn_log.get(&3).unwrap());
+    let first_local = STATE.with(|s| s.borrow().txn_log.get(&4).unwrap());
+    assert_eq!(
+        first_local.phash,
+        Some(last_archived.block().hash().to_vec())
+    );
+
+    // a collection which is not the parent of the archive keeps its transactions
+    ARCHIVE.with(|archive| archive.borrow_mut().parent_canister = Some(principal(9)));
+    assert_eq!(
+        block_on(archive_logs()),
+        Err(InsertTransactionError::RemoteError)
+    );
+    let status = STATE.with(|s| s.borrow().icrc7_archive_status());
+    assert_eq!(status.local_txn_count, 2);
+    assert_eq!(status.archived_txn_count, 3);
+    assert!(!status.sync_pending);
+    assert_eq!(
+        ARCHIVE.with(|archive| archive.borrow().get_txn_log_count()),
+        3
+    );
+}
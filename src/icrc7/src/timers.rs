@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use crate::state::{archive_logs, mutate_state, STATE};
+
+// timers do not survive upgrades, so this runs both on init and on post_upgrade
+pub fn start_archive_timer() {
+    let interval = STATE.with(|s| s.borrow().archive_check_interval());
+    ic_cdk_timers::set_timer_interval(Duration::from_nanos(interval), || {
+        ic_cdk::spawn(archive_job())
+    });
+}
+
+// the sweeper only runs for collections that burn their expired tokens, and skips its ticks
+// while the collection is paused or being imported
+pub fn start_expiry_timer() {
+    let (enabled, interval) = STATE.with(|s| {
+        let s = s.borrow();
+        (s.burns_expired_tokens(), s.expiry_check_interval())
+    });
+    if !enabled {
+        return;
+    }
+    ic_cdk_timers::set_timer_interval(Duration::from_nanos(interval), || {
+        mutate_state(|s| s.sweep_expired_tokens(ic_cdk::api::time()));
+    });
+}
+
+pub fn start_approval_sweep_timer() {
+    let interval = STATE.with(|s| s.borrow().approval_sweep_interval());
+    ic_cdk_timers::set_timer_interval(Duration::from_nanos(interval), || {
+        STATE.with(|s| s.borrow_mut().run_approval_sweep(ic_cdk::api::time()));
+    });
+}
+
+// rebuilds the certified trees after an upgrade, one batch of tokens per timer so that no
+// message runs over the instruction limit
+pub fn start_certification_timer() {
+    ic_cdk_timers::set_timer(Duration::ZERO, || {
+        if mutate_state(|s| s.certify_batch()) {
+            start_certification_timer();
+        }
+    });
+}
+
+// archives batches until the local log is back under the threshold or a sync fails
+async fn archive_job() {
+    while STATE.with(|s| s.borrow().archive_due(ic_cdk::api::time())) {
+        let result = archive_logs().await;
+        mutate_state(|s| s.record_archive_result(&result, ic_cdk::api::time()));
+        if result.is_err() {
+            break;
+        }
+    }
+}
@@ -1,93 +1,200 @@
 use candid::Principal;
 
 use crate::{
-    errors::InsertTransactionError,
-    guards::{not_anonymous_caller, owner_guard},
-    state::{call_sync_logs, STATE},
-    ApprovalArg, ApproveResult, BurnArg, BurnResult, MintArg, MintResult, SyncReceipt, Transaction,
-    TransferArg, TransferResult,
+    errors::{MintError, TransferError, UpdateMetadataError},
+    guards::{
+        admin_guard, archiver_guard, importer_guard, not_paused_archiver,
+        not_paused_authenticated_caller, not_paused_backend_caller, pauser_guard,
+    },
+    icrc7_types::{OperatorScope, Role, SnapshotChunk, SnapshotResult},
+    state::{archive_logs, mutate_state},
+    ApprovalArg, ApproveResult, BurnArg, BurnResult, MintArg, MintResult, SyncReceipt, TransferArg,
+    TransferResult, UpdateCollectionArg, UpdateCollectionResult, UpdateMetadataResult,
+    UpdateTokenMetadataArg,
 };
 use icrc_ledger_types::icrc1::account::Account;
+use serde_bytes::ByteBuf;
 
-#[ic_cdk::update(guard = "not_anonymous_caller")]
-pub fn icrc7_mint(arg: MintArg, caller: Principal) -> MintResult {
-    // let caller = ic_cdk::caller();
-    if caller == Principal::anonymous() {
-        return Err(crate::errors::MintError::GenericBatchError {
-            error_code: 100,
-            message: "Anonymous Identity".into(),
-        });
-    }
-    STATE.with(|s| s.borrow_mut().mint(&caller, arg))
+// operators registered for the Mint scope may mint on behalf of the given principal
+#[ic_cdk::update(guard = "not_paused_authenticated_caller")]
+pub fn icrc7_mint(arg: MintArg, on_behalf_of: Option<Principal>) -> MintResult {
+    let caller = ic_cdk::caller();
+    mutate_state(|s| {
+        let actor = s
+            .actor(caller, on_behalf_of, OperatorScope::Mint)
+            .ok_or(MintError::Unauthorized)?;
+        if actor.principal == Principal::anonymous() {
+            return Err(MintError::GenericBatchError {
+                error_code: 100,
+                message: "Anonymous Identity".into(),
+            });
+        }
+        s.mint(&actor, arg)
+    })
+}
+
+#[ic_cdk::update(guard = "not_paused_authenticated_caller")]
+pub fn icrc7_mint_batch(
+    args: Vec<MintArg>,
+    on_behalf_of: Option<Principal>,
+) -> Vec<Option<MintResult>> {
+    let caller = ic_cdk::caller();
+    mutate_state(|s| {
+        let Some(actor) = s.actor(caller, on_behalf_of, OperatorScope::Mint) else {
+            return vec![Some(Err(MintError::GenericBatchError {
+                error_code: 101,
+                message: "Unauthorized Operator".into(),
+            }))];
+        };
+        if actor.principal == Principal::anonymous() {
+            return vec![Some(Err(MintError::GenericBatchError {
+                error_code: 100,
+                message: "Anonymous Identity".into(),
+            }))];
+        }
+        s.mint_batch(&actor, args)
+    })
 }
 
-#[ic_cdk::update(guard = "not_anonymous_caller")]
-pub fn icrc7_transfer(args: Vec<TransferArg>, caller: Principal) -> Vec<Option<TransferResult>> {
-    // let caller = ic_cdk::caller();
-    STATE.with(|s| s.borrow_mut().icrc7_transfer(&caller, args))
+#[ic_cdk::update(guard = "not_paused_authenticated_caller")]
+pub fn icrc7_transfer(
+    args: Vec<TransferArg>,
+    on_behalf_of: Option<Principal>,
+) -> Vec<Option<TransferResult>> {
+    let caller = ic_cdk::caller();
+    mutate_state(
+        |s| match s.actor(caller, on_behalf_of, OperatorScope::Transfer) {
+            Some(actor) => s.icrc7_transfer(&actor, args),
+            None => vec![Some(Err(TransferError::GenericBatchError {
+                error_code: 101,
+                message: "Unauthorized Operator".into(),
+            }))],
+        },
+    )
 }
 
-#[ic_cdk::update(guard = "not_anonymous_caller")]
+#[ic_cdk::update(guard = "not_paused_backend_caller")]
 pub fn icrc7_burn(args: Vec<BurnArg>) -> Vec<Option<BurnResult>> {
     let caller = ic_cdk::caller();
-    STATE.with(|s| s.borrow_mut().burn(&caller, args))
+    mutate_state(|s| s.burn(&caller, args))
 }
 
-#[ic_cdk::update(guard = "not_anonymous_caller")]
+#[ic_cdk::update(guard = "not_paused_backend_caller")]
 pub fn icrc7_approve(args: Vec<ApprovalArg>) -> Vec<Option<ApproveResult>> {
     let caller = ic_cdk::caller();
-    STATE.with(|s| s.borrow_mut().approve(&caller, args))
+    mutate_state(|s| s.approve(&caller, args))
 }
 
-#[ic_cdk::update(guard = "owner_guard")]
+#[ic_cdk::update(guard = "not_paused_authenticated_caller")]
+pub fn icrc7_update_token_metadata(
+    args: Vec<UpdateTokenMetadataArg>,
+    on_behalf_of: Option<Principal>,
+) -> Vec<Option<UpdateMetadataResult>> {
+    let caller = ic_cdk::caller();
+    mutate_state(|s| {
+        let Some(actor) = s.actor(caller, on_behalf_of, OperatorScope::UpdateMetadata) else {
+            return vec![Some(Err(UpdateMetadataError::GenericBatchError {
+                error_code: 101,
+                message: "Unauthorized Operator".into(),
+            }))];
+        };
+        if actor.principal == Principal::anonymous() {
+            return vec![Some(Err(UpdateMetadataError::GenericBatchError {
+                error_code: 100,
+                message: "Anonymous Identity".into(),
+            }))];
+        }
+        s.update_token_metadata(&actor, args)
+    })
+}
+
+#[ic_cdk::update(guard = "admin_guard")]
+pub fn icrc7_update_collection(arg: UpdateCollectionArg) -> UpdateCollectionResult {
+    let caller = ic_cdk::caller();
+    mutate_state(|s| s.update_collection(&caller, arg))
+}
+
+#[ic_cdk::update(guard = "admin_guard")]
+pub fn icrc7_set_metadata_editors(editors: Vec<Account>) -> bool {
+    mutate_state(|s| s.set_metadata_editors(editors));
+    true
+}
+
+#[ic_cdk::update(guard = "admin_guard")]
 pub fn icrc7_set_minting_authority(minting_account: Account) -> bool {
-    STATE.with(|s| s.borrow_mut().minting_authority = Some(minting_account));
+    mutate_state(|s| s.set_minting_authority(minting_account));
     true
 }
 
-#[ic_cdk::update(guard = "owner_guard")]
+#[ic_cdk::update(guard = "archiver_guard")]
 pub fn icrc7_set_archive_log_canister(arg: Principal) -> bool {
-    STATE.with(|s| {
-        let mut state = s.borrow_mut();
-        state.archive_log_canister = Some(arg);
+    mutate_state(|state| {
+        state.scalars.archive_log_canister = Some(arg);
     });
 
     true
 }
 
-#[ic_cdk::update(guard = "owner_guard")]
+#[ic_cdk::update(guard = "not_paused_archiver")]
 pub async fn icrc7_archive_logs() -> SyncReceipt {
-    let archive_log_canister = STATE
-        .with(|s| s.borrow().get_archive_log_canister())
-        .ok_or(InsertTransactionError::NotSetArchiveCanister)?;
+    let result = archive_logs().await;
+    mutate_state(|s| s.record_archive_result(&result, ic_cdk::api::time()));
+    result
+}
 
-    // check sync pending
-    let sync_pending_txn_ids = STATE.with(|s| s.borrow().get_sync_pending_txn_ids());
-    if sync_pending_txn_ids.is_some() {
-        return Err(InsertTransactionError::SyncPending);
-    }
+#[ic_cdk::update(guard = "admin_guard")]
+pub fn icrc7_grant_role(principal: Principal, role: Role) -> bool {
+    mutate_state(|s| s.grant_role(principal, role))
+}
 
-    let txn_logs: Vec<Transaction> = STATE.with(|s| s.borrow().get_txn_logs(200));
+#[ic_cdk::update(guard = "admin_guard")]
+pub fn icrc7_revoke_role(principal: Principal, role: Role) -> bool {
+    mutate_state(|s| s.revoke_role(principal, role))
+}
 
-    let txn_ids: Vec<u128> = txn_logs.iter().map(|log| log.txn_id).collect();
+#[ic_cdk::update(guard = "admin_guard")]
+pub fn icrc7_grant_operator_scope(operator: Principal, scope: OperatorScope) -> bool {
+    mutate_state(|s| s.grant_operator_scope(operator, scope))
+}
 
-    // set pending
-    STATE.with(|s| {
-        s.borrow_mut()
-            .set_sync_pending_txn_ids(Some(txn_ids.clone()))
-    });
+#[ic_cdk::update(guard = "admin_guard")]
+pub fn icrc7_revoke_operator_scope(operator: Principal, scope: OperatorScope) -> bool {
+    mutate_state(|s| s.revoke_operator_scope(operator, scope))
+}
 
-    // remote call logs sync
-    let call_result = call_sync_logs(archive_log_canister, txn_logs).await;
+#[ic_cdk::update(guard = "admin_guard")]
+pub fn icrc7_add_trusted_callers(principals: Vec<Principal>) -> bool {
+    mutate_state(|s| s.add_trusted_callers(principals));
+    true
+}
 
-    match call_result {
-        Ok(count) => {
-            STATE.with(|s| s.borrow_mut().remove_txn_logs(&txn_ids));
-            Ok(count)
-        }
-        Err(_) => {
-            STATE.with(|s| s.borrow_mut().set_sync_pending_txn_ids(None));
-            Err(InsertTransactionError::RemoteError)
-        }
-    }
+#[ic_cdk::update(guard = "admin_guard")]
+pub fn icrc7_remove_trusted_callers(principals: Vec<Principal>) -> bool {
+    mutate_state(|s| s.remove_trusted_callers(principals));
+    true
+}
+
+#[ic_cdk::update(guard = "pauser_guard")]
+pub fn icrc7_set_paused(paused: bool) -> bool {
+    mutate_state(|s| s.scalars.paused = paused);
+    true
+}
+
+// a freshly installed collection is restored from a snapshot by calling begin_import, then
+// import_snapshot with every exported chunk in order and finish_import with the hash of the
+// last one, all
+// called by the admin who began the import
+#[ic_cdk::update(guard = "admin_guard")]
+pub fn icrc7_begin_import() -> SnapshotResult {
+    mutate_state(|s| s.begin_import(ic_cdk::caller()))
+}
+
+#[ic_cdk::update(guard = "importer_guard")]
+pub fn icrc7_import_snapshot(chunk: SnapshotChunk) -> SnapshotResult {
+    mutate_state(|s| s.import_snapshot(chunk))
+}
+
+#[ic_cdk::update(guard = "importer_guard")]
+pub fn icrc7_finish_import(snapshot_hash: ByteBuf) -> SnapshotResult {
+    mutate_state(|s| s.finish_import(&snapshot_hash))
 }
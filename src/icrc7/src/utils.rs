@@ -1,7 +1,10 @@
 use candid::Principal;
 use icrc_ledger_types::icrc1::account::{Account, Subaccount, DEFAULT_SUBACCOUNT};
 
-use crate::ext_types::User;
+use crate::{
+    ext_types::User,
+    icrc7_types::{SyncReceipt, Transaction},
+};
 
 pub fn account_transformer(account: Account) -> Account {
     if account.subaccount.is_some() {
@@ -40,7 +43,115 @@ pub fn burn_subaccount() -> Subaccount {
 
 pub fn burn_account() -> Account {
     Account {
-        owner: ic_cdk::api::id(),
+        owner: canister_id(),
         subaccount: Some(burn_subaccount()),
     }
 }
+
+// System API used by the state. Unit tests run outside of a canister, so they get a clock
+// they can move and a fixed canister id instead.
+#[cfg(not(test))]
+pub fn time() -> u64 {
+    ic_cdk::api::time()
+}
+
+#[cfg(not(test))]
+pub fn canister_id() -> Principal {
+    ic_cdk::api::id()
+}
+
+#[cfg(not(test))]
+pub fn caller() -> Principal {
+    ic_cdk::caller()
+}
+
+#[cfg(not(test))]
+pub fn set_certified_data(data: &[u8]) {
+    ic_cdk::api::set_certified_data(data)
+}
+
+#[cfg(not(test))]
+pub fn data_certificate() -> Option<Vec<u8>> {
+    ic_cdk::api::data_certificate()
+}
+
+// the archive rejects the call when the collection is not its parent
+#[cfg(not(test))]
+pub async fn insert_many_txn_log(
+    archive: Principal,
+    txn_logs: Vec<Transaction>,
+) -> Result<SyncReceipt, String> {
+    ic_cdk::api::call::call(archive, "insert_many_txn_log", (txn_logs,))
+        .await
+        .map(|(receipt,)| receipt)
+        .map_err(|(_rejection_code, message)| message)
+}
+
+// archive canister answering the calls of the collection, given the callee and the batch
+#[cfg(test)]
+pub type ArchiveCall = Box<dyn Fn(Principal, Vec<Transaction>) -> Result<SyncReceipt, String>>;
+
+#[cfg(test)]
+thread_local! {
+    static TIME: std::cell::Cell<u64> = const { std::cell::Cell::new(1_700_000_000_000_000_000) };
+    static CALLER: std::cell::Cell<Principal> = const { std::cell::Cell::new(Principal::anonymous()) };
+    static CERTIFIED_DATA: std::cell::RefCell<Vec<u8>> = const { std::cell::RefCell::new(vec![]) };
+    static ARCHIVE: std::cell::RefCell<Option<ArchiveCall>> = const { std::cell::RefCell::new(None) };
+}
+
+#[cfg(test)]
+pub fn time() -> u64 {
+    TIME.with(|time| time.get())
+}
+
+#[cfg(test)]
+pub fn set_time(now: u64) {
+    TIME.with(|time| time.set(now))
+}
+
+#[cfg(test)]
+pub fn canister_id() -> Principal {
+    Principal::from_slice(&[0, 0, 0, 0, 0, 0, 0, 1, 1, 1])
+}
+
+#[cfg(test)]
+pub fn caller() -> Principal {
+    CALLER.with(|caller| caller.get())
+}
+
+#[cfg(test)]
+pub fn set_caller(principal: Principal) {
+    CALLER.with(|caller| caller.set(principal))
+}
+
+#[cfg(test)]
+pub fn set_certified_data(data: &[u8]) {
+    CERTIFIED_DATA.with(|certified_data| *certified_data.borrow_mut() = data.to_vec())
+}
+
+// the last data set by the state, in place of the certificate signed by the subnet
+#[cfg(test)]
+pub fn certified_data() -> Vec<u8> {
+    CERTIFIED_DATA.with(|certified_data| certified_data.borrow().clone())
+}
+
+#[cfg(test)]
+pub fn data_certificate() -> Option<Vec<u8>> {
+    Some(vec![])
+}
+
+#[cfg(test)]
+pub fn set_archive(archive: ArchiveCall) {
+    ARCHIVE.with(|call| *call.borrow_mut() = Some(archive))
+}
+
+#[cfg(test)]
+pub async fn insert_many_txn_log(
+    archive: Principal,
+    txn_logs: Vec<Transaction>,
+) -> Result<SyncReceipt, String> {
+    ARCHIVE.with(|call| match call.borrow().as_ref() {
+        Some(call) => call(archive, txn_logs),
+        None => Err(String::from("No archive canister")),
+    })
+}
@@ -0,0 +1,7 @@
+use ic_cdk::caller;
+use icrc7_common::archive::state::STATE;
+
+// only the collection owning the archive can append to it
+pub fn parent_guard() -> Result<(), String> {
+    STATE.with(|s| s.borrow().parent_check(&caller()))
+}
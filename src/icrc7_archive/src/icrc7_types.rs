@@ -0,0 +1,8 @@
+use candid::{CandidType, Principal};
+use serde::Deserialize;
+
+#[derive(CandidType, Deserialize)]
+pub struct InitArg {
+    // the icrc7 collection allowed to append to the archive
+    pub parent_canister: Principal,
+}
@@ -0,0 +1,17 @@
+use candid::Principal;
+use ic_cdk_macros::export_candid;
+
+pub mod guards;
+pub mod icrc7_types;
+pub mod init_method;
+pub mod query_method;
+pub mod update_method;
+
+// unit tests of the archive state, the canister itself is not deployed by them
+#[cfg(test)]
+mod tests;
+
+use crate::icrc7_types::*;
+use icrc7_common::{errors::*, icrc3_types::*, transaction::*};
+
+export_candid!();
@@ -0,0 +1,36 @@
+use candid::Principal;
+
+use icrc7_common::{
+    archive::state::STATE,
+    icrc3_types::{GetBlocksArgs, GetBlocksResult},
+    transaction::Transaction,
+};
+
+#[ic_cdk::query]
+pub fn get_parent_canister() -> Option<Principal> {
+    STATE.with(|s| s.borrow().get_parent_canister())
+}
+
+#[ic_cdk::query]
+pub fn get_txn_log_count() -> u64 {
+    STATE.with(|s| s.borrow().get_txn_log_count())
+}
+
+#[ic_cdk::query]
+pub fn get_txn_logs(start: u128, length: u128) -> Vec<Transaction> {
+    STATE.with(|s| s.borrow().get_txn_logs(start, length))
+}
+
+#[ic_cdk::query]
+pub fn get_token_txn_logs(
+    token_id: u128,
+    prev: Option<u128>,
+    take: Option<u128>,
+) -> Vec<Transaction> {
+    STATE.with(|s| s.borrow().get_token_txn_logs(token_id, prev, take))
+}
+
+#[ic_cdk::query]
+pub fn icrc3_get_blocks(args: GetBlocksArgs) -> GetBlocksResult {
+    STATE.with(|s| s.borrow().icrc3_get_blocks(args))
+}
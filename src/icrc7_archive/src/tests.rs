@@ -0,0 +1,149 @@
+use candid::{Nat, Principal};
+use icrc7_common::{
+    archive::state::State,
+    errors::InsertTransactionError,
+    icrc3_types::GetBlocksRequest,
+    transaction::{Transaction, TransactionType},
+};
+use icrc_ledger_types::icrc1::account::Account;
+
+fn account(id: u8) -> Account {
+    Account {
+        owner: Principal::from_slice(&[id]),
+        subaccount: None,
+    }
+}
+
+fn transfer(txn_id: u128, tid: u128) -> Transaction {
+    Transaction::new(
+        txn_id,
+        TransactionType::Transfer {
+            tid,
+            from: account(1),
+            to: account(2),
+        },
+        txn_id as u64,
+        None,
+    )
+}
+
+fn collection_approval(txn_id: u128) -> Transaction {
+    Transaction::new(
+        txn_id,
+        TransactionType::CollectionApproval {
+            from: account(1),
+            to: account(2),
+        },
+        txn_id as u64,
+        None,
+    )
+}
+
+fn txn_ids(txn_logs: &[Transaction]) -> Vec<u128> {
+    txn_logs.iter().map(|txn| txn.txn_id).collect()
+}
+
+#[test]
+fn only_the_parent_collection_writes() {
+    let mut state = State::default();
+    assert!(state.parent_check(&Principal::from_slice(&[200])).is_err());
+
+    state.parent_canister = Some(Principal::from_slice(&[200]));
+
+    assert_eq!(state.parent_check(&Principal::from_slice(&[200])), Ok(()));
+    assert!(state.parent_check(&Principal::from_slice(&[201])).is_err());
+    assert!(state.parent_check(&Principal::anonymous()).is_err());
+}
+
+#[test]
+fn insert_and_read_range() {
+    let mut state = State::default();
+    let batch = (1..=10)
+        .map(|txn_id| transfer(txn_id, txn_id % 3))
+        .collect();
+
+    assert_eq!(state.insert_many_txn_log(batch), Ok(10));
+    assert_eq!(state.get_txn_log_count(), 10);
+    assert_eq!(txn_ids(&state.get_txn_logs(4, 3)), vec![4, 5, 6]);
+    assert_eq!(txn_ids(&state.get_txn_logs(9, 5)), vec![9, 10]);
+    assert!(state.get_txn_logs(11, 5).is_empty());
+}
+
+#[test]
+fn range_is_capped() {
+    let mut state = State::default();
+    let batch = (1..=150).map(|txn_id| transfer(txn_id, 0)).collect();
+    state.insert_many_txn_log(batch).unwrap();
+
+    let page = state.get_txn_logs(1, 1_000);
+    assert_eq!(page.len() as u128, State::DEFAULT_MAX_TAKE_VALUE);
+    assert_eq!(page.last().unwrap().txn_id, State::DEFAULT_MAX_TAKE_VALUE);
+}
+
+#[test]
+fn rejects_out_of_order_batches() {
+    let mut state = State::default();
+    state
+        .insert_many_txn_log(vec![transfer(5, 0), transfer(6, 0)])
+        .unwrap();
+
+    assert_eq!(
+        state.insert_many_txn_log(vec![transfer(7, 0), transfer(3, 0)]),
+        Err(InsertTransactionError::InvalidId)
+    );
+    assert_eq!(
+        state.insert_many_txn_log(vec![transfer(0, 0)]),
+        Err(InsertTransactionError::InvalidId)
+    );
+    // a rejected batch leaves the archive untouched
+    assert_eq!(state.get_txn_log_count(), 2);
+}
+
+#[test]
+fn resent_batch_is_skipped() {
+    let mut state = State::default();
+    let batch: Vec<Transaction> = (1..=3).map(|txn_id| transfer(txn_id, 0)).collect();
+    state.insert_many_txn_log(batch.clone()).unwrap();
+
+    let mut resent = batch;
+    resent.push(transfer(4, 0));
+    assert_eq!(state.insert_many_txn_log(resent), Ok(4));
+    assert_eq!(txn_ids(&state.get_txn_logs(1, 10)), vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn token_history_is_paginated() {
+    let mut state = State::default();
+    let mut batch: Vec<Transaction> = (1..=9).map(|txn_id| transfer(txn_id, txn_id % 2)).collect();
+    batch.push(collection_approval(10));
+    state.insert_many_txn_log(batch).unwrap();
+
+    let first_page = state.get_token_txn_logs(1, None, Some(2));
+    assert_eq!(txn_ids(&first_page), vec![1, 3]);
+    let second_page = state.get_token_txn_logs(1, Some(3), Some(10));
+    assert_eq!(txn_ids(&second_page), vec![5, 7, 9]);
+    assert_eq!(
+        txn_ids(&state.get_token_txn_logs(0, None, None)),
+        vec![2, 4, 6, 8]
+    );
+    assert!(state.get_token_txn_logs(2, None, None).is_empty());
+}
+
+#[test]
+fn blocks_are_indexed_from_zero() {
+    let mut state = State::default();
+    let batch = (101..=110).map(|txn_id| transfer(txn_id, 0)).collect();
+    state.insert_many_txn_log(batch).unwrap();
+
+    let result = state.icrc3_get_blocks(vec![GetBlocksRequest {
+        start: Nat::from(100u32),
+        length: Nat::from(3u32),
+    }]);
+    let ids: Vec<Nat> = result.blocks.iter().map(|block| block.id.clone()).collect();
+    assert_eq!(
+        ids,
+        vec![Nat::from(100u32), Nat::from(101u32), Nat::from(102u32)]
+    );
+    assert_eq!(result.log_length, Nat::from(10u32));
+    assert!(result.archived_blocks.is_empty());
+}
@@ -0,0 +1,8 @@
+use icrc7_common::{archive::state::STATE, errors::SyncReceipt, transaction::Transaction};
+
+use crate::guards::parent_guard;
+
+#[ic_cdk::update(guard = "parent_guard")]
+pub fn insert_many_txn_log(txn_logs: Vec<Transaction>) -> SyncReceipt {
+    STATE.with(|s| s.borrow_mut().insert_many_txn_log(txn_logs))
+}
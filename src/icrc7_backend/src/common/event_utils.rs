@@ -1,10 +1,10 @@
 use crate::memory::get_events_collection;
 
 use super::{
-    types::{Member, RequestResult},
+    types::{Member, RequestResult, Value, EVENT_ID_METADATA_KEY},
     utils::{
         create_icrc7_collection, mint_icrc7_for_user, slice_to_principal, string_to_principal,
-        update_minting_authority,
+        token_metadata, update_minting_authority,
     },
 };
 
@@ -45,20 +45,18 @@ pub async fn assign_nft_for_event(
             factory_canister_id,
             icrc7_name.clone(),
             icrc7_description.clone(),
-            Some(event_id.clone()), // the logo will contain the event id to save memory
+            None,
         )
         .await;
         // updating minting authority, default is on the factory canister
         update_minting_authority(factory_canister_id, owner, icrc7_canister_id).await;
-        match mint_icrc7_for_user(
-            owner,
-            icrc7_canister_id,
-            Some(icrc7_name),
-            icrc7_description.clone(),
-            Some(event_id.clone()),
-        )
-        .await
-        {
+        // the token references the event, whose metadata is resolved when the token is read
+        let mut metadata = token_metadata(icrc7_name, icrc7_description.clone());
+        metadata.insert(
+            EVENT_ID_METADATA_KEY.to_string(),
+            Value::Text(event_id.clone()),
+        );
+        match mint_icrc7_for_user(owner, icrc7_canister_id, metadata).await {
             Ok(v) => token_ids.push(v),
             Err(err) => {
                 return RequestResult::new(
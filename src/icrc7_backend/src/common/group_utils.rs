@@ -4,7 +4,7 @@ use super::{
     types::RequestResult,
     utils::{
         create_icrc7_collection, mint_icrc7_for_user, slice_to_principal, string_to_principal,
-        update_minting_authority,
+        token_metadata, update_minting_authority,
     },
 };
 
@@ -38,9 +38,7 @@ pub async fn assign_nft_to_group_member(uuid: String) -> RequestResult<Vec<u128>
         match mint_icrc7_for_user(
             string_to_principal(member.internet_identity.clone()),
             icrc7_canister_id,
-            Some(icrc7_name),
-            None,
-            None,
+            token_metadata(icrc7_name, None),
         )
         .await
         {
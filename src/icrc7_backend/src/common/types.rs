@@ -112,13 +112,10 @@ impl Storable for Event {
 pub struct MintArg {
     pub from_subaccount: Option<Subaccount>,
     pub to: Account,
-    pub token_id: u128,
+    pub token_id: Option<u128>,
     pub memo: Option<Vec<u8>>,
-    // if None, then the combination of Collection's symbol and token's id will be provided
-    // for e.g.: "ICRC7 100"
-    pub token_name: Option<String>,
-    pub token_description: Option<String>,
-    pub token_logo: Option<String>,
+    pub metadata: Icrc7TokenMetadata,
+    pub transferable: Option<bool>,
 }
 
 #[derive(CandidType, Deserialize, Debug)]
@@ -152,6 +149,7 @@ pub enum TransferError {
     NonExistingTokenId,
     InvalidRecipient,
     Unauthorized,
+    NonTransferable,
     TooOld,
     CreatedInFuture { ledger_time: u64 },
     Duplicate { duplicate_of: u128 },
@@ -168,6 +166,9 @@ pub enum MintError {
     Unauthorized,
     TokenIdAlreadyExist,
     TokenIdMinimumLimit,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: u128 },
     GenericError { error_code: u128, message: String },
     GenericBatchError { error_code: u128, message: String },
 }
@@ -181,6 +182,28 @@ pub enum MetadataValue {
     Blob(ByteBuf),
 }
 
+/// Generic value of the ICRC-3 standard, used by the collections for the token metadata.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Blob(ByteBuf),
+    Text(String),
+    Nat(Nat),
+    Int(Int),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+impl From<MetadataValue> for Value {
+    fn from(value: MetadataValue) -> Self {
+        match value {
+            MetadataValue::Nat(n) => Value::Nat(n),
+            MetadataValue::Int(i) => Value::Int(i),
+            MetadataValue::Text(t) => Value::Text(t),
+            MetadataValue::Blob(b) => Value::Blob(b),
+        }
+    }
+}
+
 #[derive(CandidType, Clone, Debug)]
 pub struct RequestResult<T> {
     pub code: u16,
@@ -198,4 +221,7 @@ impl<T> RequestResult<T> {
     }
 }
 
-pub type Icrc7TokenMetadata = HashMap<String, MetadataValue>;
+pub type Icrc7TokenMetadata = HashMap<String, Value>;
+
+// metadata key under which the minted tokens reference the event they commemorate
+pub const EVENT_ID_METADATA_KEY: &str = "event_id";
@@ -3,9 +3,9 @@ use std::str::FromStr;
 use candid::Principal;
 use ic_cdk::call;
 
-use crate::memory::{get_collections, get_current_token_id, increase_token_id};
+use crate::memory::get_collections;
 
-use super::types::{Account, Arg, MintArg, MintResult};
+use super::types::{Account, Arg, Icrc7TokenMetadata, MintArg, MintResult, Value};
 
 // function to update the minting authority on a callection
 // the function call an update method on the collection and return if the operation went right
@@ -63,14 +63,22 @@ pub async fn create_icrc7_collection(
     }
 }
 
+// function to build the metadata of a token with its name and optional description
+pub fn token_metadata(name: String, description: Option<String>) -> Icrc7TokenMetadata {
+    let mut metadata = Icrc7TokenMetadata::new();
+    metadata.insert("Name".to_string(), Value::Text(name));
+    if let Some(description) = description {
+        metadata.insert("Description".to_string(), Value::Text(description));
+    }
+    metadata
+}
+
 // function to min an icrc7 token for a specific user (that will be the owner)
 // the function call the mint method on a collection
 pub async fn mint_icrc7_for_user(
     owner: Principal,
     icrc7_canister_id: Principal,
-    icrc7_name: Option<String>,
-    icrc7_description: Option<String>,
-    icrc7_logo: Option<String>,
+    metadata: Icrc7TokenMetadata,
 ) -> MintResult {
     let account = Account {
         owner,
@@ -83,20 +91,20 @@ pub async fn mint_icrc7_for_user(
             MintArg {
                 from_subaccount: None,
                 to: account,
-                token_id: get_current_token_id(),
+                // the collection assigns the next free id
+                token_id: None,
                 memo: None,
-                token_name: icrc7_name,
-                token_description: icrc7_description,
-                token_logo: icrc7_logo,
+                metadata,
+                // participation tokens are not tradable
+                transferable: Some(false),
             },
-            owner,
+            // the backend mints as itself, with the Minter role granted by the factory
+            None::<Principal>,
         ),
     )
     .await
     .unwrap();
 
-    increase_token_id();
-
     result
 }
 
@@ -1,8 +1,7 @@
-use self::query_methods::get_icrc7_logo;
 use crate::{
     common::{
         guards::not_anonymous_caller,
-        types::{Icrc7TokenMetadata, MetadataValue, RequestResult},
+        types::{Icrc7TokenMetadata, RequestResult, Value, EVENT_ID_METADATA_KEY},
         utils::{slice_to_principal, string_to_principal},
     },
     memory::get_event_by_id,
@@ -83,24 +82,24 @@ pub async fn get_user_tokens_collection() -> RequestResult<HashMap<u128, String>
 pub async fn get_token_metadata(
     token_id: u128,
     collection_id: String,
-) -> RequestResult<Vec<MetadataValue>> {
+) -> RequestResult<Vec<Value>> {
     let collection_id = string_to_principal(collection_id);
     let (token_metadatas,): (Vec<Option<Icrc7TokenMetadata>>,) =
         match call(collection_id, "icrc7_token_metadata", (&[token_id],)).await {
             Ok(meta) => meta,
             _ => (vec![],),
         };
-    let mut resulting_metadata: Vec<MetadataValue> = vec![];
+    let mut resulting_metadata: Vec<Value> = vec![];
     for metadata in token_metadatas.iter().flatten() {
         for (k, v) in metadata {
-            if k == &("logo".to_string()) {
-                let logo_id = match get_icrc7_logo(collection_id).await.body {
-                    Some(path) => path,
+            if k == EVENT_ID_METADATA_KEY {
+                let event_id = match v {
+                    Value::Text(event_id) => event_id.clone(),
                     _ => String::new(),
                 };
-                let event = get_event_by_id(logo_id);
+                let event = get_event_by_id(event_id);
                 if event.code == 200 {
-                    resulting_metadata.push(event.body.metadata)
+                    resulting_metadata.push(event.body.metadata.into())
                 }
             } else {
                 resulting_metadata.push(v.clone())
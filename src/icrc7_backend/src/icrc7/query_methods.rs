@@ -1,7 +1,55 @@
-use candid::Principal;
+use candid::{utils::ArgumentEncoder, CandidType, Deserialize, Principal};
 use ic_cdk::call;
 
-use crate::common::types::{Account, Icrc7TokenMetadata, RequestResult};
+use crate::common::types::{Account, Icrc7TokenMetadata, RequestResult, Value};
+
+// forwards a query to the collection, a failed call comes back with code 499 and an empty body
+async fn query_collection<A, T>(
+    icrc7_collection_id: Principal,
+    method: &str,
+    args: A,
+) -> RequestResult<T>
+where
+    A: ArgumentEncoder,
+    T: CandidType + for<'de> Deserialize<'de> + Default,
+{
+    match call::<A, (T,)>(icrc7_collection_id, method, args).await {
+        Ok((body,)) => RequestResult::new(
+            200,
+            format!(
+                "Correctly retrieved information for the collection with ID = {}",
+                icrc7_collection_id
+            ),
+            body,
+        ),
+        Err((code, message)) => RequestResult::new(
+            499,
+            format!(
+                "Error calling {} on the collection with ID = {} : {:?} {}",
+                method, icrc7_collection_id, code, message
+            ),
+            T::default(),
+        ),
+    }
+}
+
+/// get_icrc7_collection_metadata
+/// get all the properties of an icrc7 collection with a single call
+///
+/// ### arguments
+/// * `icrc7_collection_id` principal of the collection
+///
+/// ### return
+/// Return a custom type containing
+/// * `code` numerical code with the result code
+/// * `message` a message describing what happened
+/// * `body` properties of the collection under their `icrc7:*` keys
+#[ic_cdk::query(composite = true)]
+pub async fn get_icrc7_collection_metadata(
+    icrc7_collection_id: Principal,
+) -> RequestResult<Vec<(String, Value)>> {
+    query_collection(icrc7_collection_id, "icrc7_collection_metadata", ()).await
+}
 
 /// get_icrc7_symbol
 /// get the symbol of an icrc7 collection
@@ -16,18 +64,7 @@ use crate::common::types::{Account, Icrc7TokenMetadata, RequestResult};
 /// * `body` symbol of the collection
 #[ic_cdk::query(composite = true)]
 pub async fn get_icrc7_symbol(icrc7_collection_id: Principal) -> RequestResult<String> {
-    let (symbol,): (String,) = match call(icrc7_collection_id, "icrc7_symbol", ()).await {
-        Ok(value) => value,
-        _ => (String::new(),),
-    };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        symbol,
-    )
+    query_collection(icrc7_collection_id, "icrc7_symbol", ()).await
 }
 
 /// get_icrc7_name
@@ -43,18 +80,7 @@ pub async fn get_icrc7_symbol(icrc7_collection_id: Principal) -> RequestResult<S
 /// * `body` name of the collection
 #[ic_cdk::query(composite = true)]
 pub async fn get_icrc7_name(icrc7_collection_id: Principal) -> RequestResult<String> {
-    let (name,): (String,) = match call(icrc7_collection_id, "icrc7_name", ()).await {
-        Ok(value) => value,
-        _ => (String::new(),),
-    };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        name,
-    )
+    query_collection(icrc7_collection_id, "icrc7_name", ()).await
 }
 
 /// get_icrc7_description
@@ -72,19 +98,7 @@ pub async fn get_icrc7_name(icrc7_collection_id: Principal) -> RequestResult<Str
 pub async fn get_icrc7_description(
     icrc7_collection_id: Principal,
 ) -> RequestResult<Option<String>> {
-    let (description,): (Option<String>,) =
-        match call(icrc7_collection_id, "icrc7_description", ()).await {
-            Ok(value) => value,
-            _ => (Option::None,),
-        };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        description,
-    )
+    query_collection(icrc7_collection_id, "icrc7_description", ()).await
 }
 
 /// get_icrc7_logo
@@ -100,18 +114,7 @@ pub async fn get_icrc7_description(
 /// * `body` logo of the collection
 #[ic_cdk::query(composite = true)]
 pub async fn get_icrc7_logo(icrc7_collection_id: Principal) -> RequestResult<Option<String>> {
-    let (logo,): (Option<String>,) = match call(icrc7_collection_id, "icrc7_logo", ()).await {
-        Ok(value) => value,
-        _ => (Option::None,),
-    };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        logo,
-    )
+    query_collection(icrc7_collection_id, "icrc7_logo", ()).await
 }
 
 /// get_icrc7_total_supply
@@ -127,18 +130,7 @@ pub async fn get_icrc7_logo(icrc7_collection_id: Principal) -> RequestResult<Opt
 /// * `body` total_supply of the collection
 #[ic_cdk::query(composite = true)]
 pub async fn get_icrc7_total_supply(icrc7_collection_id: Principal) -> RequestResult<u128> {
-    let (total_supply,): (u128,) = match call(icrc7_collection_id, "icrc7_total_supply", ()).await {
-        Ok(value) => value,
-        _ => (0,),
-    };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        total_supply,
-    )
+    query_collection(icrc7_collection_id, "icrc7_total_supply", ()).await
 }
 
 /// get_icrc7_supply_cao
@@ -154,19 +146,7 @@ pub async fn get_icrc7_total_supply(icrc7_collection_id: Principal) -> RequestRe
 /// * `body` supply_cap of the collection
 #[ic_cdk::query(composite = true)]
 pub async fn get_icrc7_supply_cap(icrc7_collection_id: Principal) -> RequestResult<Option<u128>> {
-    let (supply_cap,): (Option<u128>,) =
-        match call(icrc7_collection_id, "crc7_supply_cap", ()).await {
-            Ok(value) => value,
-            _ => (Option::None,),
-        };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        supply_cap,
-    )
+    query_collection(icrc7_collection_id, "icrc7_supply_cap", ()).await
 }
 
 /// get_icrc7_max_query_batch_size
@@ -184,19 +164,7 @@ pub async fn get_icrc7_supply_cap(icrc7_collection_id: Principal) -> RequestResu
 pub async fn get_icrc7_max_query_batch_size(
     icrc7_collection_id: Principal,
 ) -> RequestResult<Option<u128>> {
-    let (max_query,): (Option<u128>,) =
-        match call(icrc7_collection_id, "icrc7_max_query_batch_size", ()).await {
-            Ok(value) => value,
-            _ => (Option::None,),
-        };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        max_query,
-    )
+    query_collection(icrc7_collection_id, "icrc7_max_query_batch_size", ()).await
 }
 
 /// get_icrc7_max_update_batch_size
@@ -214,19 +182,7 @@ pub async fn get_icrc7_max_query_batch_size(
 pub async fn get_icrc7_max_update_batch_size(
     icrc7_collection_id: Principal,
 ) -> RequestResult<Option<u128>> {
-    let (max_update,): (Option<u128>,) =
-        match call(icrc7_collection_id, "icrc7_max_update_batch_size", ()).await {
-            Ok(value) => value,
-            _ => (Option::None,),
-        };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        max_update,
-    )
+    query_collection(icrc7_collection_id, "icrc7_max_update_batch_size", ()).await
 }
 
 /// get_icrc7_max_take_value
@@ -244,19 +200,7 @@ pub async fn get_icrc7_max_update_batch_size(
 pub async fn get_icrc7_max_take_value(
     icrc7_collection_id: Principal,
 ) -> RequestResult<Option<u128>> {
-    let (max_take_value,): (Option<u128>,) =
-        match call(icrc7_collection_id, "icrc7_max_take_value", ()).await {
-            Ok(value) => value,
-            _ => (Option::None,),
-        };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        max_take_value,
-    )
+    query_collection(icrc7_collection_id, "icrc7_max_take_value", ()).await
 }
 
 /// get_icrc7_max_memo_size
@@ -274,19 +218,7 @@ pub async fn get_icrc7_max_take_value(
 pub async fn get_icrc7_max_memo_size(
     icrc7_collection_id: Principal,
 ) -> RequestResult<Option<u128>> {
-    let (max_memo,): (Option<u128>,) =
-        match call(icrc7_collection_id, "icrc7_max_memo_size", ()).await {
-            Ok(value) => value,
-            _ => (Option::None,),
-        };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        max_memo,
-    )
+    query_collection(icrc7_collection_id, "icrc7_max_memo_size", ()).await
 }
 
 /// get_icrc7_atomic_batch_transfers
@@ -304,19 +236,7 @@ pub async fn get_icrc7_max_memo_size(
 pub async fn icrc7_atomic_batch_transfers(
     icrc7_collection_id: Principal,
 ) -> RequestResult<Option<bool>> {
-    let (atomic_transfer,): (Option<bool>,) =
-        match call(icrc7_collection_id, "icrc7_atomic_batch_transfers", ()).await {
-            Ok(value) => value,
-            _ => (Option::None,),
-        };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        atomic_transfer,
-    )
+    query_collection(icrc7_collection_id, "icrc7_atomic_batch_transfers", ()).await
 }
 
 /// icrc7_owner_of
@@ -336,19 +256,7 @@ pub async fn icrc7_owner_of(
     ids: Vec<u128>,
     icrc7_collection_id: Principal,
 ) -> RequestResult<Vec<Option<Account>>> {
-    let (owners,): (Vec<Option<Account>>,) =
-        match call(icrc7_collection_id, "icrc7_atomic_batch_transfers", (ids,)).await {
-            Ok(value) => value,
-            _ => (vec![],),
-        };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        owners,
-    )
+    query_collection(icrc7_collection_id, "icrc7_owner_of", (ids,)).await
 }
 
 /// icrc7_tokens
@@ -370,24 +278,7 @@ pub async fn icrc7_tokens(
     take: Option<u128>,
     icrc7_collection_id: Principal,
 ) -> RequestResult<Vec<u128>> {
-    let (tokens,): (Vec<u128>,) = match call(
-        icrc7_collection_id,
-        "icrc7_atomic_batch_transfers",
-        (prev, take),
-    )
-    .await
-    {
-        Ok(value) => value,
-        _ => (vec![],),
-    };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        tokens,
-    )
+    query_collection(icrc7_collection_id, "icrc7_tokens", (prev, take)).await
 }
 
 /// get_icrc7_token_metadata
@@ -407,19 +298,7 @@ pub async fn get_icrc7_token_metadata(
     token_ids: Vec<u128>,
     icrc7_collection_id: Principal,
 ) -> RequestResult<Vec<Option<Icrc7TokenMetadata>>> {
-    let (metadata,): (Vec<Option<Icrc7TokenMetadata>>,) =
-        match call(icrc7_collection_id, "icrc7_token_metadata", (token_ids,)).await {
-            Ok(value) => value,
-            _ => (vec![],),
-        };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        metadata,
-    )
+    query_collection(icrc7_collection_id, "icrc7_token_metadata", (token_ids,)).await
 }
 
 /// get_icrc7_balance_of
@@ -439,19 +318,7 @@ pub async fn icrc7_balance_of(
     accounts: Vec<Account>,
     icrc7_collection_id: Principal,
 ) -> RequestResult<Vec<u128>> {
-    let (balance,): (Vec<u128>,) =
-        match call(icrc7_collection_id, "icrc7_balance_of", (accounts,)).await {
-            Ok(value) => value,
-            _ => (vec![],),
-        };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        balance,
-    )
+    query_collection(icrc7_collection_id, "icrc7_balance_of", (accounts,)).await
 }
 
 /// get_icrc7_tokens_of
@@ -475,22 +342,10 @@ pub async fn icrc7_tokens_of(
     take: Option<u128>,
     icrc7_collection_id: Principal,
 ) -> RequestResult<Vec<u128>> {
-    let (tokens,): (Vec<u128>,) = match call(
+    query_collection(
         icrc7_collection_id,
         "icrc7_tokens_of",
         (account, prev, take),
     )
     .await
-    {
-        Ok(value) => value,
-        _ => (vec![],),
-    };
-    RequestResult::new(
-        200,
-        format!(
-            "Correctly retrieved information for the collection with ID = {}",
-            icrc7_collection_id
-        ),
-        tokens,
-    )
 }
@@ -1,5 +1,5 @@
 use candid::Principal;
-use ic_cdk::call;
+use ic_cdk::{api::call::CallResult, call};
 
 use crate::common::guards::not_anonymous_caller;
 use crate::common::types::{RequestResult, TransferArg, TransferResult};
@@ -10,7 +10,6 @@ use crate::common::types::{RequestResult, TransferArg, TransferResult};
 /// ### arguments
 /// * `icrc7_collection_id` principal of the collection containing the token
 /// * `args` vector containing all the arguments to permit transfer
-/// * `caller` owner of the collection
 ///
 /// ## return
 /// Return a custom type containing
@@ -21,16 +20,25 @@ use crate::common::types::{RequestResult, TransferArg, TransferResult};
 pub async fn icrc7_transfer(
     icrc7_collection_id: Principal,
     args: Vec<TransferArg>,
-    caller: Principal,
 ) -> RequestResult<Vec<Option<TransferResult>>> {
-    let (transfer_results,): (Vec<Option<TransferResult>>,) =
-        match call(icrc7_collection_id, "icrc7_transfer", (args, caller)).await {
-            Ok(value) => value,
-            _ => (vec![],),
-        };
-    RequestResult::new(
-        200,
-        format!("Correctly transferred from {}", caller,),
-        transfer_results,
-    )
+    let caller = ic_cdk::caller();
+    // the factory makes the backend a Transfer operator of the collections it creates, it
+    // only moves the tokens of the user calling it
+    let result: CallResult<(Vec<Option<TransferResult>>,)> =
+        call(icrc7_collection_id, "icrc7_transfer", (args, Some(caller))).await;
+    match result {
+        Ok((transfer_results,)) => RequestResult::new(
+            200,
+            format!("Correctly transferred from {}", caller,),
+            transfer_results,
+        ),
+        Err((code, message)) => RequestResult::new(
+            499,
+            format!(
+                "Error calling icrc7_transfer on the collection with ID = {} : {:?} {}",
+                icrc7_collection_id, code, message
+            ),
+            vec![],
+        ),
+    }
 }
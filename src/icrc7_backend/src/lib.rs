@@ -10,7 +10,7 @@ pub mod groups;
 pub mod icrc7;
 pub mod memory;
 
-use common::types::{Group, Icrc7TokenMetadata, Member};
+use common::types::{Group, Icrc7TokenMetadata, Member, Value};
 
 #[ic_cdk::query]
 pub fn whoami() -> Principal {
@@ -1,6 +1,5 @@
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::BTreeMap as StableBTree;
-use ic_stable_structures::Cell as StableCell;
 use ic_stable_structures::DefaultMemoryImpl;
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -21,9 +20,7 @@ thread_local! {
         StableBTree::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))))
     });
 
-    static TOKEN_COUNTER: RefCell<StableCell<u128, Memory>> = RefCell::new({
-        StableCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2))), 0).unwrap()
-    })
+    // MemoryId 2 held the former token counter, token ids are now assigned by the collections
 }
 
 pub fn get_collections() -> HashMap<String, Group> {
@@ -63,12 +60,3 @@ pub fn get_event_by_id(event_id: String) -> RequestResult<Event> {
         _ => RequestResult::new(404, format!("Not found event {}", event_id), Event::empty()),
     }
 }
-
-pub fn get_current_token_id() -> u128 {
-    TOKEN_COUNTER.with(|token| *token.borrow().get())
-}
-
-pub fn increase_token_id() {
-    let old_token_id = get_current_token_id();
-    let _ = TOKEN_COUNTER.with(|token| token.borrow_mut().set(old_token_id + 1));
-}
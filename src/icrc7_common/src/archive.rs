@@ -0,0 +1,4 @@
+// State of the archive canister. It lives next to the types it stores, so that the tests of
+// the collection can run an archive alongside it.
+pub mod memory;
+pub mod state;
@@ -0,0 +1,25 @@
+use crate::{
+    archive::state::MEMORY_MANAGER,
+    transaction::{TokenTxnKey, Transaction},
+};
+use ic_stable_structures::{
+    memory_manager::{MemoryId, VirtualMemory},
+    DefaultMemoryImpl, StableBTreeMap,
+};
+
+// A memory for upgrades, where data from the heap can be serialized/deserialized.
+const UPGRADES: MemoryId = MemoryId::new(0);
+
+pub type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+pub fn get_upgrades_memory() -> Memory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(UPGRADES))
+}
+
+pub fn get_log_memory() -> StableBTreeMap<u128, Transaction, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))))
+}
+
+pub fn get_token_index_memory() -> StableBTreeMap<TokenTxnKey, (), Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2))))
+}
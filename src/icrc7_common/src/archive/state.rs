@@ -0,0 +1,156 @@
+use std::cell::RefCell;
+
+use crate::{
+    archive::memory::{get_log_memory, get_token_index_memory, Memory},
+    errors::{InsertTransactionError, SyncReceipt},
+    icrc3_types::{BlockWithId, GetBlocksArgs, GetBlocksResult},
+    transaction::{TokenTxnKey, Transaction},
+};
+use candid::{Nat, Principal};
+use ic_stable_structures::{memory_manager::MemoryManager, DefaultMemoryImpl, StableBTreeMap};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct State {
+    pub parent_canister: Option<Principal>,
+    #[serde(skip, default = "get_log_memory")]
+    pub txn_log: StableBTreeMap<u128, Transaction, Memory>,
+    #[serde(skip, default = "get_token_index_memory")]
+    pub token_txn_index: StableBTreeMap<TokenTxnKey, (), Memory>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            parent_canister: None,
+            txn_log: get_log_memory(),
+            token_txn_index: get_token_index_memory(),
+        }
+    }
+}
+
+impl State {
+    pub const DEFAULT_TAKE_VALUE: u128 = 32;
+    pub const DEFAULT_MAX_TAKE_VALUE: u128 = 100;
+
+    pub fn get_parent_canister(&self) -> Option<Principal> {
+        self.parent_canister
+    }
+
+    // only the collection owning the archive can append to it
+    pub fn parent_check(&self, caller: &Principal) -> Result<(), String> {
+        match self.parent_canister {
+            None => Err(String::from("The archive has no parent collection")),
+            Some(parent) if parent == *caller => Ok(()),
+            Some(_) => Err(String::from("The caller is not the parent collection")),
+        }
+    }
+
+    pub fn get_txn_log_count(&self) -> u64 {
+        self.txn_log.len()
+    }
+
+    // Appends a batch sent by the collection. Transactions already archived are skipped, so
+    // the collection can safely resend a batch whose previous sync failed on its side.
+    pub fn insert_many_txn_log(&mut self, txn_logs: Vec<Transaction>) -> SyncReceipt {
+        let mut last_txn_id = self.txn_log.last_key_value().map(|(txn_id, _)| txn_id);
+        let mut new_txn_logs = vec![];
+        for txn in txn_logs.iter() {
+            if txn.txn_id == 0 {
+                return Err(InsertTransactionError::InvalidId);
+            }
+            if self.txn_log.contains_key(&txn.txn_id) {
+                continue;
+            }
+            if last_txn_id.is_some_and(|last_txn_id| txn.txn_id <= last_txn_id) {
+                return Err(InsertTransactionError::InvalidId);
+            }
+            last_txn_id = Some(txn.txn_id);
+            new_txn_logs.push(txn);
+        }
+        for txn in new_txn_logs {
+            if let Some(token_id) = txn.txn_type.token_id() {
+                self.token_txn_index.insert(
+                    TokenTxnKey {
+                        token_id,
+                        txn_id: txn.txn_id,
+                    },
+                    (),
+                );
+            }
+            self.txn_log.insert(txn.txn_id, txn.clone());
+        }
+        Ok(txn_logs.len() as u32)
+    }
+
+    // transactions with an id in [start, start + length), at most DEFAULT_MAX_TAKE_VALUE of them
+    pub fn get_txn_logs(&self, start: u128, length: u128) -> Vec<Transaction> {
+        let length = length.min(State::DEFAULT_MAX_TAKE_VALUE);
+        self.txn_log
+            .range(start..start.saturating_add(length))
+            .map(|(_, txn)| txn)
+            .collect()
+    }
+
+    // history of a token in ascending txn id order, `prev` is the last txn id of the previous page
+    pub fn get_token_txn_logs(
+        &self,
+        token_id: u128,
+        prev: Option<u128>,
+        take: Option<u128>,
+    ) -> Vec<Transaction> {
+        let take = take.unwrap_or(State::DEFAULT_TAKE_VALUE);
+        if take > State::DEFAULT_MAX_TAKE_VALUE {
+            ic_cdk::trap("Exceeds Max Take Value")
+        }
+        let start = match prev {
+            None => 0,
+            Some(prev) => match prev.checked_add(1) {
+                Some(start) => start,
+                None => return vec![],
+            },
+        };
+        self.token_txn_index
+            .range(
+                TokenTxnKey {
+                    token_id,
+                    txn_id: start,
+                }..=TokenTxnKey {
+                    token_id,
+                    txn_id: u128::MAX,
+                },
+            )
+            .take(take as usize)
+            .filter_map(|(key, _)| self.txn_log.get(&key.txn_id))
+            .collect()
+    }
+
+    // block ids are 0-based while txn ids start from 1, so block `n` is txn `n + 1`
+    pub fn icrc3_get_blocks(&self, args: GetBlocksArgs) -> GetBlocksResult {
+        let mut blocks = vec![];
+        for arg in args {
+            let start = u128::try_from(&arg.start.0).unwrap_or(u128::MAX);
+            let length = u128::try_from(&arg.length.0).unwrap_or(u128::MAX);
+            let end = start.saturating_add(length);
+            for (txn_id, txn) in self.txn_log.range(start.saturating_add(1)..=end) {
+                if blocks.len() as u128 >= State::DEFAULT_MAX_TAKE_VALUE {
+                    break;
+                }
+                blocks.push(BlockWithId {
+                    id: Nat::from(txn_id - 1),
+                    block: txn.block(),
+                });
+            }
+        }
+        GetBlocksResult {
+            log_length: Nat::from(self.txn_log.len()),
+            blocks,
+            archived_blocks: vec![],
+        }
+    }
+}
+
+thread_local! {
+    pub static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+    pub static STATE: RefCell<State> = RefCell::default();
+}
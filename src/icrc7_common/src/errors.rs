@@ -0,0 +1,15 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+// outcome of a batch of transactions sent by a collection to its archive
+#[derive(CandidType, Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum InsertTransactionError {
+    SyncPending,
+    NotSetArchiveCanister,
+    RemoteError,
+    Unexpected(String),
+    CantWrite,
+    InvalidId,
+}
+
+pub type SyncReceipt = Result<u32, InsertTransactionError>;
@@ -0,0 +1,108 @@
+use candid::{define_function, CandidType, Int, Nat};
+use icrc_ledger_types::icrc1::account::Account;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+// ICRC-3 generic value, used both for the blocks of the log and for metadata
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Blob(ByteBuf),
+    Text(String),
+    Nat(Nat),
+    Int(Int),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn text(text: impl ToString) -> Self {
+        Value::Text(text.to_string())
+    }
+
+    pub fn nat(n: impl Into<Nat>) -> Self {
+        Value::Nat(n.into())
+    }
+
+    pub fn blob(bytes: impl Into<Vec<u8>>) -> Self {
+        Value::Blob(ByteBuf::from(bytes.into()))
+    }
+
+    // accounts are encoded as an array of one or two blobs: the owner and, if set, the subaccount
+    pub fn account(account: &Account) -> Self {
+        let mut parts = vec![Value::blob(account.owner.as_slice())];
+        if let Some(subaccount) = account.subaccount {
+            parts.push(Value::blob(subaccount));
+        }
+        Value::Array(parts)
+    }
+
+    /// Computes the representation-independent hash of the value, as described in ICRC-3.
+    pub fn hash(&self) -> Hash {
+        match self {
+            Value::Blob(bytes) => Sha256::digest(bytes).into(),
+            Value::Text(text) => Sha256::digest(text.as_bytes()).into(),
+            Value::Nat(nat) => {
+                let mut buf = vec![];
+                nat.encode(&mut buf).expect("cannot encode a Nat");
+                Sha256::digest(&buf).into()
+            }
+            Value::Int(int) => {
+                let mut buf = vec![];
+                int.encode(&mut buf).expect("cannot encode an Int");
+                Sha256::digest(&buf).into()
+            }
+            Value::Array(values) => {
+                let mut hasher = Sha256::new();
+                for value in values {
+                    hasher.update(value.hash());
+                }
+                hasher.finalize().into()
+            }
+            Value::Map(entries) => {
+                let mut pairs: Vec<(Hash, Hash)> = entries
+                    .iter()
+                    .map(|(key, value)| (Sha256::digest(key.as_bytes()).into(), value.hash()))
+                    .collect();
+                pairs.sort();
+                let mut hasher = Sha256::new();
+                for (key_hash, value_hash) in pairs {
+                    hasher.update(key_hash);
+                    hasher.update(value_hash);
+                }
+                hasher.finalize().into()
+            }
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetBlocksRequest {
+    pub start: Nat,
+    pub length: Nat,
+}
+
+pub type GetBlocksArgs = Vec<GetBlocksRequest>;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BlockWithId {
+    pub id: Nat,
+    pub block: Value,
+}
+
+define_function!(pub GetBlocksFn : (GetBlocksArgs) -> (GetBlocksResult) query);
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ArchivedBlocks {
+    pub args: GetBlocksArgs,
+    pub callback: GetBlocksFn,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetBlocksResult {
+    pub log_length: Nat,
+    pub blocks: Vec<BlockWithId>,
+    pub archived_blocks: Vec<ArchivedBlocks>,
+}
@@ -0,0 +1,7 @@
+// Types shared by the icrc7 collection and its archive, which exchange transactions as Candid
+// and store them with the same layout.
+pub mod archive;
+pub mod errors;
+pub mod icrc3_types;
+pub mod schema;
+pub mod transaction;
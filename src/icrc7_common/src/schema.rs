@@ -0,0 +1,48 @@
+use candid::{decode_one, encode_one, CandidType};
+use serde::de::DeserializeOwned;
+
+// Stored values are wrapped in an envelope: these magic bytes, the layout version of the
+// value and then its Candid encoding. Values written before the envelope existed are plain
+// Candid, which starts with "DIDL", and are read as version 0.
+const ENVELOPE_MAGIC: &[u8; 3] = b"SSV";
+
+pub trait Versioned: CandidType + DeserializeOwned {
+    // layout version written by encode_versioned
+    const VERSION: u8;
+
+    // decodes a value stored with an older layout, the default covers the layouts that only
+    // differ by optional fields
+    fn from_version(version: u8, payload: &[u8]) -> Option<Self> {
+        if version < Self::VERSION {
+            decode_one(payload).ok()
+        } else {
+            None
+        }
+    }
+}
+
+pub fn encode_versioned<T: Versioned>(value: &T) -> Vec<u8> {
+    let mut bytes = ENVELOPE_MAGIC.to_vec();
+    bytes.push(T::VERSION);
+    bytes.extend(encode_one(value).expect("failed to encode stored value"));
+    bytes
+}
+
+pub fn decode_versioned<T: Versioned>(bytes: &[u8]) -> T {
+    let (version, payload) = match bytes.strip_prefix(ENVELOPE_MAGIC) {
+        Some([version, payload @ ..]) => (*version, payload),
+        _ => (0, bytes),
+    };
+    let value = if version == T::VERSION {
+        decode_one(payload).ok()
+    } else {
+        T::from_version(version, payload)
+    };
+    value.unwrap_or_else(|| {
+        panic!(
+            "failed to decode {} stored with layout version {}",
+            std::any::type_name::<T>(),
+            version
+        )
+    })
+}
@@ -0,0 +1,312 @@
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_stable_structures::{storable::Bound, Storable};
+use icrc_ledger_types::icrc1::account::Account;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    icrc3_types::Value,
+    schema::{decode_versioned, encode_versioned, Versioned},
+};
+
+// Transactions of a collection, stored by the collection and by its archive and exchanged
+// between them as Candid.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub enum TransactionType {
+    Mint {
+        tid: u128,
+        from: Account,
+        to: Account,
+    },
+    Burn {
+        tid: u128,
+        from: Account,
+        to: Account,
+    },
+    Transfer {
+        tid: u128,
+        from: Account,
+        to: Account,
+    },
+    Approval {
+        tid: u128,
+        from: Account,
+        to: Account,
+    },
+    CollectionApproval {
+        from: Account,
+        to: Account,
+    },
+    Revoke {
+        tid: u128,
+        from: Account,
+        to: Option<Account>,
+    },
+    CollectionRevoke {
+        from: Account,
+        to: Option<Account>,
+    },
+    TransferFrom {
+        tid: u128,
+        from: Account,
+        to: Account,
+        spender: Account,
+    },
+    // a metadata key of a token set to `value`, or removed when None
+    Update {
+        tid: u128,
+        from: Account,
+        key: String,
+        value: Option<Value>,
+    },
+    // collection settings changed by the owner, under their icrc7_collection_metadata keys
+    UpdateCollection {
+        from: Account,
+        changes: Vec<(String, Value)>,
+    },
+    // an approval dropped by the canister once past its expiry, `from` is the token owner
+    ApprovalExpired {
+        tid: u128,
+        from: Account,
+        to: Account,
+    },
+    // a collection approval dropped by the canister once past its expiry
+    CollectionApprovalExpired {
+        from: Account,
+        to: Account,
+    },
+}
+
+impl TransactionType {
+    // ICRC-3 block type of the transaction, as defined by ICRC-7 and ICRC-37
+    pub fn btype(&self) -> &'static str {
+        match self {
+            TransactionType::Mint { .. } => "7mint",
+            TransactionType::Burn { .. } => "7burn",
+            TransactionType::Transfer { .. } => "7xfer",
+            TransactionType::Approval { .. } => "37approve",
+            TransactionType::CollectionApproval { .. } => "37approve_coll",
+            TransactionType::Revoke { .. } => "37revoke",
+            TransactionType::CollectionRevoke { .. } => "37revoke_coll",
+            TransactionType::TransferFrom { .. } => "37xfer",
+            TransactionType::Update { .. } => "7update_token",
+            TransactionType::UpdateCollection { .. } => "7update_collection",
+            TransactionType::ApprovalExpired { .. } => "37approve_expired",
+            TransactionType::CollectionApprovalExpired { .. } => "37approve_coll_expired",
+        }
+    }
+
+    // token the transaction is about, None for collection level approvals
+    pub fn token_id(&self) -> Option<u128> {
+        match self {
+            TransactionType::Mint { tid, .. }
+            | TransactionType::Burn { tid, .. }
+            | TransactionType::Transfer { tid, .. }
+            | TransactionType::Approval { tid, .. }
+            | TransactionType::Revoke { tid, .. }
+            | TransactionType::TransferFrom { tid, .. }
+            | TransactionType::Update { tid, .. }
+            | TransactionType::ApprovalExpired { tid, .. } => Some(*tid),
+            TransactionType::CollectionApproval { .. }
+            | TransactionType::CollectionRevoke { .. }
+            | TransactionType::UpdateCollection { .. }
+            | TransactionType::CollectionApprovalExpired { .. } => None,
+        }
+    }
+
+    fn tx_fields(&self) -> Vec<(String, Value)> {
+        match self {
+            TransactionType::Mint { tid, from, to }
+            | TransactionType::Burn { tid, from, to }
+            | TransactionType::Transfer { tid, from, to } => vec![
+                ("tid".into(), Value::nat(*tid)),
+                ("from".into(), Value::account(from)),
+                ("to".into(), Value::account(to)),
+            ],
+            TransactionType::Approval { tid, from, to }
+            | TransactionType::ApprovalExpired { tid, from, to } => vec![
+                ("tid".into(), Value::nat(*tid)),
+                ("from".into(), Value::account(from)),
+                ("spender".into(), Value::account(to)),
+            ],
+            TransactionType::CollectionApproval { from, to }
+            | TransactionType::CollectionApprovalExpired { from, to } => vec![
+                ("from".into(), Value::account(from)),
+                ("spender".into(), Value::account(to)),
+            ],
+            TransactionType::Revoke { tid, from, to } => {
+                let mut fields = vec![
+                    ("tid".into(), Value::nat(*tid)),
+                    ("from".into(), Value::account(from)),
+                ];
+                if let Some(spender) = to {
+                    fields.push(("spender".into(), Value::account(spender)));
+                }
+                fields
+            }
+            TransactionType::CollectionRevoke { from, to } => {
+                let mut fields = vec![("from".into(), Value::account(from))];
+                if let Some(spender) = to {
+                    fields.push(("spender".into(), Value::account(spender)));
+                }
+                fields
+            }
+            TransactionType::TransferFrom {
+                tid,
+                from,
+                to,
+                spender,
+            } => vec![
+                ("tid".into(), Value::nat(*tid)),
+                ("from".into(), Value::account(from)),
+                ("to".into(), Value::account(to)),
+                ("spender".into(), Value::account(spender)),
+            ],
+            TransactionType::Update {
+                tid,
+                from,
+                key,
+                value,
+            } => {
+                let mut fields = vec![
+                    ("tid".into(), Value::nat(*tid)),
+                    ("from".into(), Value::account(from)),
+                    ("key".into(), Value::text(key)),
+                ];
+                if let Some(value) = value {
+                    fields.push(("value".into(), value.clone()));
+                }
+                fields
+            }
+            TransactionType::UpdateCollection { from, changes } => vec![
+                ("from".into(), Value::account(from)),
+                ("changes".into(), Value::Map(changes.clone())),
+            ],
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct Transaction {
+    pub at: u64,
+    pub txn_id: u128,
+    pub op: String,
+    pub txn_type: TransactionType,
+    pub memo: Option<Vec<u8>>,
+    // hash of the previous block, None only for the first block of the log
+    pub phash: Option<Vec<u8>>,
+    // canister that acted for the principal of the transaction, if any
+    pub operator: Option<Principal>,
+}
+
+impl Versioned for Transaction {
+    const VERSION: u8 = 1;
+}
+
+impl Storable for Transaction {
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        decode_versioned(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Owned(encode_versioned(self))
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Transaction {
+    pub fn new(txn_id: u128, txn_type: TransactionType, at: u64, memo: Option<Vec<u8>>) -> Self {
+        let op = match &txn_type {
+            TransactionType::Transfer {
+                tid: _,
+                from: _,
+                to: _,
+            } => "transfer".into(),
+            TransactionType::Mint {
+                tid: _,
+                from: _,
+                to: _,
+            } => "mint".into(),
+            TransactionType::Burn {
+                tid: _,
+                from: _,
+                to: _,
+            } => "burn".into(),
+            TransactionType::Approval {
+                tid: _,
+                from: _,
+                to: _,
+            } => "approve".into(),
+            TransactionType::CollectionApproval { from: _, to: _ } => "approve_collection".into(),
+            TransactionType::Revoke {
+                tid: _,
+                from: _,
+                to: _,
+            } => "revoke".into(),
+            TransactionType::CollectionRevoke { from: _, to: _ } => "revoke_collection".into(),
+            TransactionType::TransferFrom {
+                tid: _,
+                from: _,
+                to: _,
+                spender: _,
+            } => "transfer_from".into(),
+            TransactionType::Update { .. } => "update".into(),
+            TransactionType::UpdateCollection { .. } => "update_collection".into(),
+            TransactionType::ApprovalExpired { .. } => "approve_expired".into(),
+            TransactionType::CollectionApprovalExpired { .. } => {
+                "approve_collection_expired".into()
+            }
+        };
+        Self {
+            op,
+            txn_id,
+            at,
+            txn_type,
+            memo,
+            phash: None,
+            operator: None,
+        }
+    }
+
+    // ICRC-3 block of the transaction, its hash is the parent hash of the next block
+    pub fn block(&self) -> Value {
+        let mut tx = self.txn_type.tx_fields();
+        if let Some(ref memo) = self.memo {
+            tx.push(("memo".into(), Value::blob(memo.clone())));
+        }
+        if let Some(operator) = self.operator {
+            tx.push(("operator".into(), Value::blob(operator.as_slice())));
+        }
+        let mut block = vec![
+            ("btype".into(), Value::text(self.txn_type.btype())),
+            ("ts".into(), Value::nat(self.at)),
+        ];
+        if let Some(ref phash) = self.phash {
+            block.push(("phash".into(), Value::blob(phash.clone())));
+        }
+        block.push(("tx".into(), Value::Map(tx)));
+        Value::Map(block)
+    }
+}
+
+// key of the per-token index, ordered by token first so that the history of a token
+// can be read with a single range scan
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub struct TokenTxnKey {
+    pub token_id: u128,
+    pub txn_id: u128,
+}
+
+impl Storable for TokenTxnKey {
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
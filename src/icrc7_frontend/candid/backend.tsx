@@ -1,5 +1,6 @@
 // @ts-ignore
 export const idlFactory = ({ IDL }) => {
+  const Value = IDL.Rec();
   const Member = IDL.Record({
     'name' : IDL.Text,
     'internet_identity' : IDL.Text,
@@ -60,8 +61,18 @@ export const idlFactory = ({ IDL }) => {
     'code' : IDL.Nat16,
     'message' : IDL.Text,
   });
+  Value.fill(
+    IDL.Variant({
+      'Int' : IDL.Int,
+      'Map' : IDL.Vec(IDL.Tuple(IDL.Text, Value)),
+      'Nat' : IDL.Nat,
+      'Blob' : IDL.Vec(IDL.Nat8),
+      'Text' : IDL.Text,
+      'Array' : IDL.Vec(Value),
+    })
+  );
   const RequestResult_7 = IDL.Record({
-    'body' : IDL.Vec(IDL.Opt(IDL.Vec(IDL.Tuple(IDL.Text, MetadataValue)))),
+    'body' : IDL.Vec(IDL.Opt(IDL.Vec(IDL.Tuple(IDL.Text, Value)))),
     'code' : IDL.Nat16,
     'message' : IDL.Text,
   });
@@ -71,7 +82,7 @@ export const idlFactory = ({ IDL }) => {
     'message' : IDL.Text,
   });
   const RequestResult_9 = IDL.Record({
-    'body' : IDL.Vec(MetadataValue),
+    'body' : IDL.Vec(Value),
     'code' : IDL.Nat16,
     'message' : IDL.Text,
   });
@@ -107,6 +118,7 @@ export const idlFactory = ({ IDL }) => {
     'Unauthorized' : IDL.Null,
     'CreatedInFuture' : IDL.Record({ 'ledger_time' : IDL.Nat64 }),
     'InvalidRecipient' : IDL.Null,
+    'NonTransferable' : IDL.Null,
     'GenericBatchError' : IDL.Record({
       'message' : IDL.Text,
       'error_code' : IDL.Nat,
@@ -218,7 +230,7 @@ export const idlFactory = ({ IDL }) => {
         ['query'],
       ),
     'icrc7_transfer' : IDL.Func(
-        [IDL.Principal, IDL.Vec(TransferArg), IDL.Principal],
+        [IDL.Principal, IDL.Vec(TransferArg)],
         [RequestResult_13],
         [],
       ),